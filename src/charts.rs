@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use chrono::NaiveDateTime;
 use iced::{
     widget::{canvas::Cache, button}, Border, Color, Point, Rectangle, Theme, Vector
@@ -10,6 +11,11 @@ pub mod heatmap;
 pub mod footprint;
 pub mod candlestick;
 pub mod timeandsales;
+pub mod indicators;
+pub mod feed_monitor;
+pub mod volume_profile;
+
+use crate::data_providers::{ChartBasis, Trade};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Message {
@@ -45,6 +51,9 @@ struct CommonChartData {
     autoscale: bool,
 
     bounds: Rectangle,
+
+    fetching_older: bool,
+    history_exhausted: bool,
 }
 impl Default for CommonChartData {
     fn default() -> Self {
@@ -72,7 +81,96 @@ impl Default for CommonChartData {
             autoscale: true,
 
             bounds: Rectangle::default(),
+
+            fetching_older: false,
+            history_exhausted: false,
+        }
+    }
+}
+impl CommonChartData {
+    /// End time of the previous page of klines, once the view is panned past the earliest loaded one.
+    /// Only one page is requested at a time, and none after the exchange runs out of history.
+    fn older_klines_request(&mut self, visible_earliest: i64, earliest_loaded: Option<i64>) -> Option<i64> {
+        if self.fetching_older || self.history_exhausted {
+            return None;
+        }
+
+        match earliest_loaded {
+            Some(earliest_loaded) if visible_earliest < earliest_loaded => {
+                self.fetching_older = true;
+
+                Some(earliest_loaded - 1)
+            },
+            _ => None,
+        }
+    }
+
+    fn older_klines_received(&mut self, count: usize) {
+        self.fetching_older = false;
+        self.history_exhausted = count == 0;
+    }
+}
+
+/// Each bar built from trades takes up a one minute slot on the x axis, as they have no fixed duration
+const TRADE_BAR_SLOT: i64 = 60 * 1000;
+
+/// Assigns trades to bars for the trade based `ChartBasis` variants,
+/// keeping the actual open time of each bar for the x axis labels
+struct TradeBarBuilder {
+    basis: ChartBasis,
+    slot: i64,
+    trades: u32,
+    notional: f32,
+    open: f32,
+    high: f32,
+    low: f32,
+    open_times: BTreeMap<i64, i64>,
+}
+impl TradeBarBuilder {
+    fn new(basis: ChartBasis) -> Option<Self> {
+        basis.timeframe().is_none().then(|| TradeBarBuilder {
+            basis,
+            slot: 0,
+            trades: 0,
+            notional: 0.0,
+            open: 0.0,
+            high: 0.0,
+            low: 0.0,
+            open_times: BTreeMap::new(),
+        })
+    }
+
+    /// Slot of the bar the trade goes into, opening a new bar once the current one is complete
+    fn slot_for(&mut self, trade: &Trade) -> i64 {
+        let is_complete = self.slot == 0 || match self.basis {
+            ChartBasis::Tick(count) => self.trades >= count,
+            ChartBasis::Volume(notional) => self.notional >= notional as f32,
+            ChartBasis::Range(bps) => {
+                let high = self.high.max(trade.price.to_f32());
+                let low = self.low.min(trade.price.to_f32());
+
+                (high - low) / self.open * 10_000.0 > bps as f32
+            },
+            ChartBasis::Time(_) => false,
+        };
+
+        if is_complete {
+            self.slot += TRADE_BAR_SLOT;
+            self.trades = 0;
+            self.notional = 0.0;
+            self.open = trade.price.to_f32();
+            self.high = trade.price.to_f32();
+            self.low = trade.price.to_f32();
+
+            self.open_times.insert(self.slot, trade.time);
         }
+
+        self.trades += 1;
+        self.notional += trade.price.to_f32() * trade.qty;
+        self.high = self.high.max(trade.price.to_f32());
+        self.low = self.low.min(trade.price.to_f32());
+
+        self.slot
     }
 }
 
@@ -134,6 +232,23 @@ const PRICE_STEPS: [f32; 15] = [
     0.05,
     0.01,
 ];
+/// Ring marking a liquidation, orange for liquidated longs and yellow for shorts.
+/// Its area grows with the size, relative to the largest liquidation in view
+fn draw_liquidation_marker(frame: &mut canvas::Frame, center: Point, qty: f32, max_qty: f32, is_sell: bool) {
+    let radius = 3.0 + (qty / max_qty).sqrt() * 15.0;
+
+    let color = if is_sell {
+        Color::from_rgb8(255, 120, 40)
+    } else {
+        Color::from_rgb8(240, 210, 60)
+    };
+
+    let marker = Path::circle(center, radius);
+
+    frame.fill(&marker, Color { a: 0.25, ..color });
+    frame.stroke(&marker, Stroke::default().with_color(color).with_width(1.5));
+}
+
 fn calculate_price_step(highest: f32, lowest: f32, labels_can_fit: i32) -> (f32, f32) {
     let range = highest - lowest;
     let mut step = 1000.0; 
@@ -184,8 +299,24 @@ const M5_TIME_STEPS: [i64; 9] = [
     1000 * 60 * 5, // 5 min
 ];
 
+const H1_TIME_STEPS: [i64; 7] = [
+    1000 * 60 * 10080, // 1 week
+    1000 * 60 * 4320, // 3 days
+    1000 * 60 * 1440, // 1 day
+    1000 * 60 * 720, // 12 hour
+    1000 * 60 * 360, // 6 hour
+    1000 * 60 * 180, // 3 hour
+    1000 * 60 * 60, // 1 hour
+];
+
 // time steps in ms, to be used for x-axis labels on heatmap chart
-const TIME_STEPS: [i64; 8] = [
+const TIME_STEPS: [i64; 14] = [
+    60 * 60 * 1000, // 1 hour
+    30 * 60 * 1000, // 30 minutes
+    15 * 60 * 1000, // 15 minutes
+    10 * 60 * 1000, // 10 minutes
+    5 * 60 * 1000, // 5 minutes
+    2 * 60 * 1000, // 2 minutes
     60 * 1000, // 1 minute
     30 * 1000, // 30 seconds
     15 * 1000, // 15 seconds
@@ -206,6 +337,9 @@ fn calculate_time_step(earliest: i64, latest: i64, labels_can_fit: i32, timefram
             5 => &M5_TIME_STEPS,
             15 => &M5_TIME_STEPS[..7],
             30 => &M5_TIME_STEPS[..6],
+            60 => &H1_TIME_STEPS,
+            240 => &H1_TIME_STEPS[..4],
+            1440 => &H1_TIME_STEPS[..3],
             _ => &M1_TIME_STEPS,
         };
 
@@ -247,6 +381,22 @@ pub struct AxisLabelXCanvas<'a> {
     min: i64,
     max: i64,
     timeframe: Option<u16>,
+    /// Open times of bars built from trades, which are laid out by slot instead of time
+    open_times: Option<&'a BTreeMap<i64, i64>>,
+}
+impl AxisLabelXCanvas<'_> {
+    fn time_label(&self, time: i64) -> Option<String> {
+        let (time, time_format) = match (self.open_times, self.timeframe) {
+            (Some(open_times), _) => (*open_times.get(&time)?, "%H:%M:%S"),
+            (None, Some(timeframe)) if timeframe >= 1440 => (time, "%m/%d"),
+            (None, Some(_)) => (time, "%H:%M"),
+            // a zoomed out heatmap spans hours
+            (None, None) if self.max - self.min > 10 * 60 * 1000 => (time, "%H:%M"),
+            (None, None) => (time, "%M:%S"),
+        };
+
+        Some(NaiveDateTime::from_timestamp(time / 1000, 0).format(time_format).to_string())
+    }
 }
 impl canvas::Program<Message> for AxisLabelXCanvas<'_> {
     type State = Interaction;
@@ -290,19 +440,13 @@ impl canvas::Program<Message> for AxisLabelXCanvas<'_> {
                         break;
                     }
 
-                    if x_position >= 0.0 && x_position <= bounds.width as f64 {
+                    let time_label = self.time_label(time);
+
+                    if let Some(time_label) = time_label.filter(|_| x_position >= 0.0 && x_position <= bounds.width as f64) {
                         let text_size = 12.0;
-                        let time_as_datetime = NaiveDateTime::from_timestamp(time / 1000, 0);
-                        
-                        let time_format: &str;
-                        if self.timeframe.is_some() {
-                            time_format = "%H:%M";
-                        } else {
-                            time_format = "%M:%S";
-                        }
 
                         let label = canvas::Text {
-                            content: time_as_datetime.format(time_format).to_string(),
+                            content: time_label,
                             position: Point::new(x_position as f32 - (text_size*4.0/3.0), bounds.height - 20.0),
                             size: iced::Pixels(text_size),
                             color: Color::from_rgba8(200, 200, 200, 1.0),
@@ -334,10 +478,8 @@ impl canvas::Program<Message> for AxisLabelXCanvas<'_> {
                     let crosshair_time = NaiveDateTime::from_timestamp((crosshair_millis / 1000.0) as i64, 0);
                     let crosshair_timestamp = crosshair_time.timestamp();
                     let rounded_timestamp = (crosshair_timestamp as f64 / (timeframe as f64 * 60.0)).round() as i64 * timeframe as i64 * 60;
-                    let rounded_time = NaiveDateTime::from_timestamp(rounded_timestamp, 0);
-        
                     let snap_ratio = (rounded_timestamp as f64 * 1000.0 - earliest_in_millis as f64) / (latest_in_millis as f64 - earliest_in_millis as f64);
-                    (snap_ratio, rounded_time.format("%H:%M").to_string())
+                    (snap_ratio, self.time_label(rounded_timestamp * 1000).unwrap_or_default())
                 } else {
                     let crosshair_millis = (crosshair_millis / 100.0).round() * 100.0;
                     let crosshair_time = NaiveDateTime::from_timestamp((crosshair_millis / 1000.0).floor() as i64, ((crosshair_millis % 1000.0) * 1_000_000.0).round() as u32);
@@ -349,7 +491,7 @@ impl canvas::Program<Message> for AxisLabelXCanvas<'_> {
         
                 let snap_x = snap_ratio * bounds.width as f64;
 
-                if snap_x.is_nan() {
+                if snap_x.is_nan() || text_content.is_empty() {
                     return;
                 }
         
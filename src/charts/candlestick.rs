@@ -3,15 +3,22 @@ use iced::{
     alignment, mouse, widget::{button, canvas::{self, event::{self, Event}, stroke::Stroke, Canvas, Geometry, Path}}, Color, Element, Length, Point, Rectangle, Renderer, Size, Theme
 };
 use iced::widget::{Column, Row, Container, Text};
-use crate::data_providers::Kline;
+use crate::data_providers::{ChartBasis, Kline, Liquidation, Price, Trade};
 
-use super::{Chart, CommonChartData, Message, Interaction, AxisLabelXCanvas, AxisLabelYCanvas};
-use super::{chart_button, calculate_price_step, calculate_time_step};
+use super::{Chart, CommonChartData, Message, Interaction, AxisLabelXCanvas, AxisLabelYCanvas, TradeBarBuilder};
+use super::indicators::{view_market_stats, MarketStatsHistory};
+use super::volume_profile::{session_start, ProfileMode, ProfileRange, VolumeProfile};
+use super::{chart_button, calculate_price_step, calculate_time_step, draw_liquidation_marker};
 
 pub struct CandlestickChart {
     chart: CommonChartData,
     data_points: BTreeMap<i64, Kline>,
     timeframe: u16,
+    trade_bars: Option<TradeBarBuilder>,
+    liquidations: Vec<Liquidation>,
+    /// Price levels of the volume profile, None until the ticker's tick size is known
+    tick_size: Option<Price>,
+    volume_profile: ProfileRange,
 }
 
 impl Chart for CandlestickChart {
@@ -29,7 +36,9 @@ impl CandlestickChart {
     const MIN_SCALING: f32 = 0.1;
     const MAX_SCALING: f32 = 2.0;
 
-    pub fn new(klines: Vec<Kline>, timeframe: u16) -> CandlestickChart {
+    const MAX_LIQUIDATIONS: usize = 2000;
+
+    pub fn new(klines: Vec<Kline>, basis: ChartBasis) -> CandlestickChart {
         let mut klines_raw = BTreeMap::new();
 
         for kline in klines {
@@ -39,8 +48,110 @@ impl CandlestickChart {
         CandlestickChart {
             chart: CommonChartData::default(),
             data_points: klines_raw,
-            timeframe,
+            // bars built from trades are laid out one minute apart
+            timeframe: basis.timeframe().map_or(1, |timeframe| timeframe.to_minutes()),
+            trade_bars: TradeBarBuilder::new(basis),
+            liquidations: Vec::new(),
+            tick_size: None,
+            volume_profile: ProfileRange::default(),
+        }
+    }
+
+    /// Starts over from freshly fetched klines, keeping how the volume profile is set up
+    pub fn reset_klines(&mut self, basis: ChartBasis, klines: Vec<Kline>) {
+        let mut chart = CandlestickChart::new(klines, basis);
+        chart.tick_size = self.tick_size;
+        chart.volume_profile = self.volume_profile;
+
+        *self = chart;
+    }
+
+    pub fn change_tick_size(&mut self, tick_size: Price) {
+        self.tick_size = Some(tick_size);
+
+        self.chart.main_cache.clear();
+    }
+
+    pub fn set_volume_profile(&mut self, volume_profile: ProfileRange) {
+        self.volume_profile = volume_profile;
+
+        self.chart.main_cache.clear();
+    }
+
+    /// Switches the profile's mode, a fixed range covers the bars in view
+    pub fn select_profile_mode(&mut self, mode: ProfileMode) -> ProfileRange {
+        self.set_volume_profile(
+            ProfileRange::with_mode(mode, self.chart.x_min_time, self.chart.x_max_time)
+        );
+
+        self.volume_profile
+    }
+
+    /// Each kline in the profile's range spread over the price levels it traded through,
+    /// split into buys and sells where the exchange reports taker volume
+    fn build_volume_profile(&self) -> Option<VolumeProfile> {
+        let tick_size = self.tick_size?;
+
+        let (from, to) = self.volume_profile.bars(
+            self.chart.x_min_time,
+            self.chart.x_max_time,
+            || session_start(
+                *self.data_points.keys().next_back()?,
+                self.trade_bars.as_ref().map(|bars| &bars.open_times),
+            ),
+        )?;
+
+        let mut profile = VolumeProfile::new(tick_size, self.chart.y_max_price - self.chart.y_min_price);
+
+        for (_, kline) in self.data_points.range(from..=to) {
+            profile.add_kline(kline);
+        }
+
+        Some(profile)
+    }
+
+    /// Not drawn on bars built from trades, which have no time axis to place them on
+    pub fn insert_liquidations(&mut self, liquidations: &[Liquidation]) {
+        self.liquidations.extend_from_slice(liquidations);
+
+        if self.liquidations.len() > Self::MAX_LIQUIDATIONS {
+            let drain_to = self.liquidations.len() - Self::MAX_LIQUIDATIONS;
+            self.liquidations.drain(0..drain_to);
+        }
+
+        self.render_start();
+    }
+
+    /// Builds bars out of live trades, when the chart isn't based on time
+    pub fn insert_trades(&mut self, trades_buffer: &[Trade]) {
+        let Some(trade_bars) = &mut self.trade_bars else {
+            return;
+        };
+
+        for trade in trades_buffer {
+            let slot = trade_bars.slot_for(trade);
+
+            let kline = self.data_points.entry(slot).or_insert(Kline {
+                time: slot as u64,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                volume: (0.0, 0.0),
+            });
+
+            kline.high = kline.high.max(trade.price);
+            kline.low = kline.low.min(trade.price);
+            kline.close = trade.price;
+
+            if trade.is_sell {
+                kline.volume.1 += trade.qty;
+            } else {
+                kline.volume.0 += trade.qty;
+            }
         }
+
+        self.render_start();
     }
 
     pub fn update_latest_kline(&mut self, kline: &Kline) {
@@ -74,21 +185,54 @@ impl CandlestickChart {
         chart_state.main_cache.clear();
     }
 
-    fn calculate_range(&self) -> (i64, i64, f32, f32) {
+    pub fn insert_older_klines(&mut self, klines: &[Kline]) {
+        for kline in klines {
+            self.data_points.entry(kline.time as i64).or_insert(*kline);
+        }
+
+        self.chart.older_klines_received(klines.len());
+
+        self.render_start();
+    }
+
+    /// Lets the next pan retry a page that failed to load
+    pub fn older_klines_failed(&mut self) {
+        self.chart.fetching_older = false;
+    }
+
+    /// See `CommonChartData::older_klines_request`
+    pub fn older_klines_request(&mut self) -> Option<i64> {
+        if self.trade_bars.is_some() {
+            return None;
+        }
+
+        let (_, earliest) = self.visible_timerange();
+        let earliest_loaded = self.data_points.keys().next().copied();
+
+        self.chart.older_klines_request(earliest, earliest_loaded)
+    }
+
+    fn visible_timerange(&self) -> (i64, i64) {
         let chart = self.get_common_data();
 
         let timestamp_latest = self.data_points.keys().last().map_or(0, |time| *time);
     
         let latest: i64 = timestamp_latest - ((chart.translation.x*8000.0)*(self.timeframe as f32)) as i64;
         let earliest: i64 = latest - ((6400000.0*self.timeframe as f32) / (chart.scaling / (chart.bounds.width/800.0))) as i64;
+
+        (latest, earliest)
+    }
+
+    fn calculate_range(&self) -> (i64, i64, f32, f32) {
+        let (latest, earliest) = self.visible_timerange();
     
         let visible_klines = self.data_points.range(earliest..=latest);
     
         let (highest, lowest, avg_body_height, count) = visible_klines.fold((f32::MIN, f32::MAX, 0.0f32, 0), |(highest, lowest, total_body_height, count), (_, kline)| {
-            let body_height = (kline.open - kline.close).abs();
+            let body_height = (kline.open.to_f32() - kline.close.to_f32()).abs();
             (
-                highest.max(kline.high),
-                lowest.min(kline.low),
+                highest.max(kline.high.to_f32()),
+                lowest.min(kline.low.to_f32()),
                 total_body_height + body_height,
                 count + 1,
             )
@@ -157,7 +301,7 @@ impl CandlestickChart {
         }
     }
 
-    pub fn view(&self) -> Element<Message> {
+    pub fn view<'a>(&'a self, market_stats: Option<&'a MarketStatsHistory>) -> Element<'a, Message> {
         let chart = Canvas::new(self)
             .width(Length::FillPortion(10))
             .height(Length::FillPortion(10));
@@ -172,7 +316,8 @@ impl CandlestickChart {
                 crosshair_cache: &chart_state.x_crosshair_cache, 
                 crosshair_position: chart_state.crosshair_position, 
                 crosshair: chart_state.crosshair,
-                timeframe: Some(self.timeframe)
+                timeframe: Some(self.timeframe),
+                open_times: self.trade_bars.as_ref().map(|bars| &bars.open_times),
             })
             .width(Length::FillPortion(10))
             .height(Length::Fixed(26.0));
@@ -224,13 +369,21 @@ impl CandlestickChart {
             .push(axis_labels_x)
             .push(chart_controls);
     
-        let content = Column::new()
-            .push(chart_and_y_labels)
+        let mut content = Column::new()
+            .push(chart_and_y_labels);
+
+        // stats are sampled by time, bars built from trades have no time axis to line them up with
+        if let (Some(history), None) = (market_stats, &self.trade_bars) {
+            content = content.push(
+                view_market_stats(history, chart_state.x_min_time, chart_state.x_max_time)
+            );
+        }
+
+        content
             .push(bottom_row)
             .spacing(0)
-            .padding(5);
-    
-        content.into()
+            .padding(5)
+            .into()
     }
 }
 
@@ -427,10 +580,10 @@ impl canvas::Program<Message> for CandlestickChart {
                     continue;
                 }
                 
-                let y_open = candlesticks_area_height - ((kline.open - lowest) / y_range * candlesticks_area_height);
-                let y_high = candlesticks_area_height - ((kline.high - lowest) / y_range * candlesticks_area_height);
-                let y_low = candlesticks_area_height - ((kline.low - lowest) / y_range * candlesticks_area_height);
-                let y_close = candlesticks_area_height - ((kline.close - lowest) / y_range * candlesticks_area_height);
+                let y_open = candlesticks_area_height - ((kline.open.to_f32() - lowest) / y_range * candlesticks_area_height);
+                let y_high = candlesticks_area_height - ((kline.high.to_f32() - lowest) / y_range * candlesticks_area_height);
+                let y_low = candlesticks_area_height - ((kline.low.to_f32() - lowest) / y_range * candlesticks_area_height);
+                let y_close = candlesticks_area_height - ((kline.close.to_f32() - lowest) / y_range * candlesticks_area_height);
                 
                 let color = if kline.close >= kline.open { Color::from_rgb8(81, 205, 160) } else { Color::from_rgb8(192, 80, 77) };
 
@@ -473,6 +626,42 @@ impl canvas::Program<Message> for CandlestickChart {
                     frame.fill(&bar, color);
                 }
             }
+
+            if self.trade_bars.is_none() {
+                let aggregate_time = 1000 * 60 * self.timeframe as i64;
+
+                let visible_liquidations: Vec<&Liquidation> = self.liquidations.iter()
+                    .filter(|liquidation| liquidation.time >= earliest && liquidation.time <= latest + aggregate_time)
+                    .collect();
+
+                let max_liquidation_qty = visible_liquidations.iter()
+                    .map(|liquidation| liquidation.qty)
+                    .fold(0.0, f32::max);
+
+                for liquidation in visible_liquidations {
+                    // on the candle it happened in
+                    let candle_time = (liquidation.time / aggregate_time) * aggregate_time;
+
+                    let x_position = ((candle_time - earliest) as f64 / (latest - earliest) as f64) * bounds.width as f64;
+                    let y_position = candlesticks_area_height - ((liquidation.price.to_f32() - lowest) / y_range * candlesticks_area_height);
+
+                    if x_position.is_nan() {
+                        continue;
+                    }
+
+                    draw_liquidation_marker(
+                        frame,
+                        Point::new(x_position as f32, y_position),
+                        liquidation.qty,
+                        max_liquidation_qty,
+                        liquidation.is_sell,
+                    );
+                }
+            }
+
+            if let Some(profile) = self.build_volume_profile() {
+                profile.draw(frame, bounds.width, candlesticks_area_height, lowest, highest);
+            }
         });
 
         if chart.crosshair {
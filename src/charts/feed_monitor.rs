@@ -0,0 +1,319 @@
+use std::collections::{HashMap, VecDeque};
+
+use iced::{
+    mouse, widget::canvas::{self, stroke::Stroke, Canvas, Frame, Geometry, Path}, Color, Element, Length, Point, Rectangle, Renderer, Theme
+};
+use iced::widget::{scrollable, Column, Text};
+
+use crate::data_providers::{throughput::Throughput, ConnectionState, FeedLatency, StreamType};
+use crate::screen::dashboard::pane::Message;
+use crate::style;
+
+use super::indicators::format_large_number;
+
+#[derive(Debug, Clone, Copy)]
+struct Percentiles {
+    p50: i64,
+    p90: i64,
+    p99: i64,
+}
+
+impl Percentiles {
+    fn of(buckets: &VecDeque<Vec<i64>>) -> Option<Self> {
+        let mut values: Vec<i64> = buckets.iter().flatten().copied().collect();
+
+        if values.is_empty() {
+            return None;
+        }
+
+        values.sort_unstable();
+
+        let at = |percentile: usize| values[(values.len() - 1) * percentile / 100];
+
+        Some(Self { p50: at(50), p90: at(90), p99: at(99) })
+    }
+}
+
+/// One second of a stream, latencies over the trailing `LATENCY_WINDOW` seconds
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: i64,
+    depth_latency: Option<Percentiles>,
+    trade_latency: Option<Percentiles>,
+    messages_per_sec: f32,
+    bytes_per_sec: f32,
+}
+
+#[derive(Debug, Default)]
+struct StreamHealth {
+    /// Latencies received during each of the last seconds, the current one at the back
+    depth_latencies: VecDeque<Vec<i64>>,
+    trade_latencies: VecDeque<Vec<i64>>,
+    samples: VecDeque<Sample>,
+    resyncs: u32,
+    reconnects: u32,
+    state: Option<ConnectionState>,
+}
+
+impl StreamHealth {
+    /// Counts resyncs, and reconnects as each time a live connection is lost
+    fn record_state(&mut self, state: &ConnectionState) {
+        if self.state.as_ref() == Some(state) {
+            return;
+        }
+
+        let was_connected = matches!(self.state, Some(ConnectionState::Live | ConnectionState::Resyncing));
+
+        match state {
+            ConnectionState::Resyncing => self.resyncs += 1,
+            ConnectionState::Connecting | ConnectionState::Down(_) if was_connected => self.reconnects += 1,
+            _ => {}
+        }
+
+        self.state = Some(state.clone());
+    }
+}
+
+/// Latency, throughput and connection health of every stream the layout is subscribed to,
+/// sampled once a second and kept for the last few minutes
+#[derive(Debug, Default)]
+pub struct FeedMonitor {
+    streams: HashMap<StreamType, StreamHealth>,
+    last_sample: Option<i64>,
+}
+
+impl FeedMonitor {
+    const MAX_SAMPLES: usize = 10 * 60;
+
+    /// Seconds of latencies the percentiles are taken over, a second alone holds too few updates
+    const LATENCY_WINDOW: usize = 60;
+
+    /// Gaps longer than this, e.g. while another layout was active, start the rates over
+    const MAX_SAMPLE_GAP: i64 = 5 * 1000;
+
+    pub fn record_latency(&mut self, stream_type: StreamType, feed_latency: FeedLatency) {
+        let health = self.streams.entry(stream_type).or_default();
+
+        push_latency(&mut health.depth_latencies, feed_latency.depth_latency);
+
+        if let Some(trade_latency) = feed_latency.trade_latency {
+            push_latency(&mut health.trade_latencies, trade_latency);
+        }
+    }
+
+    pub fn record_state(&mut self, streams: &[StreamType], state: &ConnectionState) {
+        for stream_type in streams {
+            self.streams.entry(*stream_type).or_default().record_state(state);
+        }
+    }
+
+    /// Closes the second, streams that are neither in `active` nor received anything are dropped
+    pub fn sample(&mut self, time: i64, throughput: HashMap<StreamType, Throughput>, active: &[StreamType]) {
+        let elapsed = self.last_sample
+            .map(|last_sample| time - last_sample)
+            .filter(|elapsed| *elapsed > 0 && *elapsed <= Self::MAX_SAMPLE_GAP);
+
+        self.last_sample = Some(time);
+
+        self.streams.retain(|stream_type, _| active.contains(stream_type) || throughput.contains_key(stream_type));
+
+        for stream_type in active.iter().chain(throughput.keys()) {
+            self.streams.entry(*stream_type).or_default();
+        }
+
+        for (stream_type, health) in self.streams.iter_mut() {
+            let depth_latency = Percentiles::of(&health.depth_latencies);
+            let trade_latency = Percentiles::of(&health.trade_latencies);
+
+            for latencies in [&mut health.depth_latencies, &mut health.trade_latencies] {
+                latencies.push_back(vec![]);
+
+                while latencies.len() > Self::LATENCY_WINDOW {
+                    latencies.pop_front();
+                }
+            }
+
+            let Some(elapsed) = elapsed else {
+                continue;
+            };
+
+            let received = throughput.get(stream_type).copied().unwrap_or_default();
+            let seconds = elapsed as f32 / 1000.0;
+
+            health.samples.push_back(Sample {
+                time,
+                depth_latency,
+                trade_latency,
+                messages_per_sec: received.messages as f32 / seconds,
+                bytes_per_sec: received.bytes as f32 / seconds,
+            });
+
+            while health.samples.len() > Self::MAX_SAMPLES {
+                health.samples.pop_front();
+            }
+        }
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let mut streams: Vec<(&StreamType, &StreamHealth)> = self.streams.iter().collect();
+        streams.sort_by_key(|(stream_type, _)| stream_label(stream_type));
+
+        if streams.is_empty() {
+            return Text::new("No active streams")
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .size(16)
+                .into();
+        }
+
+        let column = streams.into_iter().fold(
+            Column::new().spacing(12).padding(10),
+            |column, (stream_type, health)| column.push(view_stream(stream_type, health))
+        );
+
+        scrollable(column).into()
+    }
+}
+
+fn push_latency(buckets: &mut VecDeque<Vec<i64>>, latency: i64) {
+    match buckets.back_mut() {
+        Some(current) => current.push(latency),
+        None => buckets.push_back(vec![latency]),
+    }
+}
+
+fn view_stream<'a>(stream_type: &StreamType, health: &'a StreamHealth) -> Element<'a, Message> {
+    let latest = health.samples.back();
+
+    let latency_info = |label: &str, percentiles: Option<Percentiles>| match percentiles {
+        Some(Percentiles { p50, p90, p99 }) => format!("{label} p50 {p50}ms  p90 {p90}ms  p99 {p99}ms"),
+        None => format!("{label} -"),
+    };
+
+    let mut info = vec![
+        format!(
+            "{:.1} msg/s  {}B/s",
+            latest.map_or(0.0, |sample| sample.messages_per_sec),
+            format_large_number(latest.map_or(0.0, |sample| sample.bytes_per_sec)),
+        ),
+        format!("{} resyncs  {} reconnects", health.resyncs, health.reconnects),
+    ];
+
+    if let StreamType::DepthAndTrades { .. } = stream_type {
+        info.insert(0, latency_info("Depth", latest.and_then(|sample| sample.depth_latency)));
+        info.insert(1, latency_info("Trades", latest.and_then(|sample| sample.trade_latency)));
+    }
+
+    let mut header = Text::new(stream_label(stream_type)).size(14);
+
+    if let Some(state) = health.state.as_ref().filter(|state| **state != ConnectionState::Live) {
+        header = Text::new(format!("{}  {}", stream_label(stream_type), state_label(state)))
+            .size(14)
+            .style(style::stale_text);
+    }
+
+    Column::new()
+        .spacing(2)
+        .push(header)
+        .push(Text::new(info.join("   ")).size(12))
+        .push(
+            Canvas::new(HealthCanvas { samples: &health.samples })
+                .width(Length::Fill)
+                .height(Length::Fixed(60.0))
+        )
+        .into()
+}
+
+fn stream_label(stream_type: &StreamType) -> String {
+    match stream_type {
+        StreamType::DepthAndTrades { exchange, ticker, resolution } => match resolution {
+            Some(resolution) => format!("{exchange} {ticker} depth & trades ({resolution})"),
+            None => format!("{exchange} {ticker} depth & trades"),
+        },
+        StreamType::Kline { exchange, ticker, timeframe } => format!("{exchange} {ticker} klines {timeframe}"),
+        StreamType::MarketStats { exchange, ticker } => format!("{exchange} {ticker} market stats"),
+        StreamType::Liquidations { exchange, ticker } => format!("{exchange} {ticker} liquidations"),
+        StreamType::None => "None".to_string(),
+    }
+}
+
+fn state_label(state: &ConnectionState) -> String {
+    match state {
+        ConnectionState::Connecting => "Connecting...".to_string(),
+        ConnectionState::Resyncing => "Resyncing...".to_string(),
+        ConnectionState::Down(reason) => format!("Down: {reason}"),
+        ConnectionState::Live => "Live".to_string(),
+    }
+}
+
+const DEPTH_LATENCY_COLOR: Color = Color::from_rgb(0.4, 0.6, 0.9);
+const TRADE_LATENCY_COLOR: Color = Color::from_rgb(0.9, 0.75, 0.3);
+const MESSAGES_COLOR: Color = Color::from_rgb(0.5, 0.8, 0.5);
+
+/// p99 depth and trade latency with the message rate over the kept samples, each scaled to its own range
+struct HealthCanvas<'a> {
+    samples: &'a VecDeque<Sample>,
+}
+
+impl canvas::Program<Message> for HealthCanvas<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+
+        let Some(last) = self.samples.back() else {
+            return vec![frame.into_geometry()];
+        };
+
+        // always the full window wide, so the lines grow in from the right
+        let earliest = last.time - FeedMonitor::MAX_SAMPLES as i64 * 1000;
+        let span = (last.time - earliest) as f32;
+
+        let mut plot_line = |values: Vec<(i64, f32)>, color: Color| {
+            let highest = values.iter().fold(0.0_f32, |highest, (_, value)| highest.max(*value));
+            let range = if highest > 0.0 { highest } else { 1.0 };
+
+            let line = Path::new(|builder| {
+                for (i, (time, value)) in values.iter().enumerate() {
+                    let point = Point::new(
+                        (time - earliest) as f32 / span * bounds.width,
+                        bounds.height - (value / range * (bounds.height - 2.0)) - 1.0,
+                    );
+
+                    if i == 0 {
+                        builder.move_to(point);
+                    } else {
+                        builder.line_to(point);
+                    }
+                }
+            });
+
+            frame.stroke(&line, Stroke::default().with_color(color).with_width(1.0));
+        };
+
+        plot_line(
+            self.samples.iter()
+                .map(|sample| (sample.time, sample.messages_per_sec))
+                .collect(),
+            MESSAGES_COLOR,
+        );
+
+        for (latency, color) in [
+            (self.samples.iter().filter_map(|sample| Some((sample.time, sample.depth_latency?.p99 as f32))).collect::<Vec<_>>(), DEPTH_LATENCY_COLOR),
+            (self.samples.iter().filter_map(|sample| Some((sample.time, sample.trade_latency?.p99 as f32))).collect::<Vec<_>>(), TRADE_LATENCY_COLOR),
+        ] {
+            if !latency.is_empty() {
+                plot_line(latency, color);
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
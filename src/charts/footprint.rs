@@ -3,9 +3,11 @@ use iced::{
     alignment, mouse, widget::{button, canvas::{self, event::{self, Event}, stroke::Stroke, Canvas, Geometry, Path}}, Color, Element, Length, Point, Rectangle, Renderer, Size, Theme
 };
 use iced::widget::{Column, Row, Container, Text};
-use crate::data_providers::{Kline, Trade};
+use crate::data_providers::{ChartBasis, Kline, Price, Rounding, Trade};
 
-use super::{Chart, CommonChartData, Message, Interaction, AxisLabelXCanvas, AxisLabelYCanvas};
+use super::{Chart, CommonChartData, Message, Interaction, AxisLabelXCanvas, AxisLabelYCanvas, TradeBarBuilder};
+use super::indicators::{view_market_stats, MarketStatsHistory};
+use super::volume_profile::{session_start, ProfileMode, ProfileRange, VolumeProfile};
 use super::chart_button;
 
 
@@ -20,19 +22,60 @@ impl Chart for FootprintChart {
     }
 }
 
+/// Footprint rows are keyed by the index of the tick nearest to the trade price
+fn price_level(trade: &Trade, tick_size: Price) -> i64 {
+    trade.price.tick_index(tick_size, Rounding::Nearest)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backfill {
+    NotStarted,
+    InProgress(f32),
+    Done,
+}
+
 pub struct FootprintChart {
     chart: CommonChartData,
     data_points: BTreeMap<i64, (HashMap<i64, (f32, f32)>, Kline)>,
     timeframe: u16,
-    tick_size: f32,
+    tick_size: Price,
     raw_trades: Vec<Trade>,
+    live_since: Option<i64>,
+    backfill: Backfill,
+    trade_bars: Option<TradeBarBuilder>,
+    volume_profile: ProfileRange,
 }
 
 impl FootprintChart {
     const MIN_SCALING: f32 = 0.4;
     const MAX_SCALING: f32 = 3.6;
 
-    pub fn new(timeframe: u16, tick_size: f32, klines_raw: Vec<Kline>, raw_trades: Vec<Trade>) -> Self {
+    /// Caps how far back historical trades are fetched, a few hours of aggTrades is already hundreds of requests
+    const MAX_BACKFILL_MS: i64 = 4 * 60 * 60 * 1000;
+
+    /// Bars built from trades have no visible time range to go by, so they get the last hour
+    const TRADE_BARS_BACKFILL_MS: i64 = 60 * 60 * 1000;
+
+    pub fn new(basis: ChartBasis, tick_size: Price, klines_raw: Vec<Kline>, raw_trades: Vec<Trade>) -> Self {
+        if let Some(trade_bars) = TradeBarBuilder::new(basis) {
+            let mut chart = FootprintChart {
+                chart: CommonChartData::default(),
+                data_points: BTreeMap::new(),
+                timeframe: 1,
+                tick_size,
+                raw_trades,
+                live_since: None,
+                backfill: Backfill::NotStarted,
+                trade_bars: Some(trade_bars),
+                volume_profile: ProfileRange::default(),
+            };
+            chart.rebuild_trade_bars();
+
+            return chart;
+        }
+
+        let timeframe = basis.timeframe().map_or(1, |timeframe| timeframe.to_minutes());
+
         let mut data_points = BTreeMap::new();
         let aggregate_time = 1000 * 60 * timeframe as i64;
 
@@ -41,7 +84,7 @@ impl FootprintChart {
         };
         for trade in &raw_trades {
             let rounded_time = (trade.time / aggregate_time) * aggregate_time;
-            let price_level: i64 = (trade.price * (1.0 / tick_size)).round() as i64;
+            let price_level: i64 = price_level(trade, tick_size);
 
             let entry = data_points
                 .entry(rounded_time)
@@ -66,17 +109,103 @@ impl FootprintChart {
             timeframe,
             tick_size,
             raw_trades,
+            live_since: None,
+            backfill: Backfill::NotStarted,
+            trade_bars: None,
+            volume_profile: ProfileRange::default(),
+        }
+    }
+
+    /// Re-assigns every trade gathered so far to bars, in the order they happened
+    fn rebuild_trade_bars(&mut self) {
+        let Some(trade_bars) = &self.trade_bars else {
+            return;
+        };
+
+        let mut trade_bars = TradeBarBuilder::new(trade_bars.basis);
+        self.raw_trades.sort_by_key(|trade| trade.time);
+        self.data_points.clear();
+
+        if let Some(trade_bars) = &mut trade_bars {
+            for trade in &self.raw_trades {
+                let slot = trade_bars.slot_for(trade);
+                Self::add_to_trade_bar(&mut self.data_points, slot, trade, self.tick_size);
+            }
+        }
+
+        self.trade_bars = trade_bars;
+    }
+
+    fn add_to_trade_bar(
+        data_points: &mut BTreeMap<i64, (HashMap<i64, (f32, f32)>, Kline)>,
+        slot: i64,
+        trade: &Trade,
+        tick_size: Price,
+    ) {
+        let (trades, kline) = data_points.entry(slot).or_insert((
+            HashMap::new(),
+            Kline {
+                time: slot as u64,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                volume: (0.0, 0.0),
+            },
+        ));
+
+        kline.high = kline.high.max(trade.price);
+        kline.low = kline.low.min(trade.price);
+        kline.close = trade.price;
+
+        let price_level: i64 = price_level(trade, tick_size);
+        let (buy_qty, sell_qty) = trades.entry(price_level).or_insert((0.0, 0.0));
+
+        if trade.is_sell {
+            *sell_qty += trade.qty;
+            kline.volume.1 += trade.qty;
+        } else {
+            *buy_qty += trade.qty;
+            kline.volume.0 += trade.qty;
         }
     }
 
+    /// Rebuilds the chart around freshly fetched klines, keeping the trades gathered so far
+    pub fn reset_klines(&mut self, basis: ChartBasis, klines_raw: Vec<Kline>) {
+        let raw_trades = std::mem::take(&mut self.raw_trades);
+
+        let mut chart = FootprintChart::new(basis, self.tick_size, klines_raw, raw_trades);
+        chart.live_since = self.live_since;
+        chart.backfill = self.backfill;
+        chart.volume_profile = self.volume_profile;
+
+        *self = chart;
+    }
+
     pub fn insert_datapoint(&mut self, trades_buffer: &[Trade], depth_update: i64) {
+        if self.live_since.is_none() {
+            self.live_since = trades_buffer.first().map(|trade| trade.time);
+        }
+
+        if let Some(trade_bars) = &mut self.trade_bars {
+            for trade in trades_buffer {
+                let slot = trade_bars.slot_for(trade);
+                Self::add_to_trade_bar(&mut self.data_points, slot, trade, self.tick_size);
+
+                self.raw_trades.push(*trade);
+            }
+
+            self.render_start();
+            return;
+        }
+
         let aggregate_time = 1000 * 60 * self.timeframe as i64;
         let rounded_depth_update = (depth_update / aggregate_time) * aggregate_time;
     
         self.data_points.entry(rounded_depth_update).or_insert((HashMap::new(), Kline::default()));
         
         for trade in trades_buffer {
-            let price_level: i64 = (trade.price * (1.0 / self.tick_size)).round() as i64;
+            let price_level: i64 = price_level(trade, self.tick_size);
             if let Some((trades, _)) = self.data_points.get_mut(&rounded_depth_update) {     
                 if let Some((buy_qty, sell_qty)) = trades.get_mut(&price_level) {
                     if trade.is_sell {
@@ -95,6 +224,96 @@ impl FootprintChart {
         }
     }
 
+    /// Time range of the candles in view that should be filled with historical trades, if not done already
+    pub fn backfill_range(&self) -> Option<(i64, i64)> {
+        if self.backfill != Backfill::NotStarted {
+            return None;
+        }
+
+        if self.trade_bars.is_some() {
+            let to_time = self.live_since
+                .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+
+            return Some((to_time - Self::TRADE_BARS_BACKFILL_MS, to_time));
+        }
+
+        let aggregate_time = 1000 * 60 * self.timeframe as i64;
+        let (latest, earliest) = self.visible_timerange();
+
+        // not laid out yet, assume the default 800px wide view
+        let earliest = if earliest < latest {
+            earliest
+        } else {
+            latest - (640000.0 * self.timeframe as f32) as i64
+        };
+
+        let to_time = self.live_since
+            .unwrap_or_else(|| latest + aggregate_time);
+        let from_time = ((earliest / aggregate_time) * aggregate_time)
+            .max(to_time - Self::MAX_BACKFILL_MS);
+
+        if self.data_points.is_empty() || from_time >= to_time {
+            return None;
+        }
+
+        Some((from_time, to_time))
+    }
+
+    pub fn start_backfill(&mut self) {
+        self.backfill = Backfill::InProgress(0.0);
+    }
+
+    pub fn backfill_progress(&self) -> Option<f32> {
+        match self.backfill {
+            Backfill::InProgress(progress) => Some(progress),
+            _ => None,
+        }
+    }
+
+    /// Merges a page of historical trades, skipping any the live stream has already delivered
+    pub fn insert_backfill_trades(&mut self, trades: &[Trade], progress: f32) {
+        let live_since = self.live_since.unwrap_or(i64::MAX);
+        let trades = trades.iter().filter(|trade| trade.time < live_since);
+
+        if self.trade_bars.is_some() {
+            // older trades shift every bar after them
+            self.raw_trades.extend(trades);
+            self.rebuild_trade_bars();
+        } else {
+            let aggregate_time = 1000 * 60 * self.timeframe as i64;
+
+            for trade in trades {
+                let rounded_time = (trade.time / aggregate_time) * aggregate_time;
+                let price_level: i64 = price_level(trade, self.tick_size);
+
+                let entry = self.data_points
+                    .entry(rounded_time)
+                    .or_insert((HashMap::new(), Kline::default()));
+
+                let (buy_qty, sell_qty) = entry.0.entry(price_level).or_insert((0.0, 0.0));
+                if trade.is_sell {
+                    *sell_qty += trade.qty;
+                } else {
+                    *buy_qty += trade.qty;
+                }
+
+                self.raw_trades.push(*trade);
+            }
+        }
+
+        self.backfill = if progress >= 1.0 {
+            Backfill::Done
+        } else {
+            Backfill::InProgress(progress)
+        };
+
+        self.render_start();
+    }
+
+    pub fn cancel_backfill(&mut self) {
+        self.backfill = Backfill::Done;
+    }
+
     pub fn update_latest_kline(&mut self, kline: &Kline) {
         if let Some((_, kline_value)) = self.data_points.get_mut(&(kline.time as i64)) {
             kline_value.open = kline.open;
@@ -107,15 +326,49 @@ impl FootprintChart {
         self.render_start();
     }
 
-    pub fn get_raw_trades(&self) -> Vec<Trade> {
-        self.raw_trades.clone()
+    /// Klines from a previous page, trades already aggregated into those candles are kept
+    pub fn insert_older_klines(&mut self, klines: &[Kline]) {
+        for kline in klines {
+            let (_, kline_value) = self.data_points
+                .entry(kline.time as i64)
+                .or_insert((HashMap::new(), *kline));
+
+            *kline_value = *kline;
+        }
+
+        self.chart.older_klines_received(klines.len());
+
+        self.render_start();
     }
-    
-    pub fn get_tick_size(&self) -> f32 {
+
+    /// Lets the next pan retry a page that failed to load
+    pub fn older_klines_failed(&mut self) {
+        self.chart.fetching_older = false;
+    }
+
+    /// See `CommonChartData::older_klines_request`
+    pub fn older_klines_request(&mut self) -> Option<i64> {
+        if self.trade_bars.is_some() {
+            return None;
+        }
+
+        let (_, earliest) = self.visible_timerange();
+        let earliest_loaded = self.data_points.keys().next().copied();
+
+        self.chart.older_klines_request(earliest, earliest_loaded)
+    }
+
+    pub fn get_tick_size(&self) -> Price {
         self.tick_size
     }
-    
-    pub fn change_tick_size(&mut self, new_tick_size: f32) {
+
+    pub fn change_tick_size(&mut self, new_tick_size: Price) {
+        if self.trade_bars.is_some() {
+            self.tick_size = new_tick_size;
+            self.rebuild_trade_bars();
+            return;
+        }
+
         let mut new_data_points = BTreeMap::new();
         let aggregate_time = 1000 * 60 * self.timeframe as i64;
 
@@ -125,7 +378,7 @@ impl FootprintChart {
 
         for trade in self.raw_trades.iter() {
             let rounded_time = (trade.time / aggregate_time) * aggregate_time;
-            let price_level: i64 = (trade.price * (1.0 / new_tick_size)).round() as i64;
+            let price_level: i64 = price_level(trade, new_tick_size);
 
             let entry = new_data_points
                 .entry(rounded_time)
@@ -148,6 +401,47 @@ impl FootprintChart {
         self.tick_size = new_tick_size;
     }
 
+    pub fn get_volume_profile(&self) -> ProfileRange {
+        self.volume_profile
+    }
+
+    pub fn set_volume_profile(&mut self, volume_profile: ProfileRange) {
+        self.volume_profile = volume_profile;
+
+        self.chart.main_cache.clear();
+    }
+
+    /// Switches the profile's mode, a fixed range covers the bars in view
+    pub fn select_profile_mode(&mut self, mode: ProfileMode) -> ProfileRange {
+        self.set_volume_profile(
+            ProfileRange::with_mode(mode, self.chart.x_min_time, self.chart.x_max_time)
+        );
+
+        self.volume_profile
+    }
+
+    /// Footprint rows of the bars in the profile's range, summed by price level
+    fn build_volume_profile(&self) -> Option<VolumeProfile> {
+        let (from, to) = self.volume_profile.bars(
+            self.chart.x_min_time,
+            self.chart.x_max_time,
+            || session_start(
+                *self.data_points.keys().next_back()?,
+                self.trade_bars.as_ref().map(|bars| &bars.open_times),
+            ),
+        )?;
+
+        let mut profile = VolumeProfile::new(self.tick_size, self.chart.y_max_price - self.chart.y_min_price);
+
+        for (_, (trades, _)) in self.data_points.range(from..=to) {
+            for (price_level, (buy_qty, sell_qty)) in trades {
+                profile.add(*price_level, *buy_qty, *sell_qty);
+            }
+        }
+
+        Some(profile)
+    }
+
     pub fn render_start(&mut self) {
         let (latest, earliest, highest, lowest) = self.calculate_range();
         if highest <= 0.0 || lowest <= 0.0 {
@@ -176,24 +470,26 @@ impl FootprintChart {
         chart_state.main_cache.clear();
     }
 
-    fn calculate_range(&self) -> (i64, i64, f32, f32) {
+    fn visible_timerange(&self) -> (i64, i64) {
         let chart = self.get_common_data();
 
         let timestamp_latest = self.data_points.keys().last().unwrap_or(&0);
 
         let latest: i64 = *timestamp_latest - ((chart.translation.x*800.0)*(self.timeframe as f32)) as i64;
         let earliest: i64 = latest - ((640000.0*self.timeframe as f32) / (chart.scaling / (chart.bounds.width/800.0))) as i64;
+
+        (latest, earliest)
+    }
+
+    fn calculate_range(&self) -> (i64, i64, f32, f32) {
+        let (latest, earliest) = self.visible_timerange();
     
         let mut highest: f32 = 0.0;
         let mut lowest: f32 = std::f32::MAX;
 
         for (_, (_, kline)) in self.data_points.range(earliest..=latest) {
-            if kline.high > highest {
-                highest = kline.high;
-            }
-            if kline.low < lowest {
-                lowest = kline.low;
-            }
+            highest = highest.max(kline.high.to_f32());
+            lowest = lowest.min(kline.low.to_f32());
         }
 
         highest = highest + (highest - lowest) * 0.05;
@@ -255,7 +551,7 @@ impl FootprintChart {
         }
     }
 
-    pub fn view(&self) -> Element<Message> {
+    pub fn view<'a>(&'a self, market_stats: Option<&'a MarketStatsHistory>) -> Element<'a, Message> {
         let chart = Canvas::new(self)
             .width(Length::FillPortion(10))
             .height(Length::FillPortion(10));
@@ -270,7 +566,8 @@ impl FootprintChart {
                 crosshair_cache: &chart_state.x_crosshair_cache, 
                 crosshair_position: chart_state.crosshair_position, 
                 crosshair: chart_state.crosshair,
-                timeframe: Some(self.timeframe)
+                timeframe: Some(self.timeframe),
+                open_times: self.trade_bars.as_ref().map(|bars| &bars.open_times),
             })
             .width(Length::FillPortion(10))
             .height(Length::Fixed(26.0));
@@ -322,13 +619,21 @@ impl FootprintChart {
             .push(axis_labels_x)
             .push(chart_controls);
     
-        let content = Column::new()
-            .push(chart_and_y_labels)
+        let mut content = Column::new()
+            .push(chart_and_y_labels);
+
+        // stats are sampled by time, bars built from trades have no time axis to line them up with
+        if let (Some(history), None) = (market_stats, &self.trade_bars) {
+            content = content.push(
+                view_market_stats(history, chart_state.x_min_time, chart_state.x_max_time)
+            );
+        }
+
+        content
             .push(bottom_row)
             .spacing(0)
-            .padding(5);
-    
-        content.into()
+            .padding(5)
+            .into()
     }
 }
 
@@ -500,7 +805,7 @@ impl canvas::Program<Message> for FootprintChart {
 
             let max_bar_width = min_distance / 2.0;
 
-            let bar_height = ((footprint_area_height / (y_range / self.tick_size) as f32).floor()).max(1.0);
+            let bar_height = ((footprint_area_height / (y_range / self.tick_size.to_f32())).floor()).max(1.0);
 
             for (time, (trades, kline)) in self.data_points.range(earliest..=latest) {
                 let x_position: f32 = ((time - earliest) as f32 / (latest - earliest) as f32) * bounds.width;
@@ -509,10 +814,10 @@ impl canvas::Program<Message> for FootprintChart {
                     continue;
                 }
 
-                let y_open = footprint_area_height - ((kline.open - lowest) / y_range * footprint_area_height);
-                let y_high = footprint_area_height - ((kline.high - lowest) / y_range * footprint_area_height);
-                let y_low = footprint_area_height - ((kline.low - lowest) / y_range * footprint_area_height);
-                let y_close = footprint_area_height - ((kline.close - lowest) / y_range * footprint_area_height);
+                let y_open = footprint_area_height - ((kline.open.to_f32() - lowest) / y_range * footprint_area_height);
+                let y_high = footprint_area_height - ((kline.high.to_f32() - lowest) / y_range * footprint_area_height);
+                let y_low = footprint_area_height - ((kline.low.to_f32() - lowest) / y_range * footprint_area_height);
+                let y_close = footprint_area_height - ((kline.close.to_f32() - lowest) / y_range * footprint_area_height);
 
                 let body_color = 
                     if kline.close >= kline.open { 
@@ -537,7 +842,7 @@ impl canvas::Program<Message> for FootprintChart {
                 );
 
                 for trade in trades {
-                    let price = (*trade.0 as f32) / (1.0 / self.tick_size);
+                    let price = Price::from_tick_index(*trade.0, self.tick_size).to_f32();
                     let y_position = footprint_area_height - ((price - lowest) / y_range * footprint_area_height);
 
                     if trade.1.0 > 0.0 {
@@ -597,6 +902,10 @@ impl canvas::Program<Message> for FootprintChart {
                     }
                 }
             } 
+
+            if let Some(profile) = self.build_volume_profile() {
+                profile.draw(frame, bounds.width, footprint_area_height, lowest, highest);
+            }
             
             let text_size = 9.0;
             let text_content = format!("{max_volume:.2}");
@@ -1,13 +1,123 @@
-use std::{collections::{BTreeMap, HashMap, VecDeque}, rc::Rc, time::Instant};
+use std::{collections::{BTreeMap, HashMap, VecDeque}, time::Instant};
 use chrono::NaiveDateTime;
 use iced::{
     alignment, mouse, widget::{button, canvas::{self, event::{self, Event}, stroke::Stroke, Canvas, Geometry, Path}}, Color, Element, Length, Point, Rectangle, Renderer, Size, Theme, Vector
 };
 use iced::widget::{Column, Row, Container, Text};
 
-use crate::data_providers::{Depth, Order, Trade};
+use serde::{Deserialize, Serialize};
 
-use super::{Chart, CommonChartData, Message, chart_button, Interaction, AxisLabelYCanvas, AxisLabelXCanvas};
+use crate::data_providers::{Depth, Liquidation, Order, Price, Rounding, Trade};
+
+use super::{Chart, CommonChartData, Message, chart_button, draw_liquidation_marker, Interaction, AxisLabelYCanvas, AxisLabelXCanvas};
+
+/// Time bucket each heatmap column covers, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AggregationInterval(pub u16);
+
+impl AggregationInterval {
+    pub const ALL: [AggregationInterval; 6] = [
+        AggregationInterval(100),
+        AggregationInterval(250),
+        AggregationInterval(500),
+        AggregationInterval(1000),
+        AggregationInterval(2000),
+        AggregationInterval(5000),
+    ];
+}
+
+impl Default for AggregationInterval {
+    fn default() -> Self {
+        AggregationInterval(100)
+    }
+}
+
+impl std::fmt::Display for AggregationInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            ms if ms >= 1000 && ms % 1000 == 0 => write!(f, "{}s", ms / 1000),
+            ms => write!(f, "{ms}ms"),
+        }
+    }
+}
+
+/// How a level's quantity maps to the intensity of its colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum ColorScale {
+    /// Relative to the largest visible level
+    #[default]
+    Linear,
+    /// Tells apart the smaller levels that a few large walls would wash out on a linear scale
+    Logarithmic,
+    /// Relative to the 95th percentile of the visible levels, anything above is at full intensity
+    Percentile,
+}
+
+impl ColorScale {
+    pub const ALL: [ColorScale; 3] = [ColorScale::Linear, ColorScale::Logarithmic, ColorScale::Percentile];
+}
+
+impl std::fmt::Display for ColorScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorScale::Linear => write!(f, "Linear"),
+            ColorScale::Logarithmic => write!(f, "Logarithmic"),
+            ColorScale::Percentile => write!(f, "Percentile (p95)"),
+        }
+    }
+}
+
+/// Colours of the depth cells, either one per side or a single gradient for both
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum ColorMap {
+    /// Bids in teal and asks in magenta, fading with the quantity
+    #[default]
+    BidAsk,
+    Viridis,
+    Inferno,
+    Grayscale,
+}
+
+impl ColorMap {
+    pub const ALL: [ColorMap; 4] = [ColorMap::BidAsk, ColorMap::Viridis, ColorMap::Inferno, ColorMap::Grayscale];
+
+    /// Colour of a cell at `intensity` between 0 and 1
+    fn color(&self, intensity: f32, is_bid: bool) -> Color {
+        let stops: &[(u8, u8, u8)] = match self {
+            ColorMap::BidAsk => {
+                return if is_bid {
+                    Color::from_rgba8(0, 144, 144, intensity)
+                } else {
+                    Color::from_rgba8(192, 0, 192, intensity)
+                };
+            },
+            ColorMap::Viridis => &[(68, 1, 84), (59, 82, 139), (33, 145, 140), (94, 201, 98), (253, 231, 37)],
+            ColorMap::Inferno => &[(0, 0, 4), (87, 16, 110), (188, 55, 84), (249, 142, 9), (252, 255, 164)],
+            ColorMap::Grayscale => &[(40, 40, 40), (240, 240, 240)],
+        };
+
+        let position = intensity * (stops.len() - 1) as f32;
+        let index = (position.floor() as usize).min(stops.len() - 2);
+        let ratio = position - index as f32;
+
+        let (from, to) = (stops[index], stops[index + 1]);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * ratio) / 255.0;
+
+        // the quietest levels still fade out, like they do on the bid/ask map
+        Color::from_rgba(mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2), intensity.max(0.1))
+    }
+}
+
+impl std::fmt::Display for ColorMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorMap::BidAsk => write!(f, "Bid/Ask"),
+            ColorMap::Viridis => write!(f, "Viridis"),
+            ColorMap::Inferno => write!(f, "Inferno"),
+            ColorMap::Grayscale => write!(f, "Grayscale"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct GroupedDepth {
@@ -16,7 +126,7 @@ pub struct GroupedDepth {
 }
 pub struct GroupedTrade {
     pub is_sell: bool,
-    pub price: f32,
+    pub price: Price,
     pub qty: f32,
 }
 
@@ -26,19 +136,47 @@ struct QtyScale {
     min_trade_qty: f32,
     max_aggr_volume: f32,
     max_depth_qty: f32,
+    /// Only worked out for `ColorScale::Percentile`
+    depth_qty_p95: f32,
+}
+
+type HeatmapDataPoint = (i64, (GroupedDepth, Box<[GroupedTrade]>));
+
+/// Data points older than the full resolution ones, one per `interval` with the depth averaged
+/// and the trades summed up by price, so a zoomed out heatmap reaches back hours
+struct HistoryTier {
+    interval: i64,
+    max_points: usize,
+    points: VecDeque<HeatmapDataPoint>,
+}
+
+impl HistoryTier {
+    fn new(interval: i64, max_points: usize) -> Self {
+        Self {
+            interval,
+            max_points,
+            points: VecDeque::new(),
+        }
+    }
 }
 
 pub struct HeatmapChart {
     chart: CommonChartData,
-    data_points: Vec<(i64, (GroupedDepth, Box<[GroupedTrade]>))>,
-    tick_size: f32,
+    data_points: Vec<HeatmapDataPoint>,
+    /// From the finest to the coarsest, each one taking over what ages out of the one before
+    history: Vec<HistoryTier>,
+    tick_size: Price,
     y_scaling: i32,
     size_filter: f32,
     qty_scales: QtyScale,
+    liquidations: Vec<Liquidation>,
+    aggregation: AggregationInterval,
+    color_scale: ColorScale,
+    color_map: ColorMap,
 }
 
 impl Chart for HeatmapChart {
-    type DataPoint = Vec<(i64, (GroupedDepth, Box<[GroupedTrade]>))>;
+    type DataPoint = Vec<HeatmapDataPoint>;
 
     fn get_common_data(&self) -> &CommonChartData {
         &self.chart
@@ -49,35 +187,52 @@ impl Chart for HeatmapChart {
 }
 
 impl HeatmapChart {
-    const MIN_SCALING: f32 = 1.0;
+    const MIN_SCALING: f32 = 0.01;
     const MAX_SCALING: f32 = 3.0;
 
-    pub fn new(tick_size: f32) -> Self {
+    /// About 4 minutes of 100ms updates
+    const MAX_FULL_RESOLUTION_POINTS: usize = 2400;
+
+    /// Points moved on to the next tier at a time, rounded down to its whole buckets
+    const ROLLOVER_CHUNK: usize = 400;
+
+    fn history_tiers() -> Vec<HistoryTier> {
+        vec![
+            HistoryTier::new(1000, 15 * 60), // 1s for 15 minutes
+            HistoryTier::new(5 * 1000, 12 * 60), // 5s for an hour
+            HistoryTier::new(30 * 1000, 8 * 120), // 30s for 8 hours
+        ]
+    }
+
+    pub fn new(tick_size: Price) -> Self {
         HeatmapChart {
             chart: CommonChartData::default(),
             data_points: Vec::new(),
+            history: Self::history_tiers(),
             tick_size,
             y_scaling: 100,
             size_filter: 0.0,
             qty_scales: QtyScale::default(),
+            liquidations: Vec::new(),
+            aggregation: AggregationInterval::default(),
+            color_scale: ColorScale::default(),
+            color_map: ColorMap::default(),
         }
     }
 
-    fn group_by_price(&self, orders: &[Order], is_bid: bool) -> Box<[Order]> {
+    fn group_by_price(&self, orders: impl Iterator<Item = Order>, is_bid: bool) -> Box<[Order]> {
+        let tick_size = self.tick_size;
+        let rounding = if is_bid { Rounding::Floor } else { Rounding::Ceil };
+
         let mut grouped: HashMap<i64, f32> = HashMap::new();
 
-        for &order in orders {
-            let rounded_price = if is_bid {
-                ((order.price * (1.0 / self.tick_size)).floor()) as i64
-            } else {
-                ((order.price * (1.0 / self.tick_size)).ceil()) as i64
-            };
-            *grouped.entry(rounded_price).or_insert(0.0) += order.qty;
+        for order in orders {
+            *grouped.entry(order.price.tick_index(tick_size, rounding)).or_insert(0.0) += order.qty;
         }
 
         grouped.into_iter().map(
-            |(price, qty)| Order {
-                price: price as f32 * self.tick_size, 
+            |(tick, qty)| Order {
+                price: Price::from_tick_index(tick, tick_size),
                 qty 
             }
         ).collect()
@@ -90,22 +245,69 @@ impl HeatmapChart {
         self.size_filter
     }
 
-    pub fn change_tick_size(&mut self, tick_size: f32) {
+    pub fn get_aggregation(&self) -> AggregationInterval {
+        self.aggregation
+    }
+
+    /// Columns already drawn keep their bucket, the new one applies from the next update on
+    pub fn set_aggregation(&mut self, aggregation: AggregationInterval) {
+        self.aggregation = aggregation;
+    }
+
+    pub fn get_color_scale(&self) -> ColorScale {
+        self.color_scale
+    }
+
+    pub fn set_color_scale(&mut self, color_scale: ColorScale) {
+        self.color_scale = color_scale;
+
+        self.render_start();
+    }
+
+    pub fn get_color_map(&self) -> ColorMap {
+        self.color_map
+    }
+
+    pub fn set_color_map(&mut self, color_map: ColorMap) {
+        self.color_map = color_map;
+
+        self.render_start();
+    }
+
+    /// Colour of a depth cell, `qty` put on the selected scale against the visible levels
+    fn depth_color(&self, qty: f32, is_bid: bool) -> Color {
+        let max_depth_qty = self.qty_scales.max_depth_qty;
+
+        let intensity = match self.color_scale {
+            ColorScale::Linear => qty / max_depth_qty,
+            ColorScale::Logarithmic => qty.ln_1p() / max_depth_qty.ln_1p(),
+            ColorScale::Percentile => qty / self.qty_scales.depth_qty_p95,
+        };
+
+        self.color_map.color(intensity.clamp(0.0, 1.0), is_bid)
+    }
+
+    pub fn get_tick_size(&self) -> Price {
+        self.tick_size
+    }
+
+    pub fn change_tick_size(&mut self, tick_size: Price) {
         self.tick_size = tick_size;
 
         self.data_points.clear();
+        self.history = Self::history_tiers();
     }
 
-    pub fn insert_datapoint(&mut self, trades_buffer: &[Trade], depth_update: i64, depth: Rc<Depth>) {
-        let aggregate_time = 100; // 100 ms
+    pub fn insert_datapoint(&mut self, trades_buffer: &[Trade], depth_update: i64, depth: &Depth) {
+        let aggregate_time = self.aggregation.0 as i64;
         let rounded_depth_update = (depth_update / aggregate_time) * aggregate_time;
 
         let grouped_depth = {
-            let mut grouped_bids = self.group_by_price(&depth.bids, true);
-            let mut grouped_asks = self.group_by_price(&depth.asks, false);
+            let mut grouped_bids = self.group_by_price(depth.bids(), true);
+            let mut grouped_asks = self.group_by_price(depth.asks(), false);
 
-            grouped_bids.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
-            grouped_asks.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
+            grouped_bids.sort_by_key(|order| order.price);
+            grouped_asks.sort_by_key(|order| order.price);
 
             GroupedDepth {
                 bids: grouped_bids,
@@ -113,36 +315,89 @@ impl HeatmapChart {
             }
         };
 
+        let tick_size = self.tick_size;
+
         let grouped_trades = trades_buffer
             .iter()
             .map(|trade| GroupedTrade {
                 is_sell: trade.is_sell,
                 price: {
-                    let grouped_price = if trade.is_sell {
-                        (trade.price * (1.0 / self.tick_size)).floor()
-                    } else {
-                        (trade.price * (1.0 / self.tick_size)).ceil()
-                    };
-                    grouped_price as f32 * self.tick_size
+                    let rounding = if trade.is_sell { Rounding::Floor } else { Rounding::Ceil };
+                    trade.price.round_to_tick(tick_size, rounding)
                 },
                 qty: trade.qty,
             })
-            .collect();
+            .collect::<Vec<GroupedTrade>>();
         
-        self.data_points.push((rounded_depth_update, (grouped_depth, grouped_trades)));
+        match self.data_points.last_mut() {
+            // another update within the same bucket, the latest depth stands for it
+            Some((time, (depth, trades))) if *time == rounded_depth_update => {
+                *depth = grouped_depth;
+                *trades = std::mem::take(trades).into_vec().into_iter().chain(grouped_trades).collect();
+            },
+            _ => self.data_points.push((rounded_depth_update, (grouped_depth, grouped_trades.into_boxed_slice()))),
+        }
     
-        if self.data_points.len() > 2400 {
-            self.data_points.drain(0..400);
+        if self.data_points.len() > Self::MAX_FULL_RESOLUTION_POINTS {
+            let count = expired_count(&self.data_points, Self::ROLLOVER_CHUNK, self.history[0].interval);
+            let expired: Vec<HeatmapDataPoint> = self.data_points.drain(..count).collect();
+
+            self.roll_over(0, expired);
+
+            // nothing older than the depth is left to draw them over
+            if let Some(oldest) = self.oldest_time() {
+                self.liquidations.retain(|liquidation| liquidation.time >= oldest);
+            }
         }
         
         self.render_start();
     }
 
+    /// Downsamples the points into the tier, passing on what it can't hold to the next one, the coarsest drops it
+    fn roll_over(&mut self, tier: usize, points: Vec<HeatmapDataPoint>) {
+        let next_interval = self.history.get(tier + 1).map(|next| next.interval);
+
+        let Some(current) = self.history.get_mut(tier) else {
+            return;
+        };
+
+        current.points.extend(downsample(&points, current.interval));
+
+        if current.points.len() <= current.max_points {
+            return;
+        }
+
+        let chunk = current.max_points / 6;
+        let count = match next_interval {
+            Some(interval) => expired_count(current.points.make_contiguous(), chunk, interval),
+            None => chunk,
+        };
+        let expired: Vec<HeatmapDataPoint> = current.points.drain(..count).collect();
+
+        self.roll_over(tier + 1, expired);
+    }
+
+    fn oldest_time(&self) -> Option<i64> {
+        self.history.iter().rev()
+            .find_map(|tier| tier.points.front())
+            .or(self.data_points.first())
+            .map(|(time, _)| *time)
+    }
+
+    pub fn insert_liquidations(&mut self, liquidations: &[Liquidation]) {
+        self.liquidations.extend_from_slice(liquidations);
+
+        self.render_start();
+    }
+
     fn visible_data_iter(
         &self, 
         earliest: i64, latest: i64
-    ) -> impl Iterator<Item = &(i64, (GroupedDepth, Box<[GroupedTrade]>))> {
-        self.data_points.iter().filter(move |(time, _)| *time >= earliest && *time <= latest)
+    ) -> impl Iterator<Item = &HeatmapDataPoint> {
+        self.history.iter().rev()
+            .flat_map(|tier| tier.points.iter())
+            .chain(self.data_points.iter())
+            .filter(move |(time, _)| *time >= earliest && *time <= latest)
     }
 
     fn calculate_scales(&self) -> (i64, i64, f32, f32, QtyScale) {
@@ -158,13 +413,16 @@ impl HeatmapChart {
         let (mut min_trade_qty, mut max_trade_qty) = (f32::MAX, 0.0f32);
         let (mut max_aggr_volume, mut max_depth_qty) = (0.0f32, 0.0f32);
 
+        let mut visible_depth_qtys: Vec<f32> = Vec::new();
+        let needs_percentile = self.color_scale == ColorScale::Percentile;
+
         let (autoscale, y_scaling) = (self.chart.autoscale, self.y_scaling as f32);
-        let tick_size = self.tick_size;
+        let tick_size = self.tick_size.to_f32();
 
         for (_, (depth, _)) in self.visible_data_iter(earliest, latest) {
             let mid_price = (
-                depth.bids.last().map(|order| order.price).unwrap_or(0.0)
-                + depth.asks.first().map(|order| order.price).unwrap_or(0.0)
+                depth.bids.last().map(|order| order.price.to_f32()).unwrap_or(0.0)
+                + depth.asks.first().map(|order| order.price.to_f32()).unwrap_or(0.0)
             ) / 2.0;
     
             if autoscale {
@@ -193,13 +451,26 @@ impl HeatmapChart {
             max_aggr_volume = max_aggr_volume.max(buy_volume).max(sell_volume);
 
             for order in depth.asks.iter().chain(depth.bids.iter()) {
-                if order.price > highest || order.price < lowest {
+                let price = order.price.to_f32();
+
+                if price > highest || price < lowest {
                     continue;
                 }
                 max_depth_qty = max_depth_qty.max(order.qty);
+
+                if needs_percentile {
+                    visible_depth_qtys.push(order.qty);
+                }
             }
         }
 
+        let depth_qty_p95 = if visible_depth_qtys.is_empty() {
+            max_depth_qty
+        } else {
+            let index = (visible_depth_qtys.len() - 1) * 95 / 100;
+            *visible_depth_qtys.select_nth_unstable_by(index, f32::total_cmp).1
+        };
+
         //log::info!("Heatmap scales calculation time: {:?}us", start.elapsed().as_micros());
 
         (
@@ -211,7 +482,8 @@ impl HeatmapChart {
                 max_trade_qty,
                 min_trade_qty,
                 max_aggr_volume,
-                max_depth_qty
+                max_depth_qty,
+                depth_qty_p95,
             }
         )
     }
@@ -348,6 +620,7 @@ impl HeatmapChart {
                 crosshair_position: chart_state.crosshair_position, 
                 crosshair: chart_state.crosshair,
                 timeframe: None,
+                open_times: None,
             })
             .width(Length::FillPortion(10))
             .height(Length::Fixed(26.0));
@@ -409,6 +682,63 @@ impl HeatmapChart {
     }
 }
 
+/// How many of the oldest points to move on, about `chunk` of them but ending on a whole bucket of `interval`
+fn expired_count(points: &[HeatmapDataPoint], chunk: usize, interval: i64) -> usize {
+    let Some((cut_time, _)) = points.get(chunk) else {
+        return points.len().min(chunk);
+    };
+    let cut = cut_time - cut_time.rem_euclid(interval);
+
+    match points.partition_point(|(time, _)| *time < cut) {
+        0 => chunk,
+        count => count,
+    }
+}
+
+/// One point per bucket of `interval`, the depth of each level averaged over the bucket's points
+/// and its trades summed up by price and side
+fn downsample(points: &[HeatmapDataPoint], interval: i64) -> Vec<HeatmapDataPoint> {
+    let bucket_start = |time: i64| time - time.rem_euclid(interval);
+
+    points
+        .chunk_by(|(a, _), (b, _)| bucket_start(*a) == bucket_start(*b))
+        .map(|bucket| {
+            let count = bucket.len() as f32;
+
+            let average = |orders: &mut dyn Iterator<Item = &Order>| -> Box<[Order]> {
+                let mut levels: BTreeMap<Price, f32> = BTreeMap::new();
+
+                for order in orders {
+                    *levels.entry(order.price).or_default() += order.qty;
+                }
+
+                levels.into_iter()
+                    .map(|(price, qty)| Order { price, qty: qty / count })
+                    .collect()
+            };
+
+            let depth = GroupedDepth {
+                bids: average(&mut bucket.iter().flat_map(|(_, (depth, _))| depth.bids.iter())),
+                asks: average(&mut bucket.iter().flat_map(|(_, (depth, _))| depth.asks.iter())),
+            };
+
+            let mut trades: HashMap<(Price, bool), f32> = HashMap::new();
+
+            for (_, (_, bucket_trades)) in bucket {
+                for trade in bucket_trades.iter() {
+                    *trades.entry((trade.price, trade.is_sell)).or_default() += trade.qty;
+                }
+            }
+
+            let trades = trades.into_iter()
+                .map(|((price, is_sell), qty)| GroupedTrade { is_sell, price, qty })
+                .collect();
+
+            (bucket_start(bucket[0].0), (depth, trades))
+        })
+        .collect()
+}
+
 impl canvas::Program<Message> for HeatmapChart {
     type State = Interaction;
 
@@ -525,7 +855,6 @@ impl canvas::Program<Message> for HeatmapChart {
             let mut bar_height: f32 = 1.0;
 
             let max_aggr_volume = self.qty_scales.max_aggr_volume;
-            let max_depth_qty = self.qty_scales.max_depth_qty;
             let (min_trade_qty, max_trade_qty) = (self.qty_scales.min_trade_qty, self.qty_scales.max_trade_qty);
 
             // draw: current depth as bars on the right side
@@ -537,12 +866,12 @@ impl canvas::Program<Message> for HeatmapChart {
                 }
 
                 let latest_bids: Vec<(f32, f32)> = grouped_depth.bids.iter()
-                    .map(|order| (order.price, order.qty))
+                    .map(|order| (order.price.to_f32(), order.qty))
                     .filter(|&(price, _)| price >= lowest)
                     .collect();
 
                 let latest_asks: Vec<(f32, f32)> = grouped_depth.asks.iter()
-                    .map(|order| (order.price, order.qty))
+                    .map(|order| (order.price.to_f32(), order.qty))
                     .filter(|&(price, _)| price <= highest)
                     .collect();
 
@@ -556,7 +885,7 @@ impl canvas::Program<Message> for HeatmapChart {
                     .unwrap_or(lowest); 
                 let lowest_bid_y_pos = heatmap_area_height - ((lowest_bid_visible - lowest) / y_range * heatmap_area_height);
                 
-                bar_height = (((lowest_bid_y_pos - highest_ask_y_pos) / (y_range / self.tick_size) as f32).floor()).max(1.0);
+                bar_height = (((lowest_bid_y_pos - highest_ask_y_pos) / (y_range / self.tick_size.to_f32())).floor()).max(1.0);
 
                 let mut max_qty = latest_bids.iter()
                     .map(|(_, qty)| qty)
@@ -651,39 +980,41 @@ impl canvas::Program<Message> for HeatmapChart {
                 }
 
                 for order in depth.bids.iter() {
-                    if order.price >= lowest {
+                    let price = order.price.to_f32();
+
+                    if price >= lowest {
                         if let (Some(prev_price), Some(prev_qty), Some(prev_x)) = (prev_bid_price, prev_bid_qty, prev_x_position) {
-                            let y_position = heatmap_area_height - ((order.price - lowest) / y_range * heatmap_area_height);
-                            let color_alpha = (order.qty / max_depth_qty).min(1.0);
+                            let y_position = heatmap_area_height - ((price - lowest) / y_range * heatmap_area_height);
 
-                            if prev_price != order.price || prev_qty != order.qty {
+                            if prev_price != price || prev_qty != order.qty {
                                 frame.fill_rectangle(
                                     Point::new(prev_x, y_position - (bar_height/2.0)),
                                     Size::new(x_position - prev_x, bar_height),
-                                    Color::from_rgba8(0, 144, 144, color_alpha)
+                                    self.depth_color(order.qty, true)
                                 );
                             }
                         }
-                        prev_bid_price = Some(order.price);
+                        prev_bid_price = Some(price);
                         prev_bid_qty = Some(order.qty);
                     }
                 }
 
                 for order in depth.asks.iter() {
-                    if order.price <= highest {
+                    let price = order.price.to_f32();
+
+                    if price <= highest {
                         if let (Some(prev_price), Some(prev_qty), Some(prev_x)) = (prev_ask_price, prev_ask_qty, prev_x_position) {
-                            let y_position = heatmap_area_height - ((order.price - lowest) / y_range * heatmap_area_height);
-                            let color_alpha = (order.qty / max_depth_qty).min(1.0);
+                            let y_position = heatmap_area_height - ((price - lowest) / y_range * heatmap_area_height);
 
-                            if prev_price != order.price || prev_qty != order.qty {
+                            if prev_price != price || prev_qty != order.qty {
                                 frame.fill_rectangle(
                                     Point::new(prev_x, y_position - (bar_height/2.0)), 
                                     Size::new(x_position - prev_x, bar_height), 
-                                    Color::from_rgba8(192, 0, 192, color_alpha)
+                                    self.depth_color(order.qty, false)
                                 );
                             }
                         }
-                        prev_ask_price = Some(order.price);
+                        prev_ask_price = Some(price);
                         prev_ask_qty = Some(order.qty);
                     }
                 }
@@ -700,13 +1031,15 @@ impl canvas::Program<Message> for HeatmapChart {
                         buy_volume += trade.qty;
                     }
 
-                    if trade.price < lowest || trade.price > highest {
+                    let price = trade.price.to_f32();
+
+                    if price < lowest || price > highest {
                         continue;
                     }
 
-                    if trade.qty *  trade.price > self.size_filter {
-                        let x_position = (((time - 100) - earliest) as f32 / (latest - earliest) as f32) * bounds.width;
-                        let y_position = heatmap_area_height - ((trade.price - lowest) / y_range * heatmap_area_height);
+                    if trade.qty * price > self.size_filter {
+                        let x_position = (((time - self.aggregation.0 as i64) - earliest) as f32 / (latest - earliest) as f32) * bounds.width;
+                        let y_position = heatmap_area_height - ((price - lowest) / y_range * heatmap_area_height);
 
                         let color = if trade.is_sell {
                             Color::from_rgba8(192, 80, 77, 1.0)
@@ -743,6 +1076,32 @@ impl canvas::Program<Message> for HeatmapChart {
                 }
             };
 
+            // draw: liquidations on top of the trades
+            let visible_liquidations: Vec<&Liquidation> = self.liquidations.iter()
+                .filter(|liquidation| liquidation.time >= earliest && liquidation.time <= latest)
+                .filter(|liquidation| {
+                    let price = liquidation.price.to_f32();
+                    price >= lowest && price <= highest
+                })
+                .collect();
+
+            let max_liquidation_qty = visible_liquidations.iter()
+                .map(|liquidation| liquidation.qty)
+                .fold(0.0, f32::max);
+
+            for liquidation in visible_liquidations {
+                let x_position = ((liquidation.time - earliest) as f32 / (latest - earliest) as f32) * bounds.width;
+                let y_position = heatmap_area_height - ((liquidation.price.to_f32() - lowest) / y_range * heatmap_area_height);
+
+                draw_liquidation_marker(
+                    frame,
+                    Point::new(x_position, y_position),
+                    liquidation.qty,
+                    max_liquidation_qty,
+                    liquidation.is_sell,
+                );
+            }
+
             //log::info!("Heatmap draw time: {:?}us", start.elapsed().as_micros());
         });
 
@@ -797,4 +1156,76 @@ impl canvas::Program<Message> for HeatmapChart {
             Interaction::None => { mouse::Interaction::default() }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(time: i64, bids: &[(&str, f32)], trades: &[(&str, bool, f32)]) -> HeatmapDataPoint {
+        let depth = GroupedDepth {
+            bids: bids.iter().map(|(price, qty)| Order { price: price.parse().unwrap(), qty: *qty }).collect(),
+            asks: Box::new([]),
+        };
+        let trades = trades.iter()
+            .map(|(price, is_sell, qty)| GroupedTrade { is_sell: *is_sell, price: price.parse().unwrap(), qty: *qty })
+            .collect();
+
+        (time, (depth, trades))
+    }
+
+    fn times(points: &[HeatmapDataPoint]) -> Vec<i64> {
+        points.iter().map(|(time, _)| *time).collect()
+    }
+
+    #[test]
+    fn downsample_averages_depth_and_sums_trades() {
+        let points = vec![
+            point(0, &[("10", 2.0)], &[("10", false, 0.5)]),
+            point(100, &[("10", 4.0), ("9", 3.0)], &[("10", false, 1.0), ("10", true, 2.0)]),
+            // a level missing from a point counts as empty for it
+            point(500, &[], &[]),
+            point(1200, &[("10", 1.0)], &[]),
+        ];
+
+        let downsampled = downsample(&points, 1000);
+        assert_eq!(times(&downsampled), vec![0, 1000]);
+
+        let (_, (depth, trades)) = &downsampled[0];
+        let bids: Vec<(String, f32)> = depth.bids.iter().map(|order| (order.price.to_string(), order.qty)).collect();
+        assert_eq!(bids, vec![("9".to_string(), 1.0), ("10".to_string(), 2.0)]);
+
+        let mut trades: Vec<(String, bool, f32)> = trades.iter().map(|trade| (trade.price.to_string(), trade.is_sell, trade.qty)).collect();
+        trades.sort_by_key(|(_, is_sell, _)| *is_sell);
+        assert_eq!(trades, vec![("10".to_string(), false, 1.5), ("10".to_string(), true, 2.0)]);
+
+        assert_eq!(downsampled[1].1.0.bids[0].qty, 1.0);
+    }
+
+    #[test]
+    fn expired_count_cuts_on_whole_buckets() {
+        let points: Vec<HeatmapDataPoint> = (0..7).map(|i| point(i * 400, &[], &[])).collect();
+
+        // the 5th point is at 1600, so only the ones before its bucket at 1000 go
+        assert_eq!(expired_count(&points, 4, 1000), 3);
+        assert_eq!(expired_count(&points[..3], 4, 1000), 3);
+
+        // no whole bucket ends before the chunk does, so the chunk goes as it is
+        assert_eq!(expired_count(&points[..2], 1, 1000), 1);
+    }
+
+    #[test]
+    fn roll_over_splits_a_bucket_when_the_chunk_fits_inside_it() {
+        let mut chart = HeatmapChart::new("1".parse().unwrap());
+        chart.history = vec![HistoryTier::new(1000, 6), HistoryTier::new(5000, 100)];
+
+        chart.roll_over(0, (0..7).map(|i| point(i * 1000, &[("10", 1.0)], &[])).collect());
+        assert_eq!(times(chart.history[0].points.make_contiguous()), vec![1000, 2000, 3000, 4000, 5000, 6000]);
+
+        chart.roll_over(0, vec![point(7000, &[("10", 1.0)], &[])]);
+
+        // a chunk of 1 falls inside the 5s bucket at 0 both times, so that bucket ends up split in two
+        assert_eq!(times(chart.history[0].points.make_contiguous()), vec![2000, 3000, 4000, 5000, 6000, 7000]);
+        assert_eq!(times(chart.history[1].points.make_contiguous()), vec![0, 0]);
+    }
+}
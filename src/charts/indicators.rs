@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use iced::{
+    mouse, widget::canvas::{self, stroke::Stroke, Canvas, Frame, Geometry, Path}, Color, Element, Length, Point, Rectangle, Renderer, Theme
+};
+use iced::widget::{Row, Space};
+use crate::data_providers::MarketStats;
+
+use super::Message;
+
+/// Mark price, funding and open interest of a perpetual, one sample per minute
+#[derive(Debug, Default)]
+pub struct MarketStatsHistory {
+    points: BTreeMap<i64, MarketStats>,
+}
+
+impl MarketStatsHistory {
+    const SAMPLE_INTERVAL: i64 = 60 * 1000;
+
+    /// About a week of samples
+    const MAX_POINTS: usize = 7 * 24 * 60;
+
+    /// Keeps the last update of each minute
+    pub fn insert(&mut self, stats: MarketStats) {
+        let sample_time = (stats.time / Self::SAMPLE_INTERVAL) * Self::SAMPLE_INTERVAL;
+
+        self.points.insert(sample_time, stats);
+
+        while self.points.len() > Self::MAX_POINTS {
+            self.points.pop_first();
+        }
+    }
+
+    pub fn latest(&self) -> Option<&MarketStats> {
+        self.points.values().next_back()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+}
+
+const OPEN_INTEREST_COLOR: Color = Color::from_rgb(0.4, 0.6, 0.9);
+const FUNDING_COLOR: Color = Color::from_rgb(0.9, 0.75, 0.3);
+
+/// Open interest and funding rate lines under a chart, each scaled to its own visible range.
+/// `min` and `max` are the chart's visible time range, so both line up on the same x axis
+pub struct MarketStatsCanvas<'a> {
+    pub history: &'a MarketStatsHistory,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl canvas::Program<Message> for MarketStatsCanvas<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+
+        let (earliest, latest) = (self.min, self.max);
+
+        if latest <= earliest {
+            return vec![frame.into_geometry()];
+        }
+
+        let visible: Vec<(&i64, &MarketStats)> = self.history.points.range(earliest..=latest).collect();
+
+        let x_position = |time: i64| ((time - earliest) as f64 / (latest - earliest) as f64) as f32 * bounds.width;
+
+        // leaves room for the labels on top
+        let top = 16.0;
+        let height = bounds.height - top - 2.0;
+
+        let mut plot_line = |values: Vec<(i64, f32)>, color: Color| {
+            let (lowest, highest) = values.iter()
+                .fold((f32::MAX, f32::MIN), |(lowest, highest), (_, value)| (lowest.min(*value), highest.max(*value)));
+
+            // a flat line sits in the middle
+            let range = if highest > lowest { highest - lowest } else { 1.0 };
+            let offset = if highest > lowest { 0.0 } else { height / 2.0 };
+
+            let line = Path::new(|builder| {
+                for (i, (time, value)) in values.iter().enumerate() {
+                    let point = Point::new(
+                        x_position(*time),
+                        top + height - ((value - lowest) / range * height) - offset,
+                    );
+
+                    if i == 0 {
+                        builder.move_to(point);
+                    } else {
+                        builder.line_to(point);
+                    }
+                }
+            });
+
+            frame.stroke(&line, Stroke::default().with_color(color).with_width(1.0));
+        };
+
+        let open_interest: Vec<(i64, f32)> = visible.iter()
+            .filter_map(|(time, stats)| stats.open_interest.map(|value| (**time, value)))
+            .collect();
+        if !open_interest.is_empty() {
+            plot_line(open_interest, OPEN_INTEREST_COLOR);
+        }
+
+        let funding: Vec<(i64, f32)> = visible.iter()
+            .map(|(time, stats)| (**time, stats.funding_rate))
+            .collect();
+        if !funding.is_empty() {
+            plot_line(funding, FUNDING_COLOR);
+        }
+
+        if let Some((_, stats)) = visible.last() {
+            let labels = [
+                (
+                    stats.open_interest.map_or("OI -".to_string(), |value| format!("OI {}", format_large_number(value))),
+                    OPEN_INTEREST_COLOR,
+                ),
+                (
+                    format!("Funding {:.4}%", stats.funding_rate * 100.0),
+                    FUNDING_COLOR,
+                ),
+            ];
+
+            let mut x = 4.0;
+            for (content, color) in labels {
+                let width = content.len() as f32 * 6.5;
+
+                frame.fill_text(canvas::Text {
+                    content,
+                    position: Point::new(x, 2.0),
+                    size: iced::Pixels(11.0),
+                    color,
+                    ..canvas::Text::default()
+                });
+
+                x += width + 12.0;
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+/// Shortens large amounts, e.g. 85123.4 to 85.12K
+pub fn format_large_number(value: f32) -> String {
+    let abs = value.abs();
+
+    if abs >= 1_000_000_000.0 {
+        format!("{:.2}B", value / 1_000_000_000.0)
+    } else if abs >= 1_000_000.0 {
+        format!("{:.2}M", value / 1_000_000.0)
+    } else if abs >= 1_000.0 {
+        format!("{:.2}K", value / 1_000.0)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+/// The market stats canvas, padded on the right to line up with a chart above its y axis labels
+pub fn view_market_stats(history: &MarketStatsHistory, min: i64, max: i64) -> Element<'_, Message> {
+    Row::new()
+        .push(
+            Canvas::new(MarketStatsCanvas { history, min, max })
+                .width(Length::FillPortion(10))
+                .height(Length::Fixed(80.0))
+        )
+        .push(Space::with_width(Length::Fixed(60.0)))
+        .into()
+}
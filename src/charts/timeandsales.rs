@@ -4,13 +4,14 @@ use iced::{
 };
 use iced::widget::{Column, Row, Container, Text, container, Space};
 use crate::screen::dashboard::pane::Message;
-use crate::{style, data_providers::Trade};
+use crate::{style, data_providers::{Liquidation, Price, Trade}};
 
 struct ConvertedTrade {
     time: NaiveDateTime,
-    price: f32,
+    price: Price,
     qty: f32,
     is_sell: bool,
+    is_liquidation: bool,
 }
 pub struct TimeAndSales {
     recent_trades: Vec<ConvertedTrade>,
@@ -48,10 +49,32 @@ impl TimeAndSales {
                 price: trade.price,
                 qty: trade.qty,
                 is_sell: trade.is_sell,
+                is_liquidation: false,
             };
             self.recent_trades.push(converted_trade);
         }
 
+        self.trim_trades();
+    }
+
+    /// Liquidations are listed among the trades, highlighted and never filtered out by size
+    pub fn insert_liquidations(&mut self, liquidations: &[Liquidation]) {
+        for liquidation in liquidations {
+            let liquidation_time = NaiveDateTime::from_timestamp(liquidation.time / 1000, (liquidation.time % 1000) as u32 * 1_000_000);
+            let converted_trade = ConvertedTrade {
+                time: liquidation_time,
+                price: liquidation.price,
+                qty: liquidation.qty,
+                is_sell: liquidation.is_sell,
+                is_liquidation: true,
+            };
+            self.recent_trades.push(converted_trade);
+        }
+
+        self.trim_trades();
+    }
+
+    fn trim_trades(&mut self) {
         if self.recent_trades.len() > 2000 {
             let drain_to = self.recent_trades.len() - 2000;
             self.recent_trades.drain(0..drain_to);
@@ -62,9 +85,11 @@ impl TimeAndSales {
             .height(Length::Fill)
             .padding(10);
 
-        let filtered_trades: Vec<_> = self.recent_trades.iter().filter(|trade| (trade.qty*trade.price) >= self.size_filter).collect();
+        let filtered_trades: Vec<_> = self.recent_trades.iter()
+            .filter(|trade| trade.is_liquidation || (trade.qty * trade.price.to_f32()) >= self.size_filter)
+            .collect();
 
-        let max_qty = filtered_trades.iter().map(|trade| trade.qty).fold(0.0, f32::max);
+        let max_qty = filtered_trades.iter().filter(|trade| !trade.is_liquidation).map(|trade| trade.qty).fold(0.0, f32::max);
     
         if filtered_trades.is_empty() {
             trades_column = trades_column.push(
@@ -77,6 +102,13 @@ impl TimeAndSales {
             for trade in filtered_trades.iter().rev().take(80) {
                 let trade: &ConvertedTrade = trade;
 
+                let side_label = match (trade.is_liquidation, trade.is_sell) {
+                    (true, true) => "Long liq.",
+                    (true, false) => "Short liq.",
+                    (false, true) => "Sell",
+                    (false, false) => "Buy",
+                };
+
                 let trade_row = Row::new()
                     .push(
                         container(Text::new(format!("{}", trade.time.format("%M:%S.%3f"))).size(14))
@@ -87,7 +119,7 @@ impl TimeAndSales {
                             .width(Length::FillPortion(6))
                     )
                     .push(
-                        container(Text::new(if trade.is_sell { "Sell" } else { "Buy" }).size(14))
+                        container(Text::new(side_label).size(14))
                             .width(Length::FillPortion(4)).align_x(alignment::Horizontal::Left)
                     )
                     .push(
@@ -98,7 +130,13 @@ impl TimeAndSales {
                 let color_alpha = trade.qty / max_qty;
     
                 trades_column = trades_column.push(container(trade_row)
-                    .style( move |_| if trade.is_sell { style::sell_side_red(color_alpha) } else { style::buy_side_green(color_alpha) }));
+                    .style( move |_| if trade.is_liquidation {
+                        style::liquidation_row(trade.is_sell)
+                    } else if trade.is_sell {
+                        style::sell_side_red(color_alpha)
+                    } else {
+                        style::buy_side_green(color_alpha)
+                    }));
     
                 trades_column = trades_column.push(Container::new(Space::new(Length::Fixed(0.0), Length::Fixed(5.0))));
             }
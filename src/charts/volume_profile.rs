@@ -0,0 +1,338 @@
+//! Volume traded at each price over a range of bars, drawn along the right edge of
+//! candlestick and footprint charts
+
+use std::collections::BTreeMap;
+
+use iced::{widget::canvas::{self, stroke::{LineDash, Stroke}, Frame, Path}, Color, Point, Size};
+use serde::{Deserialize, Serialize};
+
+use crate::data_providers::{Kline, Price, Rounding};
+
+/// Which bars a chart's volume profile is built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum ProfileMode {
+    #[default]
+    Off,
+    /// Bars in view, following along as the chart is panned or zoomed
+    VisibleRange,
+    /// Bars that were in view when the mode was picked
+    FixedRange,
+    /// Bars since the UTC midnight before the latest one
+    Session,
+}
+
+impl ProfileMode {
+    pub const ALL: [ProfileMode; 4] = [ProfileMode::Off, ProfileMode::VisibleRange, ProfileMode::FixedRange, ProfileMode::Session];
+}
+
+impl std::fmt::Display for ProfileMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileMode::Off => write!(f, "Off"),
+            ProfileMode::VisibleRange => write!(f, "Visible range"),
+            ProfileMode::FixedRange => write!(f, "Fixed range"),
+            ProfileMode::Session => write!(f, "Session"),
+        }
+    }
+}
+
+const DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// The profile's mode along with the bars a fixed range was pinned to, kept in the pane settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct ProfileRange {
+    pub mode: ProfileMode,
+    pub fixed: Option<(i64, i64)>,
+}
+
+impl ProfileRange {
+    /// Switches to `mode`, pinning the fixed range to the bars between `earliest` and `latest`
+    pub fn with_mode(mode: ProfileMode, earliest: i64, latest: i64) -> Self {
+        ProfileRange {
+            mode,
+            fixed: (mode == ProfileMode::FixedRange && earliest < latest).then_some((earliest, latest)),
+        }
+    }
+
+    /// Keys of the first and last bar to build the profile from, None when it's off.
+    /// A fixed range that couldn't be pinned, as the chart had no bars yet, follows the view instead
+    pub fn bars(&self, earliest: i64, latest: i64, session_start: impl FnOnce() -> Option<i64>) -> Option<(i64, i64)> {
+        let range = match self.mode {
+            ProfileMode::Off => return None,
+            ProfileMode::VisibleRange => (earliest, latest),
+            ProfileMode::FixedRange => self.fixed.unwrap_or((earliest, latest)),
+            ProfileMode::Session => (session_start()?, i64::MAX),
+        };
+
+        Some(range).filter(|(from, to)| from <= to)
+    }
+}
+
+/// Key of the first bar opened since the UTC midnight before the latest bar.
+/// Bars built from trades are keyed by slot, `open_times` has the time each of them opened
+pub fn session_start(latest: i64, open_times: Option<&BTreeMap<i64, i64>>) -> Option<i64> {
+    match open_times {
+        None => Some(latest - latest.rem_euclid(DAY)),
+        Some(open_times) => {
+            let (_, latest_time) = open_times.iter().next_back()?;
+            let midnight = latest_time - latest_time.rem_euclid(DAY);
+
+            open_times.iter()
+                .find(|(_, time)| **time >= midnight)
+                .map(|(slot, _)| *slot)
+        },
+    }
+}
+
+/// Share of the volume around the point of control that makes up the value area
+const VALUE_AREA_SHARE: f32 = 0.7;
+
+/// Rows across the visible price range at most, levels are merged into rows of several ticks beyond that
+const MAX_ROWS: f32 = 200.0;
+
+/// Share of the chart's width taken up by the level with the most volume
+const MAX_WIDTH_SHARE: f32 = 0.25;
+
+const BUY_COLOR: Color = Color::from_rgb(0.32, 0.8, 0.63);
+const SELL_COLOR: Color = Color::from_rgb(0.75, 0.31, 0.3);
+const UNSPLIT_COLOR: Color = Color::from_rgb(0.6, 0.6, 0.6);
+const POC_COLOR: Color = Color::from_rgb(1.0, 0.78, 0.3);
+const VALUE_AREA_COLOR: Color = Color::from_rgb(0.6, 0.6, 0.8);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Level {
+    buy: f32,
+    sell: f32,
+    /// From klines that don't tell taker buys and sells apart
+    unsplit: f32,
+}
+
+impl Level {
+    fn total(&self) -> f32 {
+        self.buy + self.sell + self.unsplit
+    }
+}
+
+/// Volume at each row of price levels, keyed by the index of the row's lowest tick divided by `row_ticks`
+#[derive(Debug)]
+pub struct VolumeProfile {
+    tick_size: Price,
+    row_ticks: i64,
+    levels: BTreeMap<i64, Level>,
+}
+
+impl VolumeProfile {
+    /// Rows of as many ticks as it takes to fit `price_range` in `MAX_ROWS`
+    pub fn new(tick_size: Price, price_range: f32) -> Self {
+        let row_ticks = ((price_range / tick_size.to_f32() / MAX_ROWS).ceil() as i64).max(1);
+
+        VolumeProfile {
+            tick_size,
+            row_ticks,
+            levels: BTreeMap::new(),
+        }
+    }
+
+    /// Adds volume traded at the level with the given tick index, like footprint rows are keyed by
+    pub fn add(&mut self, level: i64, buy_qty: f32, sell_qty: f32) {
+        let entry = self.levels.entry(level.div_euclid(self.row_ticks)).or_default();
+
+        entry.buy += buy_qty;
+        entry.sell += sell_qty;
+    }
+
+    /// Spreads the kline's volume evenly over the rows between its low and high,
+    /// for charts that have no trades to go by
+    pub fn add_kline(&mut self, kline: &Kline) {
+        let is_split = kline.volume.0 != -1.0;
+
+        // the sell side is the total minus taker buys, rounding can push it just under zero
+        let (buy_qty, sell_qty) = if is_split {
+            (kline.volume.0.max(0.0), kline.volume.1.max(0.0))
+        } else {
+            (0.0, kline.volume.1.max(0.0))
+        };
+
+        if kline.high <= Price::default() || buy_qty + sell_qty <= 0.0 {
+            return;
+        }
+
+        let low = kline.low.tick_index(self.tick_size, Rounding::Nearest).div_euclid(self.row_ticks);
+        let high = kline.high.tick_index(self.tick_size, Rounding::Nearest).div_euclid(self.row_ticks);
+
+        let share = 1.0 / (high - low + 1) as f32;
+
+        for row in low..=high {
+            let entry = self.levels.entry(row).or_default();
+
+            if is_split {
+                entry.buy += buy_qty * share;
+                entry.sell += sell_qty * share;
+            } else {
+                entry.unsplit += sell_qty * share;
+            }
+        }
+    }
+
+    /// The point of control, then the lowest and highest level of the value area.
+    /// The area grows from the point of control towards whichever neighbouring level traded more
+    fn value_area(&self) -> Option<(i64, i64, i64)> {
+        let levels: Vec<(i64, f32)> = self.levels.iter()
+            .map(|(level, volume)| (*level, volume.total()))
+            .collect();
+
+        let (poc_index, _) = levels.iter()
+            .enumerate()
+            .max_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b))?;
+
+        let target = levels.iter().map(|(_, total)| total).sum::<f32>() * VALUE_AREA_SHARE;
+
+        let (mut low, mut high) = (poc_index, poc_index);
+        let mut area_volume = levels[poc_index].1;
+
+        while area_volume < target && (low > 0 || high + 1 < levels.len()) {
+            let below = if low > 0 { levels[low - 1].1 } else { -1.0 };
+            let above = if high + 1 < levels.len() { levels[high + 1].1 } else { -1.0 };
+
+            if above >= below {
+                high += 1;
+                area_volume += above;
+            } else {
+                low -= 1;
+                area_volume += below;
+            }
+        }
+
+        Some((levels[poc_index].0, levels[low].0, levels[high].0))
+    }
+
+    /// Histogram against the right edge, sells outermost, with the point of control
+    /// and the value area bounds marked across the chart
+    pub fn draw(&self, frame: &mut Frame, width: f32, area_height: f32, lowest: f32, highest: f32) {
+        let y_range = highest - lowest;
+
+        let Some((poc, value_low, value_high)) = self.value_area() else {
+            return;
+        };
+
+        let max_total = self.levels.values().map(Level::total).fold(0.0, f32::max);
+
+        if y_range <= 0.0 || max_total <= 0.0 {
+            return;
+        }
+
+        let tick_size = self.tick_size.to_f32();
+        let row_size = tick_size * self.row_ticks as f32;
+
+        // middle of the row's ticks
+        let y_position = |row: i64| {
+            let price = Price::from_tick_index(row * self.row_ticks, self.tick_size).to_f32()
+                + (self.row_ticks - 1) as f32 * tick_size / 2.0;
+
+            area_height - ((price - lowest) / y_range * area_height)
+        };
+
+        let max_width = width * MAX_WIDTH_SHARE;
+        let bar_height = (area_height / (y_range / row_size)).max(1.0);
+
+        for (level, volume) in &self.levels {
+            let y = y_position(*level);
+
+            if y < -bar_height || y > area_height + bar_height {
+                continue;
+            }
+
+            let alpha = if (value_low..=value_high).contains(level) { 0.5 } else { 0.2 };
+
+            let mut x = width;
+
+            for (qty, color) in [(volume.sell, SELL_COLOR), (volume.buy, BUY_COLOR), (volume.unsplit, UNSPLIT_COLOR)] {
+                if qty <= 0.0 {
+                    continue;
+                }
+
+                let bar_width = qty / max_total * max_width;
+                x -= bar_width;
+
+                frame.fill_rectangle(
+                    Point::new(x, y - bar_height / 2.0),
+                    Size::new(bar_width, bar_height),
+                    Color { a: alpha, ..color },
+                );
+            }
+        }
+
+        for (level, label, color, dashed) in [
+            (poc, "POC", POC_COLOR, false),
+            (value_high, "VAH", VALUE_AREA_COLOR, true),
+            (value_low, "VAL", VALUE_AREA_COLOR, true),
+        ] {
+            let y = y_position(level);
+
+            let mut stroke = Stroke::default().with_color(Color { a: 0.6, ..color }).with_width(1.0);
+
+            if dashed {
+                stroke.line_dash = LineDash { segments: &[4.0, 4.0], offset: 0 };
+            }
+
+            frame.stroke(&Path::line(Point::new(0.0, y), Point::new(width, y)), stroke);
+
+            frame.fill_text(canvas::Text {
+                content: label.to_string(),
+                position: Point::new(width - max_width - 24.0, y - 12.0),
+                size: iced::Pixels(9.0),
+                color,
+                ..canvas::Text::default()
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(s: &str) -> Price {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn value_area_grows_from_poc_towards_heavier_side() {
+        let mut profile = VolumeProfile::new(price("1"), 10.0);
+
+        for (level, qty) in [(10, 1.0), (11, 5.0), (12, 10.0), (13, 4.0), (14, 3.0), (15, 1.0)] {
+            profile.add(level, qty / 2.0, qty / 2.0);
+        }
+
+        // 70% of 24 is 16.8: 10 at the POC, then 5 below it, then 4 above it
+        assert_eq!(profile.value_area(), Some((12, 11, 13)));
+    }
+
+    #[test]
+    fn merges_ticks_into_rows() {
+        // 250 / 0.5 is 500 ticks, 3 to a row keeps it under 200 rows
+        let mut profile = VolumeProfile::new(price("0.5"), 250.0);
+        assert_eq!(profile.row_ticks, 3);
+
+        profile.add(0, 1.0, 0.0);
+        profile.add(2, 0.0, 2.0);
+        profile.add(3, 4.0, 0.0);
+        profile.add(-1, 8.0, 0.0);
+
+        let totals: Vec<(i64, f32)> = profile.levels.iter().map(|(row, level)| (*row, level.total())).collect();
+        assert_eq!(totals, vec![(-1, 8.0), (0, 3.0), (1, 4.0)]);
+
+        // from tick 2 to tick 5, so split over rows 0 and 1
+        profile.add_kline(&Kline {
+            time: 0,
+            open: price("1"),
+            high: price("2.5"),
+            low: price("1"),
+            close: price("2.5"),
+            volume: (-1.0, 6.0),
+        });
+
+        assert_eq!(profile.levels[&0].unsplit, 3.0);
+        assert_eq!(profile.levels[&1].unsplit, 3.0);
+    }
+}
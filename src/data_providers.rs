@@ -1,7 +1,23 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+
+use fastwebsockets::{Frame, FragmentCollector, OpCode};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
 use serde::{Deserialize, Serialize};
 
 pub mod binance;
 pub mod bybit;
+pub mod okx;
+pub mod aggregated;
+pub mod clock;
+pub mod connection;
+pub mod throughput;
+
+#[cfg(test)]
+pub mod mock;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum StreamType {
@@ -13,104 +29,509 @@ pub enum StreamType {
     DepthAndTrades {
         exchange: Exchange,
         ticker: Ticker,
+        /// None for the exchange's default, so panes on other resolutions get a subscription of their own
+        #[serde(default)]
+        resolution: Option<DepthResolution>,
+    },
+    /// Mark price, funding and open interest, only perpetual markets have one
+    MarketStats {
+        exchange: Exchange,
+        ticker: Ticker,
+    },
+    /// Forced orders of liquidated positions, only perpetual markets have them
+    Liquidations {
+        exchange: Exchange,
+        ticker: Ticker,
     },
     None,
 }
 
+/// How detailed a depth stream is, each exchange offers its own set, see `DepthResolution::options`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum DepthResolution {
+    /// Diffs pushed on this interval, in milliseconds
+    UpdateSpeed(u16),
+    /// Levels on each side of the book
+    Levels(u16),
+}
+
+impl DepthResolution {
+    /// What the exchange's depth stream can be subscribed at, the first one being its default
+    pub fn options(exchange: Exchange) -> &'static [DepthResolution] {
+        match exchange {
+            // the merged book follows Binance's update speed, Bybit streams at its default
+            Exchange::BinanceFutures | Exchange::AggregatedLinear => &[
+                DepthResolution::UpdateSpeed(100), DepthResolution::UpdateSpeed(250), DepthResolution::UpdateSpeed(500),
+            ],
+            Exchange::BinanceSpot => &[DepthResolution::UpdateSpeed(100), DepthResolution::UpdateSpeed(1000)],
+            Exchange::BybitLinear | Exchange::BybitInverse => &[
+                DepthResolution::Levels(500), DepthResolution::Levels(200), DepthResolution::Levels(50),
+            ],
+            Exchange::BybitSpot => &[DepthResolution::Levels(200), DepthResolution::Levels(50)],
+            Exchange::OkxSwap => &[DepthResolution::Levels(400)],
+        }
+    }
+
+    pub fn default_for(exchange: Exchange) -> Self {
+        Self::options(exchange)[0]
+    }
+
+    /// The resolution a depth stream's `resolution` stands for
+    pub fn resolve(resolution: Option<Self>, exchange: Exchange) -> Self {
+        resolution.unwrap_or_else(|| Self::default_for(exchange))
+    }
+}
+
+impl std::fmt::Display for DepthResolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DepthResolution::UpdateSpeed(ms) => write!(f, "{ms}ms"),
+            DepthResolution::Levels(levels) => write!(f, "{levels} levels"),
+        }
+    }
+}
+
 // data types
+
+/// How a price that falls between two ticks gets assigned to one of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+    Nearest,
+}
+
+/// Fixed-point price with 8 decimals, parsed straight from the exchange's string
+/// so nothing is lost before it gets bucketed to a tick size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Price(i64);
+
+impl Price {
+    const DECIMALS: usize = 8;
+    const SCALE: i64 = 100_000_000;
+
+    /// For tick sizes and other values that only exist as floats, rounded to the nearest unit
+    pub fn from_f32(value: f32) -> Self {
+        Self((value as f64 * Self::SCALE as f64).round() as i64)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        (self.0 as f64 / Self::SCALE as f64) as f32
+    }
+
+    /// Raw count of 1e-8 units, for storing the price as is
+    pub fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn from_units(units: i64) -> Self {
+        Self(units)
+    }
+
+    /// Index of the tick this price falls on, counted from zero
+    pub fn tick_index(self, tick_size: Price, rounding: Rounding) -> i64 {
+        let tick = tick_size.0.max(1);
+
+        match rounding {
+            Rounding::Floor => self.0.div_euclid(tick),
+            Rounding::Ceil => -(-self.0).div_euclid(tick),
+            Rounding::Nearest => (self.0 + tick / 2).div_euclid(tick),
+        }
+    }
+
+    pub fn from_tick_index(index: i64, tick_size: Price) -> Self {
+        Self(index * tick_size.0.max(1))
+    }
+
+    pub fn round_to_tick(self, tick_size: Price, rounding: Rounding) -> Self {
+        Self::from_tick_index(self.tick_index(tick_size, rounding), tick_size)
+    }
+}
+
+impl std::str::FromStr for Price {
+    type Err = String;
+
+    /// Plain decimal notation as exchanges send it, digits past the 8th decimal are dropped
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+
+        if (int_part.is_empty() && frac_part.is_empty())
+            || !int_part.bytes().chain(frac_part.bytes()).all(|b| b.is_ascii_digit()) 
+        {
+            return Err(format!("invalid price: {s}"));
+        }
+
+        let int: i64 = if int_part.is_empty() {
+            0
+        } else {
+            int_part.parse().map_err(|e| format!("invalid price: {s}, {e}"))?
+        };
+
+        let frac = (0..Self::DECIMALS).fold(0i64, |frac, i| {
+            frac * 10 + frac_part.as_bytes().get(i).map_or(0, |b| i64::from(b - b'0'))
+        });
+
+        let units = int.checked_mul(Self::SCALE)
+            .and_then(|units| units.checked_add(frac))
+            .ok_or_else(|| format!("price out of range: {s}"))?;
+
+        Ok(Self(if negative { -units } else { units }))
+    }
+}
+
+impl std::fmt::Display for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let (int, frac) = (units / Self::SCALE as u64, units % Self::SCALE as u64);
+
+        if frac == 0 {
+            write!(f, "{sign}{int}")
+        } else {
+            let frac = format!("{frac:0width$}", width = Self::DECIMALS);
+            write!(f, "{sign}{int}.{}", frac.trim_end_matches('0'))
+        }
+    }
+}
+
+/// Written as a decimal string, so it reads back exactly
+impl Serialize for Price {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// From a decimal string, or a plain number as tick sizes were saved before they went fixed-point
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct PriceVisitor;
+
+        impl serde::de::Visitor<'_> for PriceVisitor {
+            type Value = Price;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a price as a decimal string or number")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Price, E> {
+                s.parse().map_err(E::custom)
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Price, E> {
+                Ok(Price((value * Price::SCALE as f64).round() as i64))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Price, E> {
+                value.checked_mul(Price::SCALE).map(Price).ok_or_else(|| E::custom("price out of range"))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Price, E> {
+                i64::try_from(value).map_err(E::custom).and_then(|value| self.visit_i64(value))
+            }
+        }
+
+        deserializer.deserialize_any(PriceVisitor)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Order {
-    pub price: f32,
+    pub price: Price,
     pub qty: f32,
 }
+
+/// Snapshot of an orderbook, cheap to clone as the levels are shared with the `LocalDepthCache` it came from
 #[derive(Debug, Clone, Default)]
 pub struct Depth {
     pub time: i64,
-    pub bids: Vec<Order>,
-    pub asks: Vec<Order>,
+    bids: std::sync::Arc<BTreeMap<Price, f32>>,
+    asks: std::sync::Arc<BTreeMap<Price, f32>>,
+}
+
+impl Depth {
+    /// Bid levels from the lowest price up
+    pub fn bids(&self) -> impl DoubleEndedIterator<Item = Order> + '_ {
+        self.bids.iter().map(|(price, qty)| Order { price: *price, qty: *qty })
+    }
+
+    /// Ask levels from the lowest price up
+    pub fn asks(&self) -> impl DoubleEndedIterator<Item = Order> + '_ {
+        self.asks.iter().map(|(price, qty)| Order { price: *price, qty: *qty })
+    }
+
+    pub fn best_bid(&self) -> Option<Order> {
+        self.bids().next_back()
+    }
+
+    pub fn best_ask(&self) -> Option<Order> {
+        self.asks().next()
+    }
 }
 
+/// Walks two versions of a book side in price order, keeping new or resized levels and zeroing removed ones
+pub fn level_changes(prev: impl Iterator<Item = Order>, next: impl Iterator<Item = Order>) -> Vec<Order> {
+    let (mut prev, mut next) = (prev.peekable(), next.peekable());
+    let mut changes = Vec::new();
+
+    loop {
+        match (prev.peek().copied(), next.peek().copied()) {
+            (Some(old), Some(new)) if old.price == new.price => {
+                if old.qty != new.qty {
+                    changes.push(new);
+                }
+                prev.next();
+                next.next();
+            }
+            (Some(old), Some(new)) if old.price < new.price => {
+                changes.push(Order { price: old.price, qty: 0.0 });
+                prev.next();
+            }
+            (_, Some(new)) => {
+                changes.push(new);
+                next.next();
+            }
+            (Some(old), None) => {
+                changes.push(Order { price: old.price, qty: 0.0 });
+                prev.next();
+            }
+            (None, None) => break,
+        }
+    }
+
+    changes
+}
+
+/// Several venues' books summed level by level on a common tick size, bids rounded down and asks up
+/// so a level from a book with a finer tick never moves across the spread. Kept up to date one venue
+/// at a time by summing again only the levels its update touched
+#[derive(Debug, Default)]
+pub struct ConsolidatedDepth {
+    tick_size: Price,
+    books: HashMap<Exchange, Depth>,
+    merged: Depth,
+}
+
+impl ConsolidatedDepth {
+    pub fn new(tick_size: Price) -> Self {
+        Self {
+            tick_size,
+            ..Self::default()
+        }
+    }
+
+    /// Takes the venue's latest book and returns the merged one
+    pub fn update(&mut self, venue: Exchange, book: Depth) -> Depth {
+        let old = self.books.insert(venue, book.clone()).unwrap_or_default();
+        self.apply_changes(&old, &book);
+
+        self.merged.clone()
+    }
+
+    /// Drops the venue's levels, e.g. when its book isn't live anymore
+    pub fn remove(&mut self, venue: Exchange) {
+        if let Some(old) = self.books.remove(&venue) {
+            self.apply_changes(&old, &Depth::default());
+        }
+    }
+
+    fn apply_changes(&mut self, old: &Depth, new: &Depth) {
+        let tick = self.tick_size;
+        let tick_units = tick.units().max(1);
+
+        self.merged.time = self.books.values().map(|book| book.time).max().unwrap_or(0);
+
+        // bids are rounded down onto [level, level + tick), asks up onto (level - tick, level]
+        if !std::sync::Arc::ptr_eq(&old.bids, &new.bids) {
+            let levels = level_changes(old.bids(), new.bids())
+                .into_iter()
+                .map(|order| order.price.round_to_tick(tick, Rounding::Floor));
+
+            Self::resum(&mut self.merged.bids, &self.books, levels, |book| &book.bids, |level| {
+                (std::ops::Bound::Included(level), std::ops::Bound::Excluded(Price::from_units(level.units() + tick_units)))
+            });
+        }
+        if !std::sync::Arc::ptr_eq(&old.asks, &new.asks) {
+            let levels = level_changes(old.asks(), new.asks())
+                .into_iter()
+                .map(|order| order.price.round_to_tick(tick, Rounding::Ceil));
+
+            Self::resum(&mut self.merged.asks, &self.books, levels, |book| &book.asks, |level| {
+                (std::ops::Bound::Excluded(Price::from_units(level.units() - tick_units)), std::ops::Bound::Included(level))
+            });
+        }
+    }
+
+    /// Sums every venue's levels that round onto each of `levels` again, the merged side
+    /// only gets copied if the last book handed out still shares it
+    fn resum(
+        merged: &mut std::sync::Arc<BTreeMap<Price, f32>>,
+        books: &HashMap<Exchange, Depth>,
+        levels: impl Iterator<Item = Price>,
+        side: impl Fn(&Depth) -> &BTreeMap<Price, f32>,
+        bounds: impl Fn(Price) -> (std::ops::Bound<Price>, std::ops::Bound<Price>),
+    ) {
+        let merged = std::sync::Arc::make_mut(merged);
+        let mut last_level = None;
+
+        for level in levels {
+            // changes come in price order, so do the levels they round onto
+            if last_level == Some(level) {
+                continue;
+            }
+            last_level = Some(level);
+
+            let qty: f32 = books.values()
+                .flat_map(|book| side(book).range(bounds(level)).map(|(_, qty)| qty))
+                .sum();
+
+            if qty > 0.0 {
+                merged.insert(level, qty);
+            } else {
+                merged.remove(&level);
+            }
+        }
+    }
+}
+
+/// Levels as they come off the wire, either a full snapshot or a diff where a zero qty removes the level
 #[derive(Debug, Clone, Default)]
-pub struct LocalDepthCache {
+pub struct DepthUpdate {
     pub last_update_id: i64,
     pub time: i64,
     pub bids: Vec<Order>,
     pub asks: Vec<Order>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct LocalDepthCache {
+    pub last_update_id: i64,
+    depth: Depth,
+}
+
 impl LocalDepthCache {
     pub fn new() -> Self {
-        Self {
-            last_update_id: 0,
-            time: 0,
-            bids: Vec::new(),
-            asks: Vec::new(),
-        }
+        Self::default()
     }
 
-    pub fn fetched(&mut self, new_depth: LocalDepthCache) {
+    pub fn fetched(&mut self, new_depth: DepthUpdate) {
         self.last_update_id = new_depth.last_update_id;        
-        self.time = new_depth.time;
+        self.depth.time = new_depth.time;
 
-        self.bids = new_depth.bids;
-        self.asks = new_depth.asks;
+        self.depth.bids = std::sync::Arc::new(Self::levels(&new_depth.bids));
+        self.depth.asks = std::sync::Arc::new(Self::levels(&new_depth.asks));
     }
 
-    pub fn update_depth_cache(&mut self, new_depth: LocalDepthCache) {
+    pub fn update_depth_cache(&mut self, new_depth: DepthUpdate) {
         self.last_update_id = new_depth.last_update_id;
-        self.time = new_depth.time;
+        self.depth.time = new_depth.time;
 
-        for order in new_depth.bids.iter() {
-            if order.qty == 0.0 {
-                self.bids.retain(|x| x.price != order.price);
-            } else if let Some(existing_order) = self.bids.iter_mut().find(|x| x.price == order.price) {
-                existing_order.qty = order.qty;
-            } else {
-                self.bids.push(*order);
-            }
-        }
-        for order in new_depth.asks.iter() {
+        // copies the levels only if the charts still hold on to the previous snapshot
+        Self::apply(std::sync::Arc::make_mut(&mut self.depth.bids), &new_depth.bids);
+        Self::apply(std::sync::Arc::make_mut(&mut self.depth.asks), &new_depth.asks);
+    }
+
+    fn levels(orders: &[Order]) -> BTreeMap<Price, f32> {
+        orders.iter()
+            .filter(|order| order.qty > 0.0)
+            .map(|order| (order.price, order.qty))
+            .collect()
+    }
+
+    fn apply(levels: &mut BTreeMap<Price, f32>, orders: &[Order]) {
+        for order in orders {
             if order.qty == 0.0 {
-                self.asks.retain(|x| x.price != order.price);
-            } else if let Some(existing_order) = self.asks.iter_mut().find(|x| x.price == order.price) {
-                existing_order.qty = order.qty;
+                levels.remove(&order.price);
             } else {
-                self.asks.push(*order);
+                levels.insert(order.price, order.qty);
             }
         }
     }
 
     pub fn get_depth(&self) -> Depth {
-        Depth {
-            time: self.time,
-            bids: self.bids.clone(),
-            asks: self.asks.clone(),
-        }
+        self.depth.clone()
     }
 
     pub fn get_fetch_id(&self) -> i64 {
         self.last_update_id
     }
+
+    pub fn best_bid(&self) -> Option<Order> {
+        self.depth.best_bid()
+    }
+
+    pub fn best_ask(&self) -> Option<Order> {
+        self.depth.best_ask()
+    }
+
+    /// Best bid at or above the best ask, which only happens after a missed or misapplied update
+    pub fn is_crossed(&self) -> bool {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => bid.price >= ask.price,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Trade {
     pub time: i64,
     pub is_sell: bool,
-    pub price: f32,
+    pub price: Price,
     pub qty: f32,
 }
 
+/// A page of historical trades, `progress` being the share of the requested time range fetched so far
+#[derive(Debug, Clone)]
+pub struct TradesBatch {
+    pub trades: Vec<Trade>,
+    pub progress: f32,
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Kline {
     pub time: u64,
-    pub open: f32,
-    pub high: f32,
-    pub low: f32,
-    pub close: f32,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
     pub volume: (f32, f32),
 }
 
+/// A position closed by the exchange's liquidation engine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Liquidation {
+    pub time: i64,
+    pub price: Price,
+    /// In the base currency, like trades
+    pub qty: f32,
+    /// Side of the forced order, so a liquidated long is a sell
+    pub is_sell: bool,
+}
+
+/// State of a perpetual market that isn't in its book or trades
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct MarketStats {
+    pub time: i64,
+    pub mark_price: f32,
+    pub index_price: f32,
+    /// Rate paid at `next_funding_time`, 0.0001 being 0.01%
+    pub funding_rate: f32,
+    pub next_funding_time: i64,
+    /// In contracts or base currency, whichever the exchange reports it in.
+    /// `None` until the first value arrives, some exchanges only serve it on REST
+    pub open_interest: Option<f32>,
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct FeedLatency {
     pub time: i64,
@@ -129,8 +550,8 @@ impl std::fmt::Display for TickMultiplier {
 }
 
 impl TickMultiplier {
-    pub fn multiply_with_min_tick_size(&self, min_tick_size: f32) -> f32 {
-        self.0 as f32 * min_tick_size
+    pub fn multiply_with_min_tick_size(&self, min_tick_size: Price) -> Price {
+        Price::from_units(min_tick_size.units() * i64::from(self.0))
     }
 }
 
@@ -138,7 +559,13 @@ impl TickMultiplier {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum Exchange {
     BinanceFutures,
+    BinanceSpot,
     BybitLinear,
+    BybitInverse,
+    BybitSpot,
+    OkxSwap,
+    /// Binance futures and Bybit linear perps of the same symbol merged into one market
+    AggregatedLinear,
 }
 
 impl std::fmt::Display for Exchange {
@@ -148,50 +575,210 @@ impl std::fmt::Display for Exchange {
             "{}",
             match self {
                 Exchange::BinanceFutures => "Binance Futures",
+                Exchange::BinanceSpot => "Binance Spot",
                 Exchange::BybitLinear => "Bybit Linear",
+                Exchange::BybitInverse => "Bybit Inverse",
+                Exchange::BybitSpot => "Bybit Spot",
+                Exchange::OkxSwap => "OKX Swap",
+                Exchange::AggregatedLinear => "Binance + Bybit Linear",
             }
         )
     }
 }
 impl Exchange {
-    pub const ALL: [Exchange; 2] = [Exchange::BinanceFutures, Exchange::BybitLinear];
+    pub const ALL: [Exchange; 7] = [
+        Exchange::BinanceFutures,
+        Exchange::BinanceSpot,
+        Exchange::BybitLinear,
+        Exchange::BybitInverse,
+        Exchange::BybitSpot,
+        Exchange::OkxSwap,
+        Exchange::AggregatedLinear,
+    ];
+
+    pub fn market_type(&self) -> MarketType {
+        match self {
+            Exchange::BinanceSpot | Exchange::BybitSpot => MarketType::Spot,
+            Exchange::BinanceFutures | Exchange::BybitLinear | Exchange::OkxSwap | Exchange::AggregatedLinear => MarketType::LinearPerps,
+            Exchange::BybitInverse => MarketType::InversePerps,
+        }
+    }
+
+    /// Where the provider connects to, the exchange's own hosts unless overridden with `override_endpoints`
+    pub fn base_urls(&self) -> BaseUrls {
+        ENDPOINTS.read()
+            .ok()
+            .and_then(|endpoints| endpoints.as_ref()?.exchanges.get(self).cloned())
+            .unwrap_or_else(|| self.default_base_urls())
+    }
+
+    /// How long its connections may go without any frame, pongs included, before they're taken for half-open
+    pub fn stale_feed_timeout(&self) -> std::time::Duration {
+        let secs = ENDPOINTS.read()
+            .ok()
+            .and_then(|endpoints| endpoints.as_ref()?.stale_feed_timeouts.get(self).copied())
+            .unwrap_or(DEFAULT_STALE_FEED_TIMEOUT_SECS);
+
+        std::time::Duration::from_secs(secs)
+    }
+
+    fn default_base_urls(&self) -> BaseUrls {
+        let (ws, rest) = match self {
+            Exchange::BinanceFutures => ("wss://fstream.binance.com", "https://fapi.binance.com"),
+            Exchange::BinanceSpot => ("wss://stream.binance.com", "https://api.binance.com"),
+            Exchange::BybitLinear | Exchange::BybitInverse | Exchange::BybitSpot => ("wss://stream.bybit.com", "https://api.bybit.com"),
+            Exchange::OkxSwap => ("wss://ws.okx.com:8443", "https://www.okx.com"),
+            // has no API of its own, it connects through the venues it merges
+            Exchange::AggregatedLinear => return aggregated::VENUES[0].default_base_urls(),
+        };
+
+        BaseUrls { ws: ws.to_string(), rest: rest.to_string() }
+    }
 }
 
-impl std::fmt::Display for Ticker {
+/// Scheme, host and optional port of an exchange's websocket and REST APIs, e.g. `wss://fstream.binance.com`.
+/// Plain `ws://` and `http://` work too, for pointing a provider at a local mock server
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BaseUrls {
+    pub ws: String,
+    pub rest: String,
+}
+
+/// Base URL and connection overrides, read from the saved state
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Endpoints {
+    #[serde(default)]
+    pub exchanges: HashMap<Exchange, BaseUrls>,
+    /// REST API the Binance order and account requests go to, the futures testnet by default
+    #[serde(default)]
+    pub binance_testnet: Option<String>,
+    /// Seconds without a frame before an exchange's connection is dropped, for venues that are slow to pong
+    #[serde(default)]
+    pub stale_feed_timeouts: HashMap<Exchange, u64>,
+}
+
+/// Every provider pings every 20s, which leaves a pong 10s to come back
+const DEFAULT_STALE_FEED_TIMEOUT_SECS: u64 = 30;
+
+static ENDPOINTS: RwLock<Option<Endpoints>> = RwLock::new(None);
+
+/// Merges into the overrides already set, so each exchange can be pointed elsewhere on its own
+pub fn override_endpoints(overrides: Endpoints) {
+    if let Ok(mut endpoints) = ENDPOINTS.write() {
+        let endpoints = endpoints.get_or_insert_with(Endpoints::default);
+
+        endpoints.exchanges.extend(overrides.exchanges);
+        endpoints.stale_feed_timeouts.extend(overrides.stale_feed_timeouts);
+
+        if overrides.binance_testnet.is_some() {
+            endpoints.binance_testnet = overrides.binance_testnet;
+        }
+    }
+}
+
+pub fn binance_testnet_url() -> String {
+    ENDPOINTS.read()
+        .ok()
+        .and_then(|endpoints| endpoints.as_ref()?.binance_testnet.clone())
+        .unwrap_or_else(|| "https://testnet.binancefuture.com".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum MarketType {
+    Spot,
+    LinearPerps,
+    InversePerps,
+}
+
+impl std::fmt::Display for MarketType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{}",
             match self {
-                Ticker::BTCUSDT => "BTCUSDT",
-                Ticker::ETHUSDT => "ETHUSDT",
-                Ticker::SOLUSDT => "SOLUSDT",
-                Ticker::LTCUSDT => "LTCUSDT",
+                MarketType::Spot => "Spot",
+                MarketType::LinearPerps => "Linear",
+                MarketType::InversePerps => "Inverse",
             }
         )
     }
 }
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
-pub enum Ticker {
-    BTCUSDT,
-    ETHUSDT,
-    SOLUSDT,
-    LTCUSDT,
-}
-impl Ticker {
-    pub const ALL: [Ticker; 4] = [Ticker::BTCUSDT, Ticker::ETHUSDT, Ticker::SOLUSDT, Ticker::LTCUSDT];
+
+/// Exchange symbol of an instrument, e.g. BTCUSDT
+///
+/// Stored inline as uppercase ascii so it stays `Copy` like the rest of the stream identifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ticker {
+    data: [u8; Ticker::MAX_LEN],
+    len: u8,
 }
 
 impl Ticker {
+    pub const MAX_LEN: usize = 24;
+
+    /// Creates a ticker from the given symbol, stored in uppercase
+    ///
+    /// Panics if the symbol isn't valid, see `Ticker::is_valid_symbol`
+    pub fn new(symbol: &str) -> Self {
+        assert!(Self::is_valid_symbol(symbol), "Invalid ticker symbol: {symbol}");
+
+        let mut data = [0u8; Self::MAX_LEN];
+
+        for (i, byte) in symbol.bytes().enumerate() {
+            data[i] = byte.to_ascii_uppercase();
+        }
+
+        Self {
+            data,
+            len: symbol.len() as u8,
+        }
+    }
+
+    pub fn is_valid_symbol(symbol: &str) -> bool {
+        !symbol.is_empty() && symbol.len() <= Self::MAX_LEN && symbol.is_ascii()
+    }
+
+    /// Returns the symbol in uppercase
+    /// 
+    /// e.g. BTCUSDT -> "BTCUSDT"
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.data[..self.len as usize]).unwrap_or_default()
+    }
+
     /// Returns the string representation of the ticker in lowercase
     /// 
     /// e.g. BTCUSDT -> "btcusdt"
     pub fn get_string(&self) -> String {
-        match self {
-            Ticker::BTCUSDT => "btcusdt".to_string(),
-            Ticker::ETHUSDT => "ethusdt".to_string(),
-            Ticker::SOLUSDT => "solusdt".to_string(),
-            Ticker::LTCUSDT => "ltcusdt".to_string(),
+        self.as_str().to_lowercase()
+    }
+}
+
+impl std::fmt::Display for Ticker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for Ticker {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Ticker {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let symbol = String::deserialize(deserializer)?;
+
+        if Ticker::is_valid_symbol(&symbol) {
+            Ok(Ticker::new(&symbol))
+        } else {
+            Err(serde::de::Error::custom(format!("Invalid ticker symbol: {symbol}")))
         }
     }
 }
@@ -207,6 +794,9 @@ impl std::fmt::Display for Timeframe {
                 Timeframe::M5 => "5m",
                 Timeframe::M15 => "15m",
                 Timeframe::M30 => "30m",
+                Timeframe::H1 => "1h",
+                Timeframe::H4 => "4h",
+                Timeframe::D1 => "1d",
             }
         )
     }
@@ -218,9 +808,14 @@ pub enum Timeframe {
     M5,
     M15,
     M30,
+    H1,
+    H4,
+    D1,
 }
 impl Timeframe {
-    pub const ALL: [Timeframe; 5] = [Timeframe::M1, Timeframe::M3, Timeframe::M5, Timeframe::M15, Timeframe::M30];
+    pub const ALL: [Timeframe; 8] = [
+        Timeframe::M1, Timeframe::M3, Timeframe::M5, Timeframe::M15, Timeframe::M30, Timeframe::H1, Timeframe::H4, Timeframe::D1
+    ];
 
     pub fn to_minutes(&self) -> u16 {
         match self {
@@ -229,29 +824,63 @@ impl Timeframe {
             Timeframe::M5 => 5,
             Timeframe::M15 => 15,
             Timeframe::M30 => 30,
+            Timeframe::H1 => 60,
+            Timeframe::H4 => 240,
+            Timeframe::D1 => 1440,
         }
     }
 }
 
-#[derive(Debug)]
-pub enum BinanceWsState {
-    Connected(binance::market_data::Connection),
-    Disconnected,
+/// What a single candlestick or footprint bar spans. Time bars come from the exchange's klines,
+/// the rest are built locally out of the trade stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ChartBasis {
+    Time(Timeframe),
+    /// Number of trades
+    Tick(u32),
+    /// Traded notional, in the quote currency
+    Volume(u32),
+    /// High to low span, in basis points of the bar's open
+    Range(u16),
 }
-impl Default for BinanceWsState {
-    fn default() -> Self {
-        Self::Disconnected
+
+impl std::fmt::Display for ChartBasis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChartBasis::Time(timeframe) => write!(f, "{timeframe}"),
+            ChartBasis::Tick(count) => write!(f, "{count}T"),
+            ChartBasis::Volume(notional) if notional % 1_000_000 == 0 => write!(f, "{}M vol", notional / 1_000_000),
+            ChartBasis::Volume(notional) => write!(f, "{}K vol", notional / 1_000),
+            ChartBasis::Range(bps) => write!(f, "{bps}bp range"),
+        }
     }
 }
 
-#[derive(Debug)]
-pub enum BybitWsState {
-    Connected(bybit::market_data::Connection),
-    Disconnected,
-}
-impl Default for BybitWsState {
-    fn default() -> Self {
-        Self::Disconnected
+impl ChartBasis {
+    pub const ALL: [ChartBasis; 16] = [
+        ChartBasis::Time(Timeframe::M1),
+        ChartBasis::Time(Timeframe::M3),
+        ChartBasis::Time(Timeframe::M5),
+        ChartBasis::Time(Timeframe::M15),
+        ChartBasis::Time(Timeframe::M30),
+        ChartBasis::Time(Timeframe::H1),
+        ChartBasis::Time(Timeframe::H4),
+        ChartBasis::Time(Timeframe::D1),
+        ChartBasis::Tick(100),
+        ChartBasis::Tick(500),
+        ChartBasis::Tick(1000),
+        ChartBasis::Volume(500_000),
+        ChartBasis::Volume(1_000_000),
+        ChartBasis::Volume(10_000_000),
+        ChartBasis::Range(10),
+        ChartBasis::Range(25),
+    ];
+
+    pub fn timeframe(&self) -> Option<Timeframe> {
+        match self {
+            ChartBasis::Time(timeframe) => Some(*timeframe),
+            ChartBasis::Tick(_) | ChartBasis::Volume(_) | ChartBasis::Range(_) => None,
+        }
     }
 }
 
@@ -265,10 +894,235 @@ impl Default for UserWsState {
     }
 }
 
+/// Health of a websocket connection, shown on every pane subscribed to one of its streams
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Live,
+    /// Connected, but the local orderbook is being rebuilt from a fresh snapshot
+    Resyncing,
+    /// Waiting to reconnect, with the reason it went down
+    Down(String),
+}
+
+/// Jittered exponential backoff between reconnect attempts
+#[derive(Default)]
+pub struct Backoff {
+    /// Reconnects since the last connection that stayed up for `HEALTHY_UPTIME`
+    attempt: u32,
+    started: bool,
+    connected_at: Option<tokio::time::Instant>,
+}
+
+impl Backoff {
+    const BASE: std::time::Duration = std::time::Duration::from_millis(500);
+    const MAX: std::time::Duration = std::time::Duration::from_secs(30);
+    /// A connection that drops sooner than this, e.g. accepted then closed right away,
+    /// keeps backing off instead of starting over from `BASE`
+    const HEALTHY_UPTIME: std::time::Duration = std::time::Duration::from_secs(10);
+
+    /// Called once connected, starts the uptime that decides whether the next drop starts over
+    pub fn connected(&mut self) {
+        self.connected_at = Some(tokio::time::Instant::now());
+    }
+
+    /// Upper bound of the next sleep, the actual one is jittered over its upper half
+    fn delay(&self) -> std::time::Duration {
+        Self::BASE
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(Self::MAX)
+    }
+
+    /// Sleeps before a reconnect attempt, only the very first connect goes out right away
+    pub async fn wait(&mut self) {
+        if !self.started {
+            self.started = true;
+            return;
+        }
+
+        if self.connected_at.take().is_some_and(|at| at.elapsed() >= Self::HEALTHY_UPTIME) {
+            self.attempt = 0;
+        }
+
+        let delay = self.delay();
+
+        // full jitter over the upper half, so streams dropped together don't reconnect together
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let jitter = delay.mul_f64(0.5 * (nanos as f64 / 1_000_000_000.0));
+
+        tokio::time::sleep(delay / 2 + jitter).await;
+
+        self.attempt = self.attempt.saturating_add(1);
+    }
+}
+
+/// Keeps a websocket read loop honest: pings on a fixed interval regardless of traffic,
+/// and declares the feed stale once nothing has arrived within `stale_after`
+pub struct Heartbeat {
+    ping_interval: std::time::Duration,
+    stale_after: std::time::Duration,
+    /// The exchange's ping, a ping frame or a text message depending on the venue
+    ping: fn() -> Frame<'static>,
+    last_ping: tokio::time::Instant,
+    last_frame: tokio::time::Instant,
+}
+
+impl Heartbeat {
+    pub fn new(ping_interval: std::time::Duration, stale_after: std::time::Duration, ping: fn() -> Frame<'static>) -> Self {
+        let now = tokio::time::Instant::now();
+
+        Self {
+            ping_interval,
+            stale_after,
+            ping,
+            last_ping: now,
+            last_frame: now,
+        }
+    }
+
+    /// Called once connected, so the new connection gets a full window
+    pub fn reset(&mut self) {
+        let now = tokio::time::Instant::now();
+
+        self.last_ping = now;
+        self.last_frame = now;
+    }
+
+    /// Pings if one is due, then waits for the next frame until another ping is due, `None` if nothing came by then.
+    /// Errs with the reason the connection has to go when it's closed, can't be read or written, or went stale
+    pub async fn read_frame(&mut self, websocket: &mut FragmentCollector<TokioIo<Upgraded>>) -> Result<Option<Frame<'static>>, String> {
+        if self.ping_due() {
+            websocket.write_frame((self.ping)()).await
+                .map_err(|e| format!("Failed to send ping: {e}"))?;
+
+            self.pinged();
+        }
+
+        match tokio::time::timeout(self.read_timeout(), websocket.read_frame()).await {
+            Ok(Ok(frame)) => {
+                self.beat();
+
+                match frame.opcode {
+                    OpCode::Close => Err("Connection closed".to_string()),
+                    _ => Ok(Some(frame)),
+                }
+            }
+            Ok(Err(e)) => Err(format!("Error reading frame: {e}")),
+            Err(_) if self.is_stale() => Err(format!("No data for {}s", self.stale_after.as_secs())),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn beat(&mut self) {
+        self.last_frame = tokio::time::Instant::now();
+    }
+
+    fn ping_due(&self) -> bool {
+        self.last_ping.elapsed() >= self.ping_interval
+    }
+
+    fn pinged(&mut self) {
+        self.last_ping = tokio::time::Instant::now();
+    }
+
+    fn is_stale(&self) -> bool {
+        self.last_frame.elapsed() >= self.stale_after
+    }
+
+    /// How long the next read may block before a ping is due or the feed goes stale
+    fn read_timeout(&self) -> std::time::Duration {
+        let until_ping = self.ping_interval.saturating_sub(self.last_ping.elapsed());
+        let until_stale = self.stale_after.saturating_sub(self.last_frame.elapsed());
+
+        until_ping.min(until_stale)
+    }
+}
+
+/// Market data event shared by every exchange's streams
 #[derive(Debug, Clone)]
-pub enum MarketEvents {
-    Binance(binance::market_data::Event),
-    Bybit(bybit::market_data::Event),
+pub enum Event {
+    /// State of the websocket behind the given streams changed
+    Connection(Vec<StreamType>, ConnectionState),
+    DepthReceived(StreamType, FeedLatency, i64, Depth, Vec<Trade>),
+    KlineReceived(StreamType, Kline),
+    MarketStatsReceived(StreamType, MarketStats),
+    LiquidationsReceived(StreamType, Vec<Liquidation>),
+}
+
+/// Streams an exchange's connection should carry, it follows along while staying open
+pub type SubscribedStreams = tokio::sync::watch::Receiver<HashSet<StreamType>>;
+
+/// One long-lived connection per exchange, kept in step with the panes. Adding or removing a stream
+/// subscribes or unsubscribes on the open socket instead of reconnecting every stream on it
+pub struct StreamSubscriptions {
+    senders: HashMap<Exchange, tokio::sync::watch::Sender<HashSet<StreamType>>>,
+}
+
+impl StreamSubscriptions {
+    pub fn new() -> Self {
+        Self {
+            senders: Exchange::ALL.iter()
+                .map(|exchange| (*exchange, tokio::sync::watch::Sender::new(HashSet::new())))
+                .collect(),
+        }
+    }
+
+    /// Changes what the exchange's connection is subscribed to, connections only hear of actual changes
+    pub fn set(&self, exchange: Exchange, streams: HashSet<StreamType>) {
+        if let Some(sender) = self.senders.get(&exchange) {
+            sender.send_if_modified(|current| {
+                if *current == streams {
+                    return false;
+                }
+                *current = streams;
+                true
+            });
+        }
+    }
+
+    /// For handing to `MarketDataProvider::connect`
+    pub fn streams(&self, exchange: Exchange) -> SubscribedStreams {
+        match self.senders.get(&exchange) {
+            Some(sender) => sender.subscribe(),
+            None => tokio::sync::watch::Sender::new(HashSet::new()).subscribe(),
+        }
+    }
+}
+
+/// Everything the app needs from an exchange's market data, so adding a venue means
+/// implementing this trait and registering it in `provider`
+pub trait MarketDataProvider: Sync {
+    /// The ticker in the exchange's own symbol notation, e.g. `btcusdt` on Binance
+    fn normalize_symbol(&self, ticker: Ticker) -> String;
+
+    /// Every stream in `streams`, depth and trades, klines, market stats and liquidations alike,
+    /// over connections that stay open and subscribe or unsubscribe as it changes.
+    /// A book that goes out of sync gets resynced without dropping the other streams on its socket
+    fn connect(&self, streams: SubscribedStreams) -> BoxStream<'static, Event>;
+
+    /// Latest page of klines, or the page closing at `end_time` in milliseconds to scroll back in history
+    fn fetch_klines(&self, ticker: Ticker, timeframe: Timeframe, end_time: Option<i64>) -> BoxFuture<'static, Result<Vec<Kline>, String>>;
+
+    fn fetch_ticksize(&self, ticker: Ticker) -> BoxFuture<'static, Result<Price, String>>;
+
+    /// Historical trades between `from_time` and `to_time` in milliseconds, oldest first, one page at a time
+    fn fetch_trades(&self, ticker: Ticker, from_time: i64, to_time: i64) -> BoxStream<'static, Result<TradesBatch, String>>;
+
+    fn fetch_ticker_list(&self) -> BoxFuture<'static, Result<Vec<Ticker>, String>>;
+}
+
+pub fn provider(exchange: Exchange) -> &'static dyn MarketDataProvider {
+    match exchange {
+        Exchange::BinanceFutures => &binance::Provider(Exchange::BinanceFutures),
+        Exchange::BinanceSpot => &binance::Provider(Exchange::BinanceSpot),
+        Exchange::BybitLinear => &bybit::Provider(Exchange::BybitLinear),
+        Exchange::BybitInverse => &bybit::Provider(Exchange::BybitInverse),
+        Exchange::BybitSpot => &bybit::Provider(Exchange::BybitSpot),
+        Exchange::OkxSwap => &okx::Provider,
+        Exchange::AggregatedLinear => &aggregated::Provider,
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -281,4 +1135,175 @@ pub enum StreamError {
     WebsocketError(String),
     #[error("UnknownError: {0}")]
     UnknownError(String),
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::default();
+
+        let delays: Vec<u128> = (0..8)
+            .map(|attempt| {
+                backoff.attempt = attempt;
+                backoff.delay().as_millis()
+            })
+            .collect();
+
+        assert_eq!(delays, vec![500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]);
+    }
+
+    fn ping() -> Frame<'static> {
+        Frame::text(fastwebsockets::Payload::Borrowed(b"ping"))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_pings_on_schedule_and_goes_stale_without_frames() {
+        let secs = std::time::Duration::from_secs;
+        let mut heartbeat = Heartbeat::new(secs(20), secs(30), ping);
+
+        assert_eq!(heartbeat.read_timeout(), secs(20));
+        assert!(!heartbeat.ping_due());
+
+        tokio::time::advance(secs(20)).await;
+        assert!(heartbeat.ping_due());
+        heartbeat.pinged();
+        // the ping went out but nothing came back yet, the next read only waits until the feed goes stale
+        assert_eq!(heartbeat.read_timeout(), secs(10));
+
+        tokio::time::advance(secs(5)).await;
+        heartbeat.beat();
+        assert_eq!(heartbeat.read_timeout(), secs(15));
+
+        tokio::time::advance(secs(15)).await;
+        assert!(heartbeat.ping_due());
+        heartbeat.pinged();
+
+        tokio::time::advance(secs(14)).await;
+        assert!(!heartbeat.is_stale());
+
+        tokio::time::advance(secs(1)).await;
+        assert!(heartbeat.is_stale());
+        assert_eq!(heartbeat.read_timeout(), std::time::Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeat_reset_gives_a_new_connection_a_full_window() {
+        let secs = std::time::Duration::from_secs;
+        let mut heartbeat = Heartbeat::new(secs(20), secs(30), ping);
+
+        tokio::time::advance(secs(45)).await;
+        assert!(heartbeat.is_stale() && heartbeat.ping_due());
+
+        heartbeat.reset();
+        assert!(!heartbeat.is_stale() && !heartbeat.ping_due());
+        assert_eq!(heartbeat.read_timeout(), secs(20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff_starts_over_only_after_a_healthy_connection() {
+        async fn timed(backoff: &mut Backoff) -> std::time::Duration {
+            let start = tokio::time::Instant::now();
+            backoff.wait().await;
+            start.elapsed()
+        }
+
+        let mut backoff = Backoff::default();
+
+        assert_eq!(timed(&mut backoff).await, std::time::Duration::ZERO);
+
+        // accepted and dropped right away, every retry still waits and the wait keeps growing
+        for ceiling in [500, 1000, 2000] {
+            backoff.connected();
+
+            let slept = timed(&mut backoff).await;
+            assert!(slept >= std::time::Duration::from_millis(ceiling / 2), "{slept:?}");
+            assert!(slept <= std::time::Duration::from_millis(ceiling), "{slept:?}");
+        }
+
+        backoff.connected();
+        tokio::time::advance(Backoff::HEALTHY_UPTIME).await;
+
+        let slept = timed(&mut backoff).await;
+        assert!(slept >= Backoff::BASE / 2 && slept <= Backoff::BASE, "{slept:?}");
+    }
+
+    #[test]
+    fn parses_prices_exactly() {
+        let price: Price = "123456.78".parse().unwrap();
+        assert_eq!(price.to_string(), "123456.78");
+
+        assert_eq!("0.00001234".parse::<Price>().unwrap().to_string(), "0.00001234");
+        assert_eq!("-5".parse::<Price>().unwrap().to_string(), "-5");
+        assert!("1.2.3".parse::<Price>().is_err());
+    }
+
+    #[test]
+    fn buckets_prices_to_ticks() {
+        let tick_size = Price::from_f32(0.1);
+        let price: Price = "123456.75".parse().unwrap();
+
+        assert_eq!(price.round_to_tick(tick_size, Rounding::Floor).to_string(), "123456.7");
+        assert_eq!(price.round_to_tick(tick_size, Rounding::Ceil).to_string(), "123456.8");
+        assert_eq!(price.round_to_tick(tick_size, Rounding::Nearest).to_string(), "123456.8");
+
+        let on_tick: Price = "123456.7".parse().unwrap();
+        assert_eq!(on_tick.round_to_tick(tick_size, Rounding::Ceil), on_tick);
+    }
+
+    #[test]
+    fn reads_tick_sizes_saved_as_numbers() {
+        let settings: Option<Price> = serde_json::from_str("0.1").unwrap();
+        assert_eq!(settings, Some("0.1".parse().unwrap()));
+
+        let tick_size: Price = "0.00001".parse().unwrap();
+        let saved = serde_json::to_string(&tick_size).unwrap();
+
+        assert_eq!(saved, "\"0.00001\"");
+        assert_eq!(serde_json::from_str::<Price>(&saved).unwrap(), tick_size);
+        assert_eq!(TickMultiplier(5).multiply_with_min_tick_size(tick_size).to_string(), "0.00005");
+    }
+
+    #[test]
+    fn consolidates_books_onto_coarser_tick() {
+        let book = |time, bids: &[(&str, f32)], asks: &[(&str, f32)]| {
+            let mut cache = LocalDepthCache::new();
+            let orders = |levels: &[(&str, f32)]| levels.iter()
+                .map(|(price, qty)| Order { price: price.parse().unwrap(), qty: *qty })
+                .collect();
+
+            cache.fetched(DepthUpdate { last_update_id: 0, time, bids: orders(bids), asks: orders(asks) });
+            cache.get_depth()
+        };
+
+        let coarse = book(100, &[("100.0", 1.0), ("99.5", 2.0)], &[("100.5", 1.0)]);
+        let fine = book(105, &[("100.2", 3.0)], &[("100.3", 4.0), ("100.6", 1.0)]);
+
+        let mut consolidated = ConsolidatedDepth::new(Price::from_f32(0.5));
+        consolidated.update(Exchange::BinanceFutures, coarse);
+        let merged = consolidated.update(Exchange::BybitLinear, fine);
+
+        assert_eq!(merged.time, 105);
+
+        let levels = |orders: Vec<Order>| orders.iter().map(|order| (order.price.to_string(), order.qty)).collect::<Vec<_>>();
+
+        assert_eq!(levels(merged.bids().collect()), [("99.5".to_string(), 2.0), ("100".to_string(), 4.0)]);
+        assert_eq!(levels(merged.asks().collect()), [("100.5".to_string(), 5.0), ("101".to_string(), 1.0)]);
+
+        // only the levels the update touched get summed again, the book handed out before stays as it was
+        let fine = book(110, &[("100.2", 1.0)], &[("100.6", 1.0)]);
+        let updated = consolidated.update(Exchange::BybitLinear, fine);
+
+        assert_eq!(levels(updated.bids().collect()), [("99.5".to_string(), 2.0), ("100".to_string(), 2.0)]);
+        assert_eq!(levels(updated.asks().collect()), [("100.5".to_string(), 1.0), ("101".to_string(), 1.0)]);
+        assert_eq!(levels(merged.asks().collect()), [("100.5".to_string(), 5.0), ("101".to_string(), 1.0)]);
+
+        consolidated.remove(Exchange::BybitLinear);
+        let coarse_only = consolidated.update(Exchange::BinanceFutures, book(120, &[("100.0", 1.0)], &[("100.5", 1.0)]));
+
+        assert_eq!(levels(coarse_only.bids().collect()), [("100".to_string(), 1.0)]);
+        assert_eq!(levels(coarse_only.asks().collect()), [("100.5".to_string(), 1.0)]);
+    }
+}
@@ -0,0 +1,342 @@
+//! Binance and Bybit linear perpetuals of the same symbol merged into one market, so a single
+//! heatmap or footprint shows the consolidated liquidity and trade flow of both venues
+
+use std::collections::{HashMap, HashSet};
+
+use futures::{future::BoxFuture, stream::{BoxStream, FuturesUnordered}, FutureExt, StreamExt, TryStreamExt};
+use futures::sink::SinkExt;
+use iced::stream;
+use tokio::sync::watch;
+
+use super::{
+    provider, ConnectionState, ConsolidatedDepth, Event, Exchange, Kline, MarketDataProvider, Price, StreamType, SubscribedStreams, Ticker, Timeframe, TradesBatch,
+};
+
+/// Venues merged into `Exchange::AggregatedLinear`, both list their linear perps as e.g. `BTCUSDT`
+pub const VENUES: [Exchange; 2] = [Exchange::BinanceFutures, Exchange::BybitLinear];
+
+/// Candles and market stats can't be merged tick by tick, they come from this venue alone
+const REFERENCE_VENUE: Exchange = Exchange::BinanceFutures;
+
+pub struct Provider;
+
+impl MarketDataProvider for Provider {
+    fn normalize_symbol(&self, ticker: Ticker) -> String {
+        provider(REFERENCE_VENUE).normalize_symbol(ticker)
+    }
+
+    fn connect(&self, streams: SubscribedStreams) -> BoxStream<'static, Event> {
+        connect(streams).boxed()
+    }
+
+    fn fetch_klines(&self, ticker: Ticker, timeframe: Timeframe, end_time: Option<i64>) -> BoxFuture<'static, Result<Vec<Kline>, String>> {
+        provider(REFERENCE_VENUE).fetch_klines(ticker, timeframe, end_time)
+    }
+
+    /// The coarsest of the venues' tick sizes, the merged book can't be any finer than that
+    fn fetch_ticksize(&self, ticker: Ticker) -> BoxFuture<'static, Result<Price, String>> {
+        let tick_sizes = VENUES.map(|venue| provider(venue).fetch_ticksize(ticker));
+
+        async move {
+            let tick_sizes = futures::future::try_join_all(tick_sizes).await?;
+
+            Ok(tick_sizes.into_iter().max().unwrap_or_default())
+        }
+        .boxed()
+    }
+
+    /// One venue's history after the other, each taking an equal share of the progress
+    fn fetch_trades(&self, ticker: Ticker, from_time: i64, to_time: i64) -> BoxStream<'static, Result<TradesBatch, String>> {
+        let venue_count = VENUES.len() as f32;
+
+        futures::stream::iter(VENUES.into_iter().enumerate())
+            .flat_map(move |(i, venue)| {
+                provider(venue).fetch_trades(ticker, from_time, to_time)
+                    .map_ok(move |batch| TradesBatch {
+                        trades: batch.trades,
+                        progress: (i as f32 + batch.progress) / venue_count,
+                    })
+            })
+            .boxed()
+    }
+
+    /// Symbols listed on every venue
+    fn fetch_ticker_list(&self) -> BoxFuture<'static, Result<Vec<Ticker>, String>> {
+        let ticker_lists = VENUES.map(|venue| provider(venue).fetch_ticker_list());
+
+        async move {
+            let mut ticker_lists = futures::future::try_join_all(ticker_lists).await?.into_iter();
+
+            let tickers = ticker_lists.next().unwrap_or_default();
+            let others: Vec<Vec<Ticker>> = ticker_lists.collect();
+
+            Ok(tickers.into_iter()
+                .filter(|ticker| others.iter().all(|list| list.contains(ticker)))
+                .collect())
+        }
+        .boxed()
+    }
+}
+
+// a failed tick size fetch leaves the merged book down until this retry
+const TICK_SIZE_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Streams of the venues a merged stream is made of.
+/// Depth takes its resolution to the reference venue, the others stream at their default
+fn venue_streams(stream: StreamType) -> Vec<(Exchange, StreamType)> {
+    match stream {
+        StreamType::DepthAndTrades { ticker, resolution, .. } => VENUES.map(|exchange| {
+            let resolution = if exchange == REFERENCE_VENUE { resolution } else { None };
+
+            (exchange, StreamType::DepthAndTrades { exchange, ticker, resolution })
+        }).to_vec(),
+        StreamType::Liquidations { ticker, .. } => VENUES.map(|exchange| (exchange, StreamType::Liquidations { exchange, ticker })).to_vec(),
+        StreamType::Kline { ticker, timeframe, .. } => {
+            vec![(REFERENCE_VENUE, StreamType::Kline { exchange: REFERENCE_VENUE, ticker, timeframe })]
+        }
+        StreamType::MarketStats { ticker, .. } => vec![(REFERENCE_VENUE, StreamType::MarketStats { exchange: REFERENCE_VENUE, ticker })],
+        StreamType::None => vec![],
+    }
+}
+
+fn fetch_tick_size(stream: StreamType, delay: std::time::Duration) -> BoxFuture<'static, (StreamType, Result<Price, String>)> {
+    async move {
+        tokio::time::sleep(delay).await;
+
+        let tick_size = match stream {
+            StreamType::DepthAndTrades { ticker, .. } => Provider.fetch_ticksize(ticker).await,
+            _ => Err("Not a depth stream".to_string()),
+        };
+
+        (stream, tick_size)
+    }
+    .boxed()
+}
+
+/// What a merged stream keeps of its venues
+#[derive(Default)]
+struct Merged {
+    states: VenueStates,
+    /// Each venue's book put on the coarsest tick size among them and summed level by level,
+    /// `None` until that tick size is in or for streams other than depth
+    books: Option<ConsolidatedDepth>,
+    // venue clocks drift apart by a few ms, the charts expect updates in order
+    last_time: i64,
+}
+
+/// Each venue's connection carries what the merged streams need of it, their events are merged back per stream.
+/// Depth and liquidations come from every venue, the trades of whichever venue updated are passed along untouched
+fn connect(mut streams: SubscribedStreams) -> impl futures::Stream<Item = Event> {
+    stream::channel (
+        100,
+        move |mut output| async move {
+            let venue_senders = VENUES.map(|venue| (venue, watch::Sender::new(HashSet::new())));
+
+            let mut venues = futures::stream::select_all(
+                venue_senders.iter().map(|(venue, sender)| {
+                    let venue = *venue;
+
+                    provider(venue).connect(sender.subscribe()).map(move |event| (venue, event))
+                })
+            );
+
+            let mut merged: HashMap<StreamType, Merged> = HashMap::new();
+            let mut tick_sizes = FuturesUnordered::new();
+
+            let mut changed = true;
+
+            loop {
+                if changed {
+                    let wanted = streams.borrow_and_update().clone();
+                    merged.retain(|stream, _| wanted.contains(stream));
+
+                    let added: Vec<StreamType> = wanted.into_iter()
+                        .filter(|stream| !merged.contains_key(stream))
+                        .collect();
+
+                    for stream in &added {
+                        if let StreamType::DepthAndTrades { .. } = stream {
+                            tick_sizes.push(fetch_tick_size(*stream, std::time::Duration::ZERO));
+                        }
+                        merged.insert(*stream, Merged::default());
+                    }
+
+                    for (venue, sender) in &venue_senders {
+                        let wanted: HashSet<StreamType> = merged.keys()
+                            .flat_map(|stream| venue_streams(*stream))
+                            .filter(|(exchange, _)| exchange == venue)
+                            .map(|(_, stream)| stream)
+                            .collect();
+
+                        sender.send_if_modified(|streams| {
+                            let modified = *streams != wanted;
+                            *streams = wanted;
+                            modified
+                        });
+                    }
+
+                    if !added.is_empty() {
+                        let _ = output.send(Event::Connection(added, ConnectionState::Connecting)).await;
+                    }
+                }
+
+                tokio::select! {
+                    result = streams.changed() => {
+                        if result.is_err() {
+                            return;
+                        }
+                        changed = true;
+                        continue;
+                    }
+                    Some((venue, event)) = venues.next() => {
+                        for event in merge(&mut merged, venue, event) {
+                            let _ = output.send(event).await;
+                        }
+                    }
+                    Some((stream, tick_size)) = tick_sizes.next() => {
+                        match (merged.get_mut(&stream), tick_size) {
+                            (Some(stream_merged), Ok(tick_size)) => {
+                                stream_merged.books = Some(ConsolidatedDepth::new(tick_size));
+
+                                let _ = output.send(Event::Connection(vec![stream], stream_merged.states.merged())).await;
+                            }
+                            (Some(_), Err(err)) => {
+                                let _ = output.send(Event::Connection(
+                                    vec![stream], ConnectionState::Down(format!("Failed to fetch tick sizes: {err}"))
+                                )).await;
+
+                                tick_sizes.push(fetch_tick_size(stream, TICK_SIZE_RETRY_DELAY));
+                            }
+                            (None, _) => {}
+                        }
+                    }
+                }
+
+                changed = false;
+            }
+        }
+    )
+}
+
+/// A venue's event as it appears on the merged streams made of it
+fn merge(merged: &mut HashMap<StreamType, Merged>, venue: Exchange, event: Event) -> Vec<Event> {
+    let made_of = |merged: &HashMap<StreamType, Merged>, venue_stream: StreamType| -> Vec<StreamType> {
+        merged.keys()
+            .filter(|stream| venue_streams(**stream).contains(&(venue, venue_stream)))
+            .copied()
+            .collect()
+    };
+
+    let mut events = vec![];
+
+    match event {
+        Event::Connection(venue_stream_types, state) => {
+            let mut single_venue = vec![];
+
+            for venue_stream in venue_stream_types {
+                for stream in made_of(merged, venue_stream) {
+                    let Some(stream_merged) = merged.get_mut(&stream) else {
+                        continue;
+                    };
+
+                    match stream {
+                        StreamType::DepthAndTrades { .. } | StreamType::Liquidations { .. } => {
+                            // a book that isn't live anymore would leave stale levels in the merged one
+                            if state != ConnectionState::Live {
+                                if let Some(books) = &mut stream_merged.books {
+                                    books.remove(venue);
+                                }
+                            }
+
+                            let updated = stream_merged.states.update(venue, state.clone());
+
+                            // a merged book stays connecting until the tick size is in
+                            let waiting = matches!(stream, StreamType::DepthAndTrades { .. }) && stream_merged.books.is_none();
+
+                            if let (Some(state), false) = (updated, waiting) {
+                                events.push(Event::Connection(vec![stream], state));
+                            }
+                        }
+                        _ => single_venue.push(stream),
+                    }
+                }
+            }
+
+            if !single_venue.is_empty() {
+                events.push(Event::Connection(single_venue, state));
+            }
+        }
+        Event::DepthReceived(venue_stream, feed_latency, time, depth, trades) => {
+            for stream in made_of(merged, venue_stream) {
+                let Some(Merged { books: Some(books), last_time, .. }) = merged.get_mut(&stream) else {
+                    continue;
+                };
+
+                *last_time = (*last_time).max(time);
+
+                events.push(
+                    Event::DepthReceived(
+                        stream,
+                        feed_latency,
+                        *last_time,
+                        books.update(venue, depth.clone()),
+                        trades.clone(),
+                    )
+                );
+            }
+        }
+        Event::KlineReceived(venue_stream, kline) => events.push(Event::KlineReceived(relabel(venue_stream), kline)),
+        Event::MarketStatsReceived(venue_stream, stats) => events.push(Event::MarketStatsReceived(relabel(venue_stream), stats)),
+        Event::LiquidationsReceived(venue_stream, liquidations) => {
+            events.push(Event::LiquidationsReceived(relabel(venue_stream), liquidations));
+        }
+    }
+
+    events
+}
+
+/// Connection state of each venue behind a merged stream, reported as the least healthy one of them
+#[derive(Default)]
+struct VenueStates(HashMap<Exchange, ConnectionState>);
+
+impl VenueStates {
+    /// Records a venue's new state, returning the merged one if that changed
+    fn update(&mut self, venue: Exchange, state: ConnectionState) -> Option<ConnectionState> {
+        let before = self.merged();
+        self.0.insert(venue, state);
+        let after = self.merged();
+
+        (before != after).then_some(after)
+    }
+
+    fn merged(&self) -> ConnectionState {
+        let severity = |state: &ConnectionState| match state {
+            ConnectionState::Live => 0,
+            ConnectionState::Resyncing => 1,
+            ConnectionState::Connecting => 2,
+            ConnectionState::Down(_) => 3,
+        };
+
+        let least_healthy = VENUES.iter()
+            .map(|venue| (venue, self.0.get(venue).unwrap_or(&ConnectionState::Connecting)))
+            .max_by_key(|(_, state)| severity(state));
+
+        match least_healthy {
+            Some((venue, ConnectionState::Down(reason))) => ConnectionState::Down(format!("{venue}: {reason}")),
+            Some((_, state)) => state.clone(),
+            None => ConnectionState::Connecting,
+        }
+    }
+}
+
+fn relabel(stream_type: StreamType) -> StreamType {
+    let exchange = Exchange::AggregatedLinear;
+
+    match stream_type {
+        StreamType::Kline { ticker, timeframe, .. } => StreamType::Kline { exchange, ticker, timeframe },
+        StreamType::DepthAndTrades { ticker, resolution, .. } => StreamType::DepthAndTrades { exchange, ticker, resolution },
+        StreamType::MarketStats { ticker, .. } => StreamType::MarketStats { exchange, ticker },
+        StreamType::Liquidations { ticker, .. } => StreamType::Liquidations { exchange, ticker },
+        StreamType::None => StreamType::None,
+    }
+}
@@ -1,2 +1,43 @@
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt, TryFutureExt, TryStreamExt};
+
+use super::{connection, Event, Exchange, Kline, MarketDataProvider, Price, SubscribedStreams, Ticker, Timeframe, TradesBatch};
+
 pub mod market_data;
-pub mod user_data;
\ No newline at end of file
+pub mod user_data;
+
+/// Market data of one of the exchange's markets, e.g. `Exchange::BinanceSpot`
+pub struct Provider(pub Exchange);
+
+impl MarketDataProvider for Provider {
+    fn normalize_symbol(&self, ticker: Ticker) -> String {
+        market_data::symbol(ticker)
+    }
+
+    fn connect(&self, streams: SubscribedStreams) -> BoxStream<'static, Event> {
+        connection::connect(market_data::MarketFeed::new(self.0), streams).boxed()
+    }
+
+    fn fetch_klines(&self, ticker: Ticker, timeframe: Timeframe, end_time: Option<i64>) -> BoxFuture<'static, Result<Vec<Kline>, String>> {
+        market_data::fetch_klines(self.0, ticker, timeframe, end_time)
+            .map_err(|err| format!("{err}"))
+            .boxed()
+    }
+
+    fn fetch_ticksize(&self, ticker: Ticker) -> BoxFuture<'static, Result<Price, String>> {
+        market_data::fetch_ticksize(self.0, ticker)
+            .map_err(|err| format!("{err}"))
+            .boxed()
+    }
+
+    fn fetch_trades(&self, ticker: Ticker, from_time: i64, to_time: i64) -> BoxStream<'static, Result<TradesBatch, String>> {
+        market_data::fetch_trades(self.0, ticker, from_time, to_time)
+            .map_err(|err| format!("{err}"))
+            .boxed()
+    }
+
+    fn fetch_ticker_list(&self) -> BoxFuture<'static, Result<Vec<Ticker>, String>> {
+        market_data::fetch_ticker_list(self.0)
+            .map_err(|err| format!("{err}"))
+            .boxed()
+    }
+}
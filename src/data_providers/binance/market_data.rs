@@ -1,5 +1,8 @@
-use iced::{futures, stream};  
+use std::collections::HashMap;
+
+use iced::{futures, stream};
 use futures::stream::Stream;
+use futures::future::{BoxFuture, FutureExt};
 use serde::Deserializer;
 use futures::sink::SinkExt;
 
@@ -12,7 +15,7 @@ use sonic_rs::to_object_iter_unchecked;
 
 use anyhow::{Context, Result};
 
-use fastwebsockets::{FragmentCollector, OpCode};
+use fastwebsockets::{Frame, FragmentCollector, OpCode};
 use http_body_util::Empty;
 use hyper::header::{CONNECTION, UPGRADE};
 use hyper::upgrade::Upgraded;
@@ -23,35 +26,28 @@ use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor};
 use tokio_rustls::TlsConnector;
 
 use crate::data_providers::{
-    LocalDepthCache, Trade, Depth, Order, FeedLatency, Kline, StreamError,
+    DepthResolution, DepthUpdate, LocalDepthCache, Trade, TradesBatch, Order, Price, FeedLatency, Kline, Liquidation, MarketStats, StreamError, Event, Exchange, MarketType, StreamType,
+    clock, ConnectionState,
+    connection::{self, Feed, Socket},
 };
 
-#[allow(clippy::large_enum_variant)]
-enum State {
-    Disconnected,
-    Connected(
-        FragmentCollector<TokioIo<Upgraded>>
-    ),
-}
+fn rest_api(exchange: Exchange) -> String {
+    let version = match exchange.market_type() {
+        MarketType::Spot => "/api/v3",
+        MarketType::LinearPerps => "/fapi/v1",
+        MarketType::InversePerps => "/dapi/v1",
+    };
 
-#[derive(Debug, Clone)]
-pub enum Event {
-    Connected(Connection),
-    Disconnected(String),
-    DepthReceived(Ticker, FeedLatency, i64, Depth, Vec<Trade>),
-    KlineReceived(Ticker, Kline, Timeframe),
+    exchange.base_urls().rest + version
 }
 
-#[derive(Debug, Clone)]
-pub struct Connection;
-
 impl<'de> Deserialize<'de> for Order {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
         let arr: Vec<&str> = Vec::<&str>::deserialize(deserializer)?;
-        let price: f32 = arr[0].parse::<f32>().map_err(serde::de::Error::custom)?;
+        let price: Price = arr[0].parse::<Price>().map_err(serde::de::Error::custom)?;
         let qty: f32 = arr[1].parse::<f32>().map_err(serde::de::Error::custom)?;
         Ok(Order { price, qty })
     }
@@ -60,7 +56,8 @@ impl<'de> Deserialize<'de> for Order {
 pub struct FetchedDepth {
     #[serde(rename = "lastUpdateId")]
     update_id: i64,
-    #[serde(rename = "T")]
+    // not given on spot snapshots
+    #[serde(rename = "T", default)]
     time: i64,
     #[serde(rename = "bids")]
     bids: Vec<Order>,
@@ -68,24 +65,38 @@ pub struct FetchedDepth {
     asks: Vec<Order>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct SonicDepth {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SonicDepth {
+	#[serde(rename = "E")]
+	event_time: u64,
 	#[serde(rename = "T")]
-	time: u64,
+	time: Option<u64>,
 	#[serde(rename = "U")]
 	first_id: u64,
 	#[serde(rename = "u")]
 	final_id: u64,
 	#[serde(rename = "pu")]
-	prev_final_id: u64,
+	prev_final_id: Option<u64>,
 	#[serde(rename = "b")]
 	bids: Vec<BidAsk>,
 	#[serde(rename = "a")]
 	asks: Vec<BidAsk>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct BidAsk {
+impl SonicDepth {
+    // spot updates only carry the event time
+    fn time(&self) -> u64 {
+        self.time.unwrap_or(self.event_time)
+    }
+
+    // futures link updates with `pu`, on spot each update starts right after the previous one
+    fn prev_final_id(&self) -> u64 {
+        self.prev_final_id.unwrap_or(self.first_id.saturating_sub(1))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BidAsk {
 	#[serde(rename = "0")]
 	price: String,
 	#[serde(rename = "1")]
@@ -93,7 +104,7 @@ struct BidAsk {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct SonicTrade {
+pub struct SonicTrade {
 	#[serde(rename = "T")]
 	time: u64,
 	#[serde(rename = "p")]
@@ -105,7 +116,7 @@ struct SonicTrade {
 }
 
 #[derive(Deserialize, Debug, Clone)]
-struct SonicKline {
+pub struct SonicKline {
     #[serde(rename = "t")]
     time: u64,
     #[serde(rename = "o")]
@@ -120,23 +131,53 @@ struct SonicKline {
     volume: String,
     #[serde(rename = "V")]
     taker_buy_base_asset_volume: String,
-    #[serde(rename = "i")]
-    interval: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 struct SonicKlineWrap {
-    #[serde(rename = "s")]
-    symbol: String,
     #[serde(rename = "k")]
     kline: SonicKline,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct SonicMarkPrice {
+    #[serde(rename = "E")]
+    time: i64,
+    #[serde(rename = "p")]
+    mark_price: String,
+    #[serde(rename = "i")]
+    index_price: String,
+    #[serde(rename = "r")]
+    funding_rate: String,
+    #[serde(rename = "T")]
+    next_funding_time: i64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SonicForceOrder {
+    #[serde(rename = "S")]
+    side: String,
+    #[serde(rename = "q")]
+    qty: String,
+    #[serde(rename = "ap")]
+    avg_price: String,
+    #[serde(rename = "T")]
+    time: i64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct SonicForceOrderWrap {
+    #[serde(rename = "o")]
+    order: SonicForceOrder,
+}
+
 #[derive(Debug)]
-enum StreamData {
+pub enum StreamData {
 	Trade(SonicTrade),
 	Depth(SonicDepth),
-    Kline(Ticker, SonicKline),
+    Kline(SonicKline),
+    MarkPrice(SonicMarkPrice),
+    ForceOrder(SonicForceOrder),
 }
 
 #[derive(Debug)]
@@ -144,6 +185,8 @@ enum StreamName {
     Depth,
     Trade,
     Kline,
+    MarkPrice,
+    ForceOrder,
     Unknown,
 }
 impl StreamName {
@@ -153,6 +196,8 @@ impl StreamName {
                 _ if after_at.starts_with("dep") => StreamName::Depth,
                 _ if after_at.starts_with("agg") => StreamName::Trade,
                 _ if after_at.starts_with("kli") => StreamName::Kline,
+                _ if after_at.starts_with("mar") => StreamName::MarkPrice,
+                _ if after_at.starts_with("for") => StreamName::ForceOrder,
                 _ => StreamName::Unknown,
             }
         } else {
@@ -166,10 +211,14 @@ enum StreamWrapper {
 	Trade,
 	Depth,
     Kline,
+    MarkPrice,
+    ForceOrder,
 }
 
-fn feed_de(bytes: &Bytes) -> Result<StreamData> {
+/// The message along with the name of the stream it came on
+fn feed_de(bytes: &Bytes) -> Result<(String, StreamData)> {
 	let mut stream_type: Option<StreamWrapper> = None;
+	let mut stream_name = String::new();
 
 	let iter: sonic_rs::ObjectJsonIter = unsafe { to_object_iter_unchecked(bytes) };
 
@@ -179,6 +228,8 @@ fn feed_de(bytes: &Bytes) -> Result<StreamData> {
 
 		if k == "stream" {
 			if let Some(val) = v.as_str() {
+                stream_name = val.to_string();
+
                 match StreamName::from_stream_type(val) {
 					StreamName::Depth => {
 						stream_type = Some(StreamWrapper::Depth);
@@ -188,6 +239,12 @@ fn feed_de(bytes: &Bytes) -> Result<StreamData> {
 					},
                     StreamName::Kline => {
                         stream_type = Some(StreamWrapper::Kline);
+                    },
+                    StreamName::MarkPrice => {
+                        stream_type = Some(StreamWrapper::MarkPrice);
+                    },
+                    StreamName::ForceOrder => {
+                        stream_type = Some(StreamWrapper::ForceOrder);
                     },
 					_ => {
                         log::warn!("Unknown stream name");
@@ -200,27 +257,31 @@ fn feed_de(bytes: &Bytes) -> Result<StreamData> {
 					let trade: SonicTrade = sonic_rs::from_str(&v.as_raw_faststr())
 						.context("Error parsing trade")?;
 
-					return Ok(StreamData::Trade(trade));
+					return Ok((stream_name, StreamData::Trade(trade)));
 				},
 				Some(StreamWrapper::Depth) => {
 					let depth: SonicDepth = sonic_rs::from_str(&v.as_raw_faststr())
 						.context("Error parsing depth")?;
 
-					return Ok(StreamData::Depth(depth));
+					return Ok((stream_name, StreamData::Depth(depth)));
 				},
                 Some(StreamWrapper::Kline) => {
                     let kline_wrap: SonicKlineWrap = sonic_rs::from_str(&v.as_raw_faststr())
                         .context("Error parsing kline")?;
 
-                    let ticker = match &kline_wrap.symbol[..] {
-                        "BTCUSDT" => Ticker::BTCUSDT,
-                        "ETHUSDT" => Ticker::ETHUSDT,
-                        "SOLUSDT" => Ticker::SOLUSDT,
-                        "LTCUSDT" => Ticker::LTCUSDT,
-                        _ => Ticker::BTCUSDT,
-                    };
+                    return Ok((stream_name, StreamData::Kline(kline_wrap.kline)));
+                },
+                Some(StreamWrapper::MarkPrice) => {
+                    let mark_price: SonicMarkPrice = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing mark price")?;
+
+                    return Ok((stream_name, StreamData::MarkPrice(mark_price)));
+                },
+                Some(StreamWrapper::ForceOrder) => {
+                    let force_order: SonicForceOrderWrap = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing force order")?;
 
-                    return Ok(StreamData::Kline(ticker, kline_wrap.kline));
+                    return Ok((stream_name, StreamData::ForceOrder(force_order.order)));
                 },
 				_ => {
 					log::error!("Unknown stream type");
@@ -255,20 +316,15 @@ fn tls_connector() -> Result<TlsConnector> {
 	Ok(TlsConnector::from(std::sync::Arc::new(config)))
 }
 
-async fn connect(domain: &str, streams: &str) -> Result<FragmentCollector<TokioIo<Upgraded>>> {
-	let mut addr = String::from(domain);
-	addr.push_str(":443");
+async fn connect(base_url: &str, streams: &str) -> Result<FragmentCollector<TokioIo<Upgraded>>> {
+	let base = url::Url::parse(base_url)?;
+	let host = base.host_str().context("websocket url has no host")?;
+	let port = base.port_or_known_default().context("websocket url has no port")?;
+	let addr = format!("{host}:{port}");
 
 	let tcp_stream: TcpStream = TcpStream::connect(&addr).await?;
-	let tls_connector: TlsConnector = tls_connector().unwrap();
-	let domain: tokio_rustls::rustls::ServerName =
-	tokio_rustls::rustls::ServerName::try_from(domain).map_err(|_| {
-		std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid dnsname")
-	})?;
-
-	let tls_stream: tokio_rustls::client::TlsStream<TcpStream> = tls_connector.connect(domain, tcp_stream).await?;
 
-    let url = format!("wss://{}/stream?streams={}", &addr, streams);
+	let url = format!("{base_url}/stream?streams={streams}");
 
 	let req: Request<Empty<Bytes>> = Request::builder()
 	.method("GET")
@@ -283,7 +339,21 @@ async fn connect(domain: &str, streams: &str) -> Result<FragmentCollector<TokioI
 	.header("Sec-WebSocket-Version", "13")
 	.body(Empty::<Bytes>::new())?;
 
-	let (ws, _) = fastwebsockets::handshake::client(&SpawnExecutor, req, tls_stream).await?;
+	// plain ws is only there for local mock servers
+	let (ws, _) = if base.scheme() == "wss" {
+		let tls_connector: TlsConnector = tls_connector().unwrap();
+		let domain: tokio_rustls::rustls::ServerName =
+		tokio_rustls::rustls::ServerName::try_from(host).map_err(|_| {
+			std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid dnsname")
+		})?;
+
+		let tls_stream: tokio_rustls::client::TlsStream<TcpStream> = tls_connector.connect(domain, tcp_stream).await?;
+
+		fastwebsockets::handshake::client(&SpawnExecutor, req, tls_stream).await?
+	} else {
+		fastwebsockets::handshake::client(&SpawnExecutor, req, tcp_stream).await?
+	};
+
 	Ok(FragmentCollector::new(ws))
 }
 struct SpawnExecutor;
@@ -298,334 +368,395 @@ where
   }
 }
 
-pub fn connect_market_stream(ticker: Ticker) -> impl Stream<Item = Event> {    
-    stream::channel (
-        100,
-        move |mut output| async move {
-            let mut state = State::Disconnected;     
-            let mut trades_buffer: Vec<Trade> = Vec::new(); 
+/// Waits `delay` first, for retries and the next poll
+fn fetch_snapshot(exchange: Exchange, ticker: Ticker, delay: tokio::time::Duration) -> BoxFuture<'static, Fetched> {
+    async move {
+        tokio::time::sleep(delay).await;
+
+        let snapshot = fetch_depth(exchange, ticker).await
+            .map(|depth| DepthUpdate {
+                last_update_id: depth.update_id,
+                time: depth.time,
+                bids: depth.bids,
+                asks: depth.asks,
+            })
+            .map_err(|e| format!("Failed to fetch depth for {ticker}, error: {e}"));
+
+        Fetched::Snapshot(snapshot)
+    }
+    .boxed()
+}
 
-            let selected_ticker = ticker;
+// open interest isn't on any stream, so it's polled alongside the mark price stream
+const OPEN_INTEREST_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(10);
 
-            let symbol_str = match selected_ticker {
-                Ticker::BTCUSDT => "btcusdt",
-                Ticker::ETHUSDT => "ethusdt",
-                Ticker::SOLUSDT => "solusdt",
-                Ticker::LTCUSDT => "ltcusdt",
-            };
+// a failed snapshot leaves the book down until this retry
+const SNAPSHOT_RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(3);
 
-            let stream_1 = format!("{symbol_str}@aggTrade");
-            let stream_2 = format!("{symbol_str}@depth@100ms");
-
-            let mut orderbook: LocalDepthCache = LocalDepthCache::new();
-
-            let mut already_fetching: bool = false;
-
-            let mut prev_id: u64 = 0;
-
-            let mut trade_latencies: Vec<i64> = Vec::new();
-
-            loop {
-                match &mut state {
-                    State::Disconnected => {        
-                        let streams = format!("{stream_1}/{stream_2}");
-
-                        let domain: &str = "fstream.binance.com";
-
-                        if let Ok(websocket) = connect(domain, streams.as_str()
-                        )
-                        .await {
-                            let (tx, rx) = tokio::sync::oneshot::channel();
-                                                
-                            tokio::spawn(async move {
-                                let fetched_depth = fetch_depth(selected_ticker).await;
-
-                                let depth = match fetched_depth {
-                                    Ok(depth) => {
-                                        LocalDepthCache {
-                                            last_update_id: depth.update_id,
-                                            time: depth.time,
-                                            bids: depth.bids,
-                                            asks: depth.asks,
-                                        }
-                                    },
-                                    Err(e) => {
-                                        log::error!("Failed to fetch depth for {}, error: {}", symbol_str, e);
-                                        return;
-                                    }
-                                };
-
-                                let _ = tx.send(depth);
-                            });
-                            match rx.await {
-                                Ok(depth) => {
-                                    orderbook.fetched(depth);
-
-                                    prev_id = 0;
-
-                                    state = State::Connected(websocket);
-                                    let _ = output.send(Event::Connected(Connection)).await;                                 
-                                },
-                                Err(e) => {
-                                    let _ = output.send(Event::Disconnected(
-                                        format!("Failed to send fetched depth for {}, error: {}", symbol_str, e)
-                                    )).await.expect("Trying to send disconnect event...");
-                                }
-                            }
-                        } else {
-                            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-                            let _ = output.send(Event::Disconnected(
-                                "Failed to connect to websocket".to_string()
-                            )).await;
-                        }
-                    },
-                    State::Connected(ws) => {
-                        let feed_latency: FeedLatency;
-
-                        match ws.read_frame().await {
-                            Ok(msg) => match msg.opcode {
-                                OpCode::Text => {                    
-                                    let json_bytes: Bytes = Bytes::from(msg.payload.to_vec());
-                    
-                                    if let Ok(data) = feed_de(&json_bytes) {
-                                        match data {
-                                            StreamData::Trade(de_trade) => {
-                                                let trade = Trade {
-                                                    time: de_trade.time as i64,
-                                                    is_sell: de_trade.is_sell,
-                                                    price: str_f32_parse(&de_trade.price),
-                                                    qty: str_f32_parse(&de_trade.qty),
-                                                };
-
-                                                trade_latencies.push(
-                                                    chrono::Utc::now().timestamp_millis() - trade.time
-                                                );
-
-                                                trades_buffer.push(trade);
-                                            },
-                                            StreamData::Depth(de_depth) => {
-                                                if already_fetching {
-                                                    log::warn!("Already fetching...\n");
-    
-                                                    continue;
-                                                }
-    
-                                                let last_update_id = orderbook.get_fetch_id() as u64;
-                                                
-                                                if (de_depth.final_id <= last_update_id) || last_update_id == 0 {
-                                                    continue;
-                                                }
-    
-                                                if prev_id == 0 && (de_depth.first_id > last_update_id + 1) || (last_update_id + 1 > de_depth.final_id) {
-                                                    log::warn!("Out of sync at first event. Trying to resync...\n");
-    
-                                                    let (tx, rx) = tokio::sync::oneshot::channel();
-                                                    already_fetching = true;
-    
-                                                    tokio::spawn(async move {
-                                                        let fetched_depth = fetch_depth(selected_ticker).await;
-    
-                                                        let depth = match fetched_depth {
-                                                            Ok(depth) => {
-                                                                LocalDepthCache {
-                                                                    last_update_id: depth.update_id,
-                                                                    time: depth.time,
-                                                                    bids: depth.bids,
-                                                                    asks: depth.asks,
-                                                                }
-                                                            },
-                                                            Err(e) => {
-                                                                log::error!("Failed to fetch depth for {}, error: {}", symbol_str, e);
-                                                                return;
-                                                            }
-                                                        };
-    
-                                                        let _ = tx.send(depth);
-                                                    });
-                                                    match rx.await {
-                                                        Ok(depth) => {
-                                                            orderbook.fetched(depth)
-                                                        },
-                                                        Err(e) => {
-                                                            state = State::Disconnected;
-                                                            let _ = output.send(Event::Disconnected(
-                                                                format!("Failed to send fetched depth for {}, error: {}", symbol_str, e)
-                                                            )).await.expect("Trying to send disconnect event...");
-                                                        }
-                                                    }
-                                                    already_fetching = false;
-                                                }
-                                        
-                                                if (prev_id == 0) || (prev_id == de_depth.prev_final_id) {
-                                                    let time = de_depth.time as i64;
-    
-                                                    let depth_latency = chrono::Utc::now().timestamp_millis() - time;
-    
-                                                    let depth_update = LocalDepthCache {
-                                                        last_update_id: de_depth.final_id as i64,
-                                                        time,
-                                                        bids: de_depth.bids.iter().map(
-                                                            |x| Order { price: str_f32_parse(&x.price), qty: str_f32_parse(&x.qty) }
-                                                        ).collect(),
-                                                        asks: de_depth.asks.iter().map(
-                                                            |x| Order { price: str_f32_parse(&x.price), qty: str_f32_parse(&x.qty) }
-                                                        ).collect(),
-                                                    };
-    
-                                                    orderbook.update_depth_cache(depth_update);
-                                                    
-                                                    let avg_trade_latency = if !trade_latencies.is_empty() {
-                                                        let avg = trade_latencies.iter().sum::<i64>() / trade_latencies.len() as i64;
-                                                        trade_latencies.clear();
-                                                        Some(avg)
-                                                    } else {
-                                                        None
-                                                    };
-                                                    feed_latency = FeedLatency {
-                                                        time,
-                                                        depth_latency,
-                                                        trade_latency: avg_trade_latency,
-                                                    };
-    
-                                                    let _ = output.send(
-                                                        Event::DepthReceived(
-                                                            selected_ticker,
-                                                            feed_latency,
-                                                            time, 
-                                                            orderbook.get_depth(),
-                                                            std::mem::take(&mut trades_buffer)
-                                                        )
-                                                    ).await;
-    
-                                                    prev_id = de_depth.final_id;
-                                                } else {
-                                                    state = State::Disconnected;
-                                                    let _ = output.send(
-                                                        Event::Disconnected(
-                                                            format!("Out of sync. Expected update_id: {}, got: {}", de_depth.prev_final_id, prev_id)
-                                                        )
-                                                    ).await;
-                                                }
-                                            },
-                                            _ => {}
-                                        }
-                                    } else {
-                                        log::error!("\nUnknown data: {:?}", &json_bytes);
-                                    }
-                                }
-                                OpCode::Close => {
-                                    state = State::Disconnected;
-                                    let _ = output.send(
-                                        Event::Disconnected("Connection closed".to_string())
-                                    ).await;
-                                }
-                                _ => {}
-                            },
-                            Err(e) => {    
-                                state = State::Disconnected;           
-                                let _ = output.send(
-                                    Event::Disconnected("Error reading frame: ".to_string() + &e.to_string())
-                                ).await;
-                            }
-                        };
-                    }
-                }
+fn poll_open_interest(exchange: Exchange, ticker: Ticker, delay: tokio::time::Duration) -> BoxFuture<'static, Fetched> {
+    async move {
+        tokio::time::sleep(delay).await;
+
+        Fetched::OpenInterest(fetch_open_interest(exchange, ticker).await.map_err(|e| e.to_string()))
+    }
+    .boxed()
+}
+
+pub enum Fetched {
+    Snapshot(Result<DepthUpdate, String>),
+    OpenInterest(Result<f32, String>),
+}
+
+/// A depth stream's book, a REST snapshot with the diffs that came after it applied on top
+struct Book {
+    ticker: Ticker,
+    orderbook: LocalDepthCache,
+    /// Diffs that came in while a snapshot was on its way, `None` once it's applied
+    pending: Option<Vec<SonicDepth>>,
+    prev_id: u64,
+    trades: Vec<Trade>,
+    trade_latencies: Vec<i64>,
+}
+
+impl Book {
+    fn new(ticker: Ticker) -> Self {
+        Self {
+            ticker,
+            orderbook: LocalDepthCache::new(),
+            pending: Some(vec![]),
+            prev_id: 0,
+            trades: vec![],
+            trade_latencies: vec![],
+        }
+    }
+
+    fn snapshot_fetched(&mut self, exchange: Exchange, stream: StreamType, snapshot: DepthUpdate, ctx: &mut connection::Context<MarketFeed>) {
+        self.orderbook.fetched(snapshot);
+        self.prev_id = 0;
+
+        ctx.send(Event::Connection(vec![stream], ConnectionState::Live));
+
+        for diff in self.pending.take().unwrap_or_default() {
+            self.apply(exchange, stream, &diff, ctx);
+        }
+    }
+
+    fn apply(&mut self, exchange: Exchange, stream: StreamType, diff: &SonicDepth, ctx: &mut connection::Context<MarketFeed>) {
+        if let Some(pending) = &mut self.pending {
+            pending.push(diff.clone());
+            return;
+        }
+
+        let last_update_id = self.orderbook.get_fetch_id() as u64;
+
+        if diff.final_id <= last_update_id {
+            return;
+        }
+
+        // the first diff after a snapshot has to span its last update id, the rest follow one another
+        let in_sync = if self.prev_id == 0 {
+            diff.first_id <= last_update_id + 1
+        } else {
+            self.prev_id == diff.prev_final_id()
+        };
+
+        if !in_sync {
+            log::warn!(
+                "Out of sync on {}. Expected update_id: {}, got: {}. Trying to resync...\n",
+                self.ticker, self.prev_id.max(last_update_id), diff.prev_final_id()
+            );
+
+            self.resync(exchange, stream, ctx);
+            return;
+        }
+
+        let time = diff.time() as i64;
+
+        let depth_latency = clock::now(exchange) - time;
+
+        self.orderbook.update_depth_cache(DepthUpdate {
+            last_update_id: diff.final_id as i64,
+            time,
+            bids: diff.bids.iter().map(
+                |x| Order { price: str_price_parse(&x.price), qty: str_f32_parse(&x.qty) }
+            ).collect(),
+            asks: diff.asks.iter().map(
+                |x| Order { price: str_price_parse(&x.price), qty: str_f32_parse(&x.qty) }
+            ).collect(),
+        });
+
+        if self.orderbook.is_crossed() {
+            log::warn!("Crossed orderbook on {}. Trying to resync...\n", self.ticker);
+
+            self.resync(exchange, stream, ctx);
+            return;
+        }
+
+        let avg_trade_latency = if !self.trade_latencies.is_empty() {
+            let avg = self.trade_latencies.iter().sum::<i64>() / self.trade_latencies.len() as i64;
+            self.trade_latencies.clear();
+            Some(avg)
+        } else {
+            None
+        };
+        let feed_latency = FeedLatency {
+            time,
+            depth_latency,
+            trade_latency: avg_trade_latency,
+        };
+
+        ctx.send(
+            Event::DepthReceived(
+                stream,
+                feed_latency,
+                time,
+                self.orderbook.get_depth(),
+                std::mem::take(&mut self.trades)
+            )
+        );
+
+        self.prev_id = diff.final_id;
+    }
+
+    /// Rebuilds the book from a fresh snapshot on the same socket, holding on to diffs until it's there
+    fn resync(&mut self, exchange: Exchange, stream: StreamType, ctx: &mut connection::Context<MarketFeed>) {
+        self.orderbook = LocalDepthCache::new();
+        self.pending = Some(vec![]);
+        self.prev_id = 0;
+
+        ctx.send(Event::Connection(vec![stream], ConnectionState::Resyncing));
+        ctx.fetch(stream, fetch_snapshot(exchange, self.ticker, tokio::time::Duration::ZERO));
+    }
+}
+
+enum StreamState {
+    Depth(Book),
+    /// The last mark price, sent again with each new open interest as it only comes once a second
+    Stats(Option<MarketStats>, Option<f32>),
+    Kline,
+    Liquidations,
+}
+
+/// Every stream of one of the exchange's markets over a single combined stream connection
+pub struct MarketFeed {
+    exchange: Exchange,
+    streams: HashMap<StreamType, StreamState>,
+}
+
+impl MarketFeed {
+    pub fn new(exchange: Exchange) -> Self {
+        Self {
+            exchange,
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl Feed for MarketFeed {
+    type Topic = String;
+    type Message = StreamData;
+    type Fetched = Fetched;
+
+    // the server pings us every few minutes, ours are only there to catch half-open connections
+    const PING_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(20);
+
+    fn exchange(&self) -> Exchange {
+        self.exchange
+    }
+
+    fn ping() -> Frame<'static> {
+        Frame::new(true, OpCode::Ping, None, fastwebsockets::Payload::Borrowed(&[]))
+    }
+
+    fn topics(&self, stream: &StreamType) -> Vec<String> {
+        match *stream {
+            StreamType::DepthAndTrades { ticker, resolution, .. } => {
+                let symbol_str = symbol(ticker);
+
+                // without a speed it's 250ms on futures and 1000ms on spot
+                let depth = match DepthResolution::resolve(resolution, self.exchange) {
+                    DepthResolution::UpdateSpeed(100) => format!("{symbol_str}@depth@100ms"),
+                    DepthResolution::UpdateSpeed(500) => format!("{symbol_str}@depth@500ms"),
+                    _ => format!("{symbol_str}@depth"),
+                };
+
+                vec![format!("{symbol_str}@aggTrade"), depth]
+            }
+            StreamType::Kline { ticker, timeframe, .. } => vec![kline_stream_name(ticker, timeframe)],
+            StreamType::MarketStats { ticker, .. } => vec![format!("{}@markPrice@1s", symbol(ticker))],
+            StreamType::Liquidations { ticker, .. } => vec![format!("{}@forceOrder", symbol(ticker))],
+            StreamType::None => vec![],
+        }
+    }
+
+    fn connect(&self, topics: Vec<String>) -> BoxFuture<'static, Result<Socket>> {
+        let base_url = self.exchange.base_urls().ws;
+
+        async move { connect(&base_url, &topics.join("/")).await }.boxed()
+    }
+
+    fn subscription_messages(&self, topics: &[String], subscribe: bool) -> Vec<String> {
+        let (method, id) = if subscribe { ("SUBSCRIBE", 0) } else { ("UNSUBSCRIBE", 1) };
+
+        vec![serde_json::json!({
+            "method": method,
+            "params": topics,
+            "id": id,
+        }).to_string()]
+    }
+
+    fn parse(&self, payload: &[u8]) -> Option<(String, StreamData)> {
+        // replies to our (un)subscribe requests
+        if payload.starts_with(b"{\"result\"") {
+            return None;
+        }
+
+        feed_de(&Bytes::copy_from_slice(payload)).ok()
+    }
+
+    fn start(&mut self, stream: StreamType, ctx: &mut connection::Context<Self>) -> ConnectionState {
+        let (state, connection_state) = match stream {
+            StreamType::DepthAndTrades { ticker, .. } => {
+                ctx.fetch(stream, fetch_snapshot(self.exchange, ticker, tokio::time::Duration::ZERO));
+
+                // live once the snapshot is in
+                (StreamState::Depth(Book::new(ticker)), ConnectionState::Connecting)
+            }
+            StreamType::MarketStats { ticker, .. } => {
+                ctx.fetch(stream, poll_open_interest(self.exchange, ticker, tokio::time::Duration::ZERO));
+
+                (StreamState::Stats(None, None), ConnectionState::Live)
             }
-        },
-    )
-}
-
-pub fn connect_kline_stream(streams: Vec<(Ticker, Timeframe)>) -> impl Stream<Item = Event> {    
-    stream::channel (
-        100,
-        move |mut output| async move {
-            let mut state = State::Disconnected;    
-
-            let stream_str = streams.iter().map(|(ticker, timeframe)| {
-                let symbol_str = match ticker {
-                    Ticker::BTCUSDT => "btcusdt",
-                    Ticker::ETHUSDT => "ethusdt",
-                    Ticker::SOLUSDT => "solusdt",
-                    Ticker::LTCUSDT => "ltcusdt",
+            StreamType::Kline { .. } => (StreamState::Kline, ConnectionState::Live),
+            StreamType::Liquidations { .. } => (StreamState::Liquidations, ConnectionState::Live),
+            StreamType::None => return ConnectionState::Live,
+        };
+
+        self.streams.insert(stream, state);
+
+        connection_state
+    }
+
+    fn stop(&mut self, stream: &StreamType) {
+        self.streams.remove(stream);
+    }
+
+    fn on_message(&mut self, stream: StreamType, message: &StreamData, ctx: &mut connection::Context<Self>) {
+        let exchange = self.exchange;
+
+        match (self.streams.get_mut(&stream), message) {
+            (Some(StreamState::Depth(book)), StreamData::Trade(de_trade)) => {
+                let trade = Trade {
+                    time: de_trade.time as i64,
+                    is_sell: de_trade.is_sell,
+                    price: str_price_parse(&de_trade.price),
+                    qty: str_f32_parse(&de_trade.qty),
                 };
-                let timeframe_str = match timeframe {
-                    Timeframe::M1 => "1m",
-                    Timeframe::M3 => "3m",
-                    Timeframe::M5 => "5m",
-                    Timeframe::M15 => "15m",
-                    Timeframe::M30 => "30m",
+
+                book.trade_latencies.push(
+                    clock::now(exchange) - trade.time
+                );
+
+                book.trades.push(trade);
+            }
+            (Some(StreamState::Depth(book)), StreamData::Depth(de_depth)) => {
+                book.apply(exchange, stream, de_depth, ctx);
+            }
+            (Some(StreamState::Stats(last_stats, open_interest)), StreamData::MarkPrice(de_mark)) => {
+                let stats = MarketStats {
+                    time: de_mark.time,
+                    mark_price: str_f32_parse(&de_mark.mark_price),
+                    index_price: str_f32_parse(&de_mark.index_price),
+                    funding_rate: str_f32_parse(&de_mark.funding_rate),
+                    next_funding_time: de_mark.next_funding_time,
+                    open_interest: *open_interest,
                 };
-                format!("{symbol_str}@kline_{timeframe_str}")
-            }).collect::<Vec<String>>().join("/");
-
-            loop {
-                match &mut state {
-                    State::Disconnected => {
-                        let domain: &str = "fstream.binance.com";
-
-                        let streams = stream_str.as_str();
-                        
-                        if let Ok(websocket) = connect(
-                            domain, streams
-                        )
-                        .await {
-                            state = State::Connected(websocket);
-                            let _ = output.send(Event::Connected(Connection)).await;        
-                        } else {
-                            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-                            let _ = output.send(Event::Disconnected(
-                                "Failed to connect to websocket".to_string()
-                            )).await;
-                        }
-                    },
-                    State::Connected(ws) => {
-                        match ws.read_frame().await {
-                            Ok(msg) => match msg.opcode {
-                                OpCode::Text => {                    
-                                    let json_bytes: Bytes = Bytes::from(msg.payload.to_vec());
-                    
-                                    if let Ok(StreamData::Kline(ticker, de_kline)) = feed_de(&json_bytes) {
-                                        let buy_volume = str_f32_parse(&de_kline.taker_buy_base_asset_volume);
-                                        let sell_volume = str_f32_parse(&de_kline.volume) - buy_volume;
-
-                                        let kline = Kline {
-                                            time: de_kline.time,
-                                            open: str_f32_parse(&de_kline.open),
-                                            high: str_f32_parse(&de_kline.high),
-                                            low: str_f32_parse(&de_kline.low),
-                                            close: str_f32_parse(&de_kline.close),
-                                            volume: (buy_volume, sell_volume),
-                                        };
-
-                                        if let Some(timeframe) = streams.iter().find(|(_, tf)| tf.to_string() == de_kline.interval) {
-                                            let _ = output.send(Event::KlineReceived(ticker, kline, timeframe.1)).await;
-                                        }
-                                    } else {
-                                        log::error!("\nUnknown data: {:?}", &json_bytes);
-                                    }
-                                }
-                                OpCode::Close => {
-                                    state = State::Disconnected;
-                                    let _ = output.send(
-                                        Event::Disconnected("Connection closed".to_string())
-                                    ).await;
-                                }
-                                _ => {}
-                            }, 
-                            Err(e) => {      
-                                state = State::Disconnected;        
-                                let _ = output.send(
-                                    Event::Disconnected("Error reading frame: ".to_string() + &e.to_string())
-                                ).await;  
-                            }
+                *last_stats = Some(stats);
+
+                ctx.send(Event::MarketStatsReceived(stream, stats));
+            }
+            (Some(StreamState::Kline), StreamData::Kline(de_kline)) => {
+                let buy_volume = str_f32_parse(&de_kline.taker_buy_base_asset_volume);
+                let sell_volume = str_f32_parse(&de_kline.volume) - buy_volume;
+
+                let kline = Kline {
+                    time: de_kline.time,
+                    open: str_price_parse(&de_kline.open),
+                    high: str_price_parse(&de_kline.high),
+                    low: str_price_parse(&de_kline.low),
+                    close: str_price_parse(&de_kline.close),
+                    volume: (buy_volume, sell_volume),
+                };
+
+                ctx.send(Event::KlineReceived(stream, kline));
+            }
+            // Binance pushes at most one liquidation per symbol each second, the largest one in that second
+            (Some(StreamState::Liquidations), StreamData::ForceOrder(de_order)) => {
+                let liquidation = Liquidation {
+                    time: de_order.time,
+                    price: str_price_parse(&de_order.avg_price),
+                    qty: str_f32_parse(&de_order.qty),
+                    is_sell: de_order.side == "SELL",
+                };
+
+                ctx.send(Event::LiquidationsReceived(stream, vec![liquidation]));
+            }
+            _ => {}
+        }
+    }
+
+    fn on_fetched(&mut self, stream: StreamType, fetched: Fetched, ctx: &mut connection::Context<Self>) {
+        let exchange = self.exchange;
+
+        match (self.streams.get_mut(&stream), fetched) {
+            (Some(StreamState::Depth(book)), Fetched::Snapshot(Ok(snapshot))) if book.pending.is_some() => {
+                book.snapshot_fetched(exchange, stream, snapshot, ctx);
+            }
+            (Some(StreamState::Depth(book)), Fetched::Snapshot(Err(e))) => {
+                log::error!("{e}");
+
+                ctx.send(Event::Connection(vec![stream], ConnectionState::Down(e)));
+                ctx.fetch(stream, fetch_snapshot(exchange, book.ticker, SNAPSHOT_RETRY_DELAY));
+            }
+            (Some(StreamState::Stats(last_stats, open_interest)), Fetched::OpenInterest(result)) => {
+                match result {
+                    Ok(value) => {
+                        *open_interest = Some(value);
+
+                        if let Some(stats) = last_stats {
+                            stats.open_interest = Some(value);
+                            ctx.send(Event::MarketStatsReceived(stream, *stats));
                         }
                     }
+                    Err(e) => log::warn!("Failed to fetch open interest: {e}"),
+                }
+
+                if let StreamType::MarketStats { ticker, .. } = stream {
+                    ctx.fetch(stream, poll_open_interest(exchange, ticker, OPEN_INTEREST_INTERVAL));
                 }
             }
-        },
-    )
+            _ => {}
+        }
+    }
+}
+
+fn kline_stream_name(ticker: Ticker, timeframe: Timeframe) -> String {
+    let timeframe_str = match timeframe {
+        Timeframe::M1 => "1m",
+        Timeframe::M3 => "3m",
+        Timeframe::M5 => "5m",
+        Timeframe::M15 => "15m",
+        Timeframe::M30 => "30m",
+        Timeframe::H1 => "1h",
+        Timeframe::H4 => "4h",
+        Timeframe::D1 => "1d",
+    };
+    format!("{}@kline_{timeframe_str}", symbol(ticker))
+}
+
+/// Binance takes lowercase symbols on both its streams and REST endpoints
+pub fn symbol(ticker: Ticker) -> String {
+    ticker.get_string()
 }
 
 fn str_f32_parse(s: &str) -> f32 {
@@ -635,6 +766,13 @@ fn str_f32_parse(s: &str) -> f32 {
     })
 }
 
+fn str_price_parse(s: &str) -> Price {
+    s.parse::<Price>().unwrap_or_else(|e| {
+        log::error!("Failed to parse price: {}, error: {}", s, e);
+        Price::default()
+    })
+}
+
 mod string_to_f32 {
     use serde::{self, Deserialize, Deserializer};
 
@@ -651,10 +789,10 @@ mod string_to_f32 {
 #[derive(Deserialize, Debug, Clone)]
 struct FetchedKlines (
     u64,
-    #[serde(with = "string_to_f32")] f32,
-    #[serde(with = "string_to_f32")] f32,
-    #[serde(with = "string_to_f32")] f32,
-    #[serde(with = "string_to_f32")] f32,
+    Price,
+    Price,
+    Price,
+    Price,
     #[serde(with = "string_to_f32")] f32,
     u64,
     String,
@@ -678,17 +816,24 @@ impl From<FetchedKlines> for Kline {
     }
 }
 
-pub async fn fetch_klines(ticker: Ticker, timeframe: Timeframe) -> Result<Vec<Kline>, StreamError> {
-    let symbol_str = ticker.get_string();
+pub async fn fetch_klines(exchange: Exchange, ticker: Ticker, timeframe: Timeframe, end_time: Option<i64>) -> Result<Vec<Kline>, StreamError> {
+    let symbol_str = symbol(ticker);
     let timeframe_str = match timeframe {
         Timeframe::M1 => "1m",
         Timeframe::M3 => "3m",
         Timeframe::M5 => "5m",
         Timeframe::M15 => "15m",
         Timeframe::M30 => "30m",
+        Timeframe::H1 => "1h",
+        Timeframe::H4 => "4h",
+        Timeframe::D1 => "1d",
     };
 
-    let url = format!("https://fapi.binance.com/fapi/v1/klines?symbol={symbol_str}&interval={timeframe_str}&limit=720");
+    let mut url = format!("{}/klines?symbol={symbol_str}&interval={timeframe_str}&limit=720", rest_api(exchange));
+
+    if let Some(end_time) = end_time {
+        url.push_str(&format!("&endTime={end_time}"));
+    }
 
     let response = reqwest::get(&url)
         .await.map_err(StreamError::FetchError)?;
@@ -703,10 +848,10 @@ pub async fn fetch_klines(ticker: Ticker, timeframe: Timeframe) -> Result<Vec<Kl
     Ok(klines)
 }
 
-pub async fn fetch_depth(ticker: Ticker) -> Result<FetchedDepth, StreamError> {
-    let symbol_str = ticker.get_string();
+pub async fn fetch_depth(exchange: Exchange, ticker: Ticker) -> Result<FetchedDepth, StreamError> {
+    let symbol_str = symbol(ticker);
 
-    let url = format!("https://fapi.binance.com/fapi/v1/depth?symbol={symbol_str}&limit=1000");
+    let url = format!("{}/depth?symbol={symbol_str}&limit=1000", rest_api(exchange));
 
     let response = reqwest::get(&url)
         .await.map_err(StreamError::FetchError)?;
@@ -721,9 +866,194 @@ pub async fn fetch_depth(ticker: Ticker) -> Result<FetchedDepth, StreamError> {
     Ok(depth)
 }
 
-pub async fn fetch_ticksize(ticker: Ticker) -> Result<f32, StreamError> {
-    let symbol_str = ticker.get_string().to_uppercase();
-    let url = "https://fapi.binance.com/fapi/v1/exchangeInfo".to_string();
+#[derive(Deserialize, Debug)]
+struct FetchedOpenInterest {
+    #[serde(rename = "openInterest", with = "string_to_f32")]
+    open_interest: f32,
+}
+
+/// Open interest in contracts, only futures markets have it
+pub async fn fetch_open_interest(exchange: Exchange, ticker: Ticker) -> Result<f32, StreamError> {
+    let url = format!("{}/openInterest?symbol={}", rest_api(exchange), symbol(ticker).to_uppercase());
+
+    let text = get_weighted(exchange, &url).await?;
+
+    let fetched: FetchedOpenInterest = serde_json::from_str(&text)
+        .map_err(|e| StreamError::ParseError(format!("Failed to parse open interest: {e}")))?;
+
+    Ok(fetched.open_interest)
+}
+
+#[derive(Deserialize, Debug)]
+struct FetchedAggTrade {
+    #[serde(rename = "a")]
+    id: u64,
+    #[serde(rename = "T")]
+    time: i64,
+    #[serde(rename = "p")]
+    price: Price,
+    #[serde(rename = "q", with = "string_to_f32")]
+    qty: f32,
+    #[serde(rename = "m")]
+    is_sell: bool,
+}
+
+/// Request weight allowed per minute, per IP
+fn weight_limit(exchange: Exchange) -> u32 {
+    match exchange.market_type() {
+        MarketType::Spot => 6000,
+        MarketType::LinearPerps | MarketType::InversePerps => 2400,
+    }
+}
+
+/// GETs a weighted endpoint, backing off when Binance answers 429 and
+/// sleeping into the next minute once most of the minute's weight is used
+async fn get_weighted(exchange: Exchange, url: &str) -> Result<String, StreamError> {
+    loop {
+        let response = reqwest::get(url).await.map_err(StreamError::FetchError)?;
+
+        match response.status().as_u16() {
+            429 => {
+                let retry_after = response.headers().get("retry-after")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .unwrap_or(60);
+
+                log::warn!("Rate limited by Binance, retrying in {retry_after}s");
+                tokio::time::sleep(tokio::time::Duration::from_secs(retry_after)).await;
+                continue;
+            },
+            418 => return Err(StreamError::UnknownError("IP banned by Binance for exceeding rate limits".to_string())),
+            _ => {},
+        }
+
+        let used_weight = response.headers().get("x-mbx-used-weight-1m")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        let text = response.text().await.map_err(StreamError::FetchError)?;
+
+        if used_weight >= weight_limit(exchange) * 4 / 5 {
+            let now = chrono::Utc::now().timestamp_millis() as u64;
+            let until_next_minute = 60_000 - now % 60_000;
+
+            log::info!("Used {used_weight} request weight, pausing {until_next_minute}ms");
+            tokio::time::sleep(tokio::time::Duration::from_millis(until_next_minute)).await;
+        }
+
+        return Ok(text);
+    }
+}
+
+/// Pages through `aggTrades` from `from_time` up to `to_time`.
+/// Requests are time bounded to an hour at most until one comes back full, the rest continue from the last trade id.
+pub fn fetch_trades(exchange: Exchange, ticker: Ticker, from_time: i64, to_time: i64) -> impl Stream<Item = Result<TradesBatch, StreamError>> {
+    stream::channel(16, move |mut output| async move {
+        let symbol_str = symbol(ticker).to_uppercase();
+        let range = (to_time - from_time).max(1) as f32;
+
+        let mut window_start = from_time;
+        let mut from_id: Option<u64> = None;
+
+        while window_start < to_time {
+            let window_end = (window_start + 3_600_000).min(to_time);
+
+            let url = match from_id {
+                Some(id) => format!(
+                    "{}/aggTrades?symbol={symbol_str}&fromId={id}&limit=1000", rest_api(exchange)
+                ),
+                None => format!(
+                    "{}/aggTrades?symbol={symbol_str}&startTime={window_start}&endTime={}&limit=1000",
+                    rest_api(exchange), window_end - 1
+                ),
+            };
+
+            let fetched: Vec<FetchedAggTrade> = match get_weighted(exchange, &url).await
+                .and_then(|text| serde_json::from_str(&text)
+                    .map_err(|e| StreamError::ParseError(format!("Failed to parse aggTrades: {}", e)))
+                ) {
+                    Ok(fetched) => fetched,
+                    Err(e) => {
+                        let _ = output.send(Err(e)).await;
+                        return;
+                    }
+                };
+
+            let Some(last) = fetched.last() else {
+                if from_id.is_some() {
+                    // caught up with the latest trade
+                    break;
+                }
+                // nothing traded within this hour
+                window_start = window_end;
+                continue;
+            };
+
+            let is_full_page = fetched.len() == 1000;
+            let paging_by_id = from_id.is_some();
+
+            if paging_by_id || is_full_page {
+                from_id = Some(last.id + 1);
+                window_start = last.time;
+            } else {
+                window_start = window_end;
+            }
+
+            // a short page of a time window only means that hour was quiet, of trade ids that there are no more
+            let is_last_page = last.time >= to_time || window_start >= to_time || (paging_by_id && !is_full_page);
+
+            let trades: Vec<Trade> = fetched.iter()
+                .filter(|trade| trade.time < to_time)
+                .map(|trade| Trade {
+                    time: trade.time,
+                    is_sell: trade.is_sell,
+                    price: trade.price,
+                    qty: trade.qty,
+                })
+                .collect();
+
+            let progress = if is_last_page { 1.0 } else { ((window_start - from_time) as f32 / range).min(1.0) };
+
+            let _ = output.send(Ok(TradesBatch { trades, progress })).await;
+
+            if is_last_page {
+                return;
+            }
+        }
+
+        let _ = output.send(Ok(TradesBatch { trades: vec![], progress: 1.0 })).await;
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct FetchedServerTime {
+    #[serde(rename = "serverTime")]
+    server_time: i64,
+}
+
+/// The exchange's clock in milliseconds
+pub async fn fetch_server_time(exchange: Exchange) -> Result<i64, StreamError> {
+    let url = format!("{}/time", rest_api(exchange));
+
+    let response = reqwest::get(&url)
+        .await.map_err(StreamError::FetchError)?;
+    let text = response.text()
+        .await.map_err(StreamError::FetchError)?;
+
+    let fetched: FetchedServerTime = serde_json::from_str(&text)
+        .map_err(|e| StreamError::ParseError(format!("Failed to parse server time: {}", e)))?;
+
+    Ok(fetched.server_time)
+}
+
+pub async fn fetch_ticksize(exchange: Exchange, ticker: Ticker) -> Result<Price, StreamError> {
+    let symbol_str = symbol(ticker).to_uppercase();
+    let url = match exchange.market_type() {
+        // spot lists thousands of pairs, so only ask for the one we need
+        MarketType::Spot => format!("{}/exchangeInfo?symbol={symbol_str}", rest_api(exchange)),
+        MarketType::LinearPerps | MarketType::InversePerps => format!("{}/exchangeInfo", rest_api(exchange)),
+    };
 
     let response = reqwest::get(&url).await.map_err(StreamError::FetchError)?;
     let text = response.text().await.map_err(StreamError::FetchError)?;
@@ -746,8 +1076,166 @@ pub async fn fetch_ticksize(ticker: Ticker) -> Result<f32, StreamError> {
         ["tickSize"].as_str()
         .ok_or_else(|| StreamError::ParseError("tickSize not found".to_string()))?;
 
-    let tick_size = tick_size_str.parse::<f32>()
+    let tick_size = tick_size_str.parse::<Price>()
         .map_err(|e| StreamError::ParseError(format!("Failed to parse tickSize: {}", e)))?;
 
     Ok(tick_size)
 }
+
+/// Fetches every spot pair or perpetual contract that is currently trading on the given market
+pub async fn fetch_ticker_list(exchange: Exchange) -> Result<Vec<Ticker>, StreamError> {
+    let url = format!("{}/exchangeInfo", rest_api(exchange));
+
+    let response = reqwest::get(&url).await.map_err(StreamError::FetchError)?;
+    let text = response.text().await.map_err(StreamError::FetchError)?;
+
+    let exchange_info: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|e| StreamError::ParseError(format!("Failed to parse exchange info: {}", e)))?;
+
+    let symbols = exchange_info["symbols"].as_array()
+        .ok_or_else(|| StreamError::ParseError("Missing symbols array".to_string()))?;
+
+    let mut tickers: Vec<Ticker> = symbols.iter()
+        .filter(|x| match exchange.market_type() {
+            MarketType::Spot => x["status"].as_str() == Some("TRADING"),
+            // coin-m lists its status under `contractStatus`
+            MarketType::LinearPerps | MarketType::InversePerps => {
+                x["contractType"].as_str() == Some("PERPETUAL")
+                    && (x["status"].as_str() == Some("TRADING") || x["contractStatus"].as_str() == Some("TRADING"))
+            },
+        })
+        .filter_map(|x| x["symbol"].as_str())
+        .filter(|symbol| Ticker::is_valid_symbol(symbol))
+        .map(Ticker::new)
+        .collect();
+
+    tickers.sort();
+
+    Ok(tickers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use crate::data_providers::mock::{next_event, MockServer, Script, Step};
+
+    const SNAPSHOT: &str = r#"{"lastUpdateId":100,"E":1718095353000,"T":1718095353000,"bids":[["67000.0","1.5"]],"asks":[["67000.1","2"]]}"#;
+    const TRADE: &str = r#"{"stream":"btcusdt@aggTrade","data":{"e":"aggTrade","E":1718095353101,"a":1,"p":"67000.1","q":"0.5","f":1,"l":1,"T":1718095353100,"m":false}}"#;
+    const DEPTH: &str = r#"{"stream":"btcusdt@depth@100ms","data":{"e":"depthUpdate","E":1718095353115,"T":1718095353114,"s":"BTCUSDT","U":95,"u":105,"pu":90,"b":[["67000.0","3"]],"a":[]}}"#;
+    const TRADES_FIRST_HOUR: &str = r#"[{"a":1,"p":"67000.1","q":"0.5","f":1,"l":1,"T":1718092800100,"m":false},{"a":2,"p":"67000.0","q":"1","f":2,"l":2,"T":1718092900000,"m":true}]"#;
+    const TRADES_THIRD_HOUR: &str = r#"[{"a":3,"p":"67100.0","q":"2","f":3,"l":3,"T":1718100000000,"m":false}]"#;
+
+    // a bid above the best ask
+    const CROSSING_DEPTH: &str = r#"{"stream":"btcusdt@depth@100ms","data":{"e":"depthUpdate","E":1718095353215,"T":1718095353214,"s":"BTCUSDT","U":106,"u":110,"pu":105,"b":[["67000.2","1"]],"a":[]}}"#;
+
+    #[tokio::test]
+    async fn resyncs_crossed_book_in_place_and_reconnects_on_close() {
+        let exchange = Exchange::BinanceFutures;
+        let ticker = Ticker::new("BTCUSDT");
+        let depth_stream = StreamType::DepthAndTrades { exchange, ticker, resolution: None };
+        let liquidations = StreamType::Liquidations { exchange, ticker };
+
+        let mock = MockServer::start(
+            Script::new()
+                .rest("/fapi/v1/depth", SNAPSHOT)
+                .session(vec![
+                    Step::Send(TRADE.to_string()),
+                    Step::Send(DEPTH.to_string()),
+                    Step::Send(CROSSING_DEPTH.to_string()),
+                    Step::Receive,
+                    Step::Receive,
+                    Step::Close,
+                ])
+                .session(vec![Step::Close])
+                .session(vec![])
+        ).await;
+        mock.serve(exchange);
+
+        let streams = tokio::sync::watch::Sender::new(std::collections::HashSet::from([depth_stream]));
+        let mut events = Box::pin(connection::connect(MarketFeed::new(exchange), streams.subscribe()));
+
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Connecting)));
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Live)));
+
+        match next_event(&mut events).await {
+            Event::DepthReceived(_, _, time, depth, trades) => {
+                assert_eq!(time, 1718095353114);
+                assert_eq!(depth.best_bid().map(|order| order.qty), Some(3.0));
+                assert_eq!(trades.len(), 1);
+                assert_eq!(trades[0].price.to_string(), "67000.1");
+            }
+            other => panic!("expected depth, got {other:?}"),
+        }
+
+        // a fresh snapshot over the same socket
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Resyncing)));
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Live)));
+        assert_eq!(mock.connections(), 1);
+
+        // swapping streams subscribes and unsubscribes on it too
+        streams.send_replace(std::collections::HashSet::from([liquidations]));
+
+        match next_event(&mut events).await {
+            Event::Connection(started, ConnectionState::Live) => assert_eq!(started, vec![liquidations]),
+            other => panic!("expected liquidations going live, got {other:?}"),
+        }
+
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Down(_))));
+
+        let received = mock.received();
+        assert!(received[0].contains(r#""SUBSCRIBE""#) && received[0].contains("btcusdt@forceOrder"));
+        assert!(received[1].contains(r#""UNSUBSCRIBE""#) && received[1].contains("btcusdt@depth@100ms"));
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Connecting)));
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Live)));
+
+        // the server hanging up right after accepting doesn't reset the backoff, the retry waits 0.5-1s
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Down(_))));
+
+        tokio::time::pause();
+        assert!(futures::poll!(events.next()).is_pending());
+
+        tokio::time::advance(tokio::time::Duration::from_millis(400)).await;
+        assert!(futures::poll!(events.next()).is_pending());
+        assert_eq!(mock.connections(), 2);
+
+        tokio::time::advance(tokio::time::Duration::from_millis(600)).await;
+        tokio::time::resume();
+
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Connecting)));
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Live)));
+        assert_eq!(mock.connections(), 3);
+    }
+
+    #[tokio::test]
+    async fn backfill_pages_past_a_sparse_first_hour() {
+        let exchange = Exchange::BinanceSpot;
+        let from_time = 1718092800000;
+        let to_time = from_time + 3 * 3_600_000;
+
+        let mock = MockServer::start(
+            Script::new()
+                .rest("/api/v3/aggTrades", TRADES_FIRST_HOUR)
+                .rest("/api/v3/aggTrades", "[]")
+                .rest("/api/v3/aggTrades", TRADES_THIRD_HOUR)
+        ).await;
+        mock.serve(exchange);
+
+        let batches: Vec<TradesBatch> = fetch_trades(exchange, Ticker::new("BTCUSDT"), from_time, to_time)
+            .map(|batch| batch.expect("backfill failed"))
+            .collect()
+            .await;
+
+        let trades: Vec<i64> = batches.iter().flat_map(|batch| batch.trades.iter().map(|trade| trade.time)).collect();
+        assert_eq!(trades, vec![1718092800100, 1718092900000, 1718100000000]);
+
+        // the short first page doesn't end it early
+        assert!(batches[..batches.len() - 1].iter().all(|batch| batch.progress < 1.0));
+        assert_eq!(batches.last().map(|batch| batch.progress), Some(1.0));
+
+        let requested = mock.requested();
+        assert_eq!(requested.len(), 3);
+        assert!(requested[1].contains(&format!("startTime={}", from_time + 3_600_000)));
+        assert!(requested.iter().all(|query| !query.contains("fromId")));
+    }
+}
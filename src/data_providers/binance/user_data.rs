@@ -6,12 +6,13 @@ use sha2::Sha256;
 use hex;
 use futures::channel::mpsc;
 use futures::sink::SinkExt;
-use chrono::Utc;
 use serde::Deserialize;
 use serde_json::json;
 use futures::FutureExt;
 use async_tungstenite::tungstenite;
 
+use crate::data_providers::{binance_testnet_url, clock, Exchange};
+
 mod string_to_f32 {
     use serde::{self, Deserialize, Deserializer};
 
@@ -331,10 +332,10 @@ pub struct NewOrder {
 }
 
 pub async fn create_limit_order (side: String, qty: String, price: String, api_key: &str, secret_key: &str) -> Result<NewOrder, BinanceError> {
-    let params = format!("symbol=BTCUSDT&side={}&type=LIMIT&timeInForce=GTC&quantity={}&price={}&timestamp={}", side, qty, price, Utc::now().timestamp_millis());
+    let params = format!("symbol=BTCUSDT&side={}&type=LIMIT&timeInForce=GTC&quantity={}&price={}&timestamp={}", side, qty, price, timestamp());
     let signature = sign_params(&params, secret_key);
 
-    let url = format!("https://testnet.binancefuture.com/fapi/v1/order?{}&signature={}", params, signature);
+    let url = format!("{}/fapi/v1/order?{}&signature={}", binance_testnet_url(), params, signature);
 
     let mut headers = HeaderMap::new();
     headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key).unwrap());
@@ -352,10 +353,10 @@ pub async fn create_limit_order (side: String, qty: String, price: String, api_k
 }
 
 pub async fn create_market_order (side: String, qty: String, api_key: &str, secret_key: &str) -> Result<NewOrder, BinanceError> {
-    let params = format!("symbol=BTCUSDT&side={}&type=MARKET&quantity={}&timestamp={}", side, qty, Utc::now().timestamp_millis());
+    let params = format!("symbol=BTCUSDT&side={}&type=MARKET&quantity={}&timestamp={}", side, qty, timestamp());
     let signature = sign_params(&params, secret_key);
 
-    let url = format!("https://testnet.binancefuture.com/fapi/v1/order?{params}&signature={signature}");
+    let url = format!("{}/fapi/v1/order?{params}&signature={signature}", binance_testnet_url());
 
     let mut headers = HeaderMap::new();
     headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key).unwrap());
@@ -373,10 +374,10 @@ pub async fn create_market_order (side: String, qty: String, api_key: &str, secr
 }
 
 pub async fn cancel_order(order_id: String, api_key: &str, secret_key: &str) -> Result<(), BinanceError> {
-    let params = format!("symbol=BTCUSDT&orderId={}&timestamp={}", order_id, Utc::now().timestamp_millis());
+    let params = format!("symbol=BTCUSDT&orderId={}&timestamp={}", order_id, timestamp());
     let signature = sign_params(&params, secret_key);
 
-    let url = format!("https://testnet.binancefuture.com/fapi/v1/order?{params}&signature={signature}");
+    let url = format!("{}/fapi/v1/order?{params}&signature={signature}", binance_testnet_url());
 
     let mut headers = HeaderMap::new();
     headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key).unwrap());
@@ -393,10 +394,10 @@ pub async fn cancel_order(order_id: String, api_key: &str, secret_key: &str) ->
 }
 
 pub async fn fetch_open_orders(symbol: String, api_key: &str, secret_key: &str) -> Result<Vec<NewOrder>, BinanceError> {
-    let params = format!("timestamp={}&symbol={}", Utc::now().timestamp_millis(), symbol);
+    let params = format!("timestamp={}&symbol={}", timestamp(), symbol);
     let signature = sign_params(&params, secret_key);
 
-    let url = format!("https://testnet.binancefuture.com/fapi/v1/openOrders?{params}&signature={signature}");
+    let url = format!("{}/fapi/v1/openOrders?{params}&signature={signature}", binance_testnet_url());
 
     let mut headers = HeaderMap::new();
     headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key).unwrap());
@@ -409,10 +410,10 @@ pub async fn fetch_open_orders(symbol: String, api_key: &str, secret_key: &str)
 }
 
 pub async fn fetch_open_positions(api_key: &str, secret_key: &str) -> Result<Vec<FetchedPosition>, BinanceError> {
-    let params = format!("timestamp={}", Utc::now().timestamp_millis());
+    let params = format!("timestamp={}", timestamp());
     let signature = sign_params(&params, secret_key);
 
-    let url = format!("https://testnet.binancefuture.com/fapi/v2/positionRisk?{params}&signature={signature}");
+    let url = format!("{}/fapi/v2/positionRisk?{params}&signature={signature}", binance_testnet_url());
 
     let mut headers = HeaderMap::new();
     headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key).unwrap());
@@ -426,10 +427,10 @@ pub async fn fetch_open_positions(api_key: &str, secret_key: &str) -> Result<Vec
 }
 
 pub async fn fetch_acc_balance(api_key: &str, secret_key: &str) -> Result<Vec<FetchedBalance>, BinanceError> {
-    let params = format!("timestamp={}", Utc::now().timestamp_millis());
+    let params = format!("timestamp={}", timestamp());
     let signature = sign_params(&params, secret_key);
 
-    let url = format!("https://testnet.binancefuture.com/fapi/v2/balance?{params}&signature={signature}");
+    let url = format!("{}/fapi/v2/balance?{params}&signature={signature}", binance_testnet_url());
 
     let mut headers = HeaderMap::new();
     headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key).unwrap());
@@ -442,10 +443,10 @@ pub async fn fetch_acc_balance(api_key: &str, secret_key: &str) -> Result<Vec<Fe
 }
 
 pub async fn get_listen_key(api_key: &str, secret_key: &str) -> Result<String, BinanceError> {
-    let params = format!("timestamp={}", Utc::now().timestamp_millis());
+    let params = format!("timestamp={}", timestamp());
     let signature = sign_params(&params, secret_key);
 
-    let url = format!("https://testnet.binancefuture.com/fapi/v1/listenKey?{params}&signature={signature}");
+    let url = format!("{}/fapi/v1/listenKey?{params}&signature={signature}", binance_testnet_url());
 
     let mut headers = HeaderMap::new();
     headers.insert("X-MBX-APIKEY", HeaderValue::from_str(api_key).unwrap());
@@ -462,6 +463,11 @@ pub async fn get_listen_key(api_key: &str, secret_key: &str) -> Result<String, B
     }
 }
 
+/// Binance's time rather than the local one, requests stamped too far off its clock get rejected with -1021
+fn timestamp() -> i64 {
+    clock::now(Exchange::BinanceFutures)
+}
+
 fn sign_params(params: &str, secret_key: &str) -> String {
     type HmacSha256 = Hmac<Sha256>;
 
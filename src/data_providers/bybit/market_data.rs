@@ -1,6 +1,7 @@
-use iced::{stream, futures};
-use futures::sink::SinkExt;
-use futures::stream::Stream;
+use std::collections::HashMap;
+
+use iced::futures;
+use futures::future::{BoxFuture, FutureExt};
 
 use serde_json::Value;
 use bytes::Bytes;
@@ -11,7 +12,7 @@ use sonic_rs::to_object_iter_unchecked;
 use anyhow::anyhow;
 use anyhow::{Context, Result};
 
-use fastwebsockets::{Frame, FragmentCollector, OpCode};
+use fastwebsockets::{Frame, FragmentCollector};
 use http_body_util::Empty;
 use hyper::header::{CONNECTION, UPGRADE};
 use hyper::upgrade::Upgraded;
@@ -21,32 +22,34 @@ use tokio::net::TcpStream;
 use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor};
 use tokio_rustls::TlsConnector;
 
-use crate::data_providers::{Depth, FeedLatency, Kline, LocalDepthCache, Order, Trade};
+use crate::data_providers::{
+    clock, ConnectionState, DepthResolution, DepthUpdate, Event, Exchange, FeedLatency, Kline, Liquidation, LocalDepthCache, MarketStats, MarketType, Order, Price, StreamType, Trade,
+    connection::{self, Feed, Socket},
+};
 use crate::{Ticker, Timeframe};
 
-#[allow(clippy::large_enum_variant)]
-enum State {
-    Disconnected,
-    Connected(
-        FragmentCollector<TokioIo<Upgraded>>
-    ),
+fn category(exchange: Exchange) -> &'static str {
+    match exchange.market_type() {
+        MarketType::Spot => "spot",
+        MarketType::LinearPerps => "linear",
+        MarketType::InversePerps => "inverse",
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum Event {
-    Connected(Connection),
-    Disconnected(String),
-    DepthReceived(Ticker, FeedLatency, i64, Depth, Vec<Trade>),
-    KlineReceived(Ticker, Kline, Timeframe),
+/// Inverse contracts are sized in USD, converts them into the base coin like the other categories
+fn base_qty(exchange: Exchange, price: Price, qty: f32) -> f32 {
+    match exchange.market_type() {
+        MarketType::InversePerps if price > Price::default() => qty / price.to_f32(),
+        _ => qty,
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct Connection;
-
 #[derive(Serialize, Deserialize, Debug)]
-struct SonicDepth {
+pub struct SonicDepth {
 	#[serde(rename = "u")]
 	pub update_id: u64,
+	#[serde(rename = "seq", default)]
+	pub seq: u64,
 	#[serde(rename = "b")]
 	pub bids: Vec<BidAsk>,
 	#[serde(rename = "a")]
@@ -54,7 +57,7 @@ struct SonicDepth {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct BidAsk {
+pub struct BidAsk {
 	#[serde(rename = "0")]
 	pub price: String,
 	#[serde(rename = "1")]
@@ -62,7 +65,7 @@ struct BidAsk {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct SonicTrade {
+pub struct SonicTrade {
 	#[serde(rename = "T")]
 	pub time: u64,
 	#[serde(rename = "p")]
@@ -87,22 +90,56 @@ pub struct SonicKline {
     pub close: String,
     #[serde(rename = "volume")]
     pub volume: String,
+    #[serde(rename = "turnover")]
+    pub turnover: String,
     #[serde(rename = "interval")]
     pub interval: String,
 }
 
+/// Snapshot first, then deltas that only carry the fields that changed
+#[derive(Deserialize, Debug)]
+pub struct SonicTicker {
+    #[serde(rename = "markPrice")]
+    mark_price: Option<String>,
+    #[serde(rename = "indexPrice")]
+    index_price: Option<String>,
+    #[serde(rename = "fundingRate")]
+    funding_rate: Option<String>,
+    #[serde(rename = "nextFundingTime")]
+    next_funding_time: Option<String>,
+    #[serde(rename = "openInterest")]
+    open_interest: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SonicLiquidation {
+    #[serde(rename = "T")]
+    time: i64,
+    // side of the liquidated position, not of the order closing it
+    #[serde(rename = "S")]
+    position_side: String,
+    #[serde(rename = "v")]
+    qty: String,
+    #[serde(rename = "p")]
+    price: String,
+}
+
 #[derive(Debug)]
-enum StreamData {
+pub enum StreamData {
 	Trade(Vec<SonicTrade>),
 	Depth(SonicDepth, String, i64),
-    Kline(Ticker, Vec<SonicKline>),
+    Kline(Vec<SonicKline>),
+    Ticker(SonicTicker, String, i64),
+    Liquidation(Vec<SonicLiquidation>),
 }
 
 #[derive(Debug)]
 enum StreamName {
-    Depth(Ticker),
-    Trade(Ticker),
-    Kline(Ticker),
+    Depth,
+    Trade,
+    Kline,
+    Ticker,
+    Liquidation,
     Unknown,
 }
 impl StreamName {
@@ -111,31 +148,24 @@ impl StreamName {
             match *first {
                 "publicTrade" => {
                     match rest {
-                        [ticker] if *ticker == "BTCUSDT" => StreamName::Trade(Ticker::BTCUSDT),
-                        [ticker] if *ticker == "ETHUSDT" => StreamName::Trade(Ticker::ETHUSDT),
-                        [ticker] if *ticker == "SOLUSDT" => StreamName::Trade(Ticker::SOLUSDT),
-                        [ticker] if *ticker == "LTCUSDT" => StreamName::Trade(Ticker::LTCUSDT),
+                        [ticker] if Ticker::is_valid_symbol(ticker) => StreamName::Trade,
                         _ => StreamName::Unknown,
                     }
                 },
                 "orderbook" => {
                     match rest {
-                        [_, ticker] if *ticker == "BTCUSDT" => StreamName::Depth(Ticker::BTCUSDT),
-                        [_, ticker] if *ticker == "ETHUSDT" => StreamName::Depth(Ticker::ETHUSDT),
-                        [_, ticker] if *ticker == "SOLUSDT" => StreamName::Depth(Ticker::SOLUSDT),
-                        [_, ticker] if *ticker == "LTCUSDT" => StreamName::Depth(Ticker::LTCUSDT),
+                        [_, ticker] if Ticker::is_valid_symbol(ticker) => StreamName::Depth,
                         _ => StreamName::Unknown,
                     }
                 },
                 "kline" => {
                     match rest {
-                        [_, ticker] if *ticker == "BTCUSDT" => StreamName::Kline(Ticker::BTCUSDT),
-                        [_, ticker] if *ticker == "ETHUSDT" => StreamName::Kline(Ticker::ETHUSDT),
-                        [_, ticker] if *ticker == "SOLUSDT" => StreamName::Kline(Ticker::SOLUSDT),
-                        [_, ticker] if *ticker == "LTCUSDT" => StreamName::Kline(Ticker::LTCUSDT),
+                        [_, ticker] if Ticker::is_valid_symbol(ticker) => StreamName::Kline,
                         _ => StreamName::Unknown,
                     }
                 },
+                "tickers" => StreamName::Ticker,
+                "allLiquidation" => StreamName::Liquidation,
                 _ => StreamName::Unknown,
             }
         }).unwrap_or(StreamName::Unknown)
@@ -147,39 +177,46 @@ enum StreamWrapper {
 	Trade,
 	Depth,
     Kline,
+    Ticker,
+    Liquidation,
 }
 
-fn feed_de(bytes: &Bytes) -> Result<StreamData> {
+/// The message along with the topic it came on
+fn feed_de(bytes: &Bytes) -> Result<(String, StreamData)> {
     let mut stream_type: Option<StreamWrapper> = None;
 
     let mut depth_wrap: Option<SonicDepth> = None;
 
+    let mut ticker_wrap: Option<SonicTicker> = None;
+
     let mut data_type: String = String::new();
 
     let iter: sonic_rs::ObjectJsonIter = unsafe { to_object_iter_unchecked(bytes) };
 
-    let mut topic_ticker = Ticker::BTCUSDT;
+    let mut topic = String::new();
 
     for elem in iter {
         let (k, v) = elem.context("Error parsing stream")?;
 
         if k == "topic" {
             if let Some(val) = v.as_str() {
+                topic = val.to_string();
+
                 match StreamName::from_topic(val) {
-                    StreamName::Depth(ticker) => {
+                    StreamName::Depth => {
                         stream_type = Some(StreamWrapper::Depth);
-
-                        topic_ticker = ticker;
                     },
-                    StreamName::Trade(ticker) => {
+                    StreamName::Trade => {
                         stream_type = Some(StreamWrapper::Trade);
-
-                        topic_ticker = ticker;
                     },
-                    StreamName::Kline(ticker) => {
+                    StreamName::Kline => {
                         stream_type = Some(StreamWrapper::Kline);
-
-                        topic_ticker = ticker;
+                    },
+                    StreamName::Ticker => {
+                        stream_type = Some(StreamWrapper::Ticker);
+                    },
+                    StreamName::Liquidation => {
+                        stream_type = Some(StreamWrapper::Liquidation);
                     },
                     _ => {
                         log::error!("Unknown stream name");
@@ -194,12 +231,13 @@ fn feed_de(bytes: &Bytes) -> Result<StreamData> {
                     let trade_wrap: Vec<SonicTrade> = sonic_rs::from_str(&v.as_raw_faststr())
                         .context("Error parsing trade")?;
 
-                    return Ok(StreamData::Trade(trade_wrap));
+                    return Ok((topic, StreamData::Trade(trade_wrap)));
                 },
                 Some(StreamWrapper::Depth) => {
                     if depth_wrap.is_none() {
                         depth_wrap = Some(SonicDepth {
                             update_id: 0,
+                            seq: 0,
                             bids: Vec::new(),
                             asks: Vec::new(),
                         });
@@ -211,7 +249,17 @@ fn feed_de(bytes: &Bytes) -> Result<StreamData> {
                     let kline_wrap: Vec<SonicKline> = sonic_rs::from_str(&v.as_raw_faststr())
                         .context("Error parsing kline")?;
 
-                    return Ok(StreamData::Kline(topic_ticker, kline_wrap));
+                    return Ok((topic, StreamData::Kline(kline_wrap)));
+                },
+                Some(StreamWrapper::Ticker) => {
+                    ticker_wrap = Some(sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing ticker")?);
+                },
+                Some(StreamWrapper::Liquidation) => {
+                    let liquidation_wrap: Vec<SonicLiquidation> = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing liquidation")?;
+
+                    return Ok((topic, StreamData::Liquidation(liquidation_wrap)));
                 },
                 _ => {
                     log::error!("Unknown stream type");
@@ -221,7 +269,13 @@ fn feed_de(bytes: &Bytes) -> Result<StreamData> {
             if let Some(dw) = depth_wrap {
                 let time: u64 = v.as_u64().context("Error parsing time")?;
                 
-                return Ok(StreamData::Depth(dw, data_type.to_string(), time as i64));
+                return Ok((topic, StreamData::Depth(dw, data_type.to_string(), time as i64)));
+            }
+        } else if k == "ts" {
+            if let Some(tw) = ticker_wrap.take() {
+                let time: u64 = v.as_u64().context("Error parsing time")?;
+
+                return Ok((topic, StreamData::Ticker(tw, data_type.to_string(), time as i64)));
             }
         }
     }
@@ -250,20 +304,15 @@ fn tls_connector() -> Result<TlsConnector> {
 	Ok(TlsConnector::from(std::sync::Arc::new(config)))
 }
 
-async fn connect(domain: &str) -> Result<FragmentCollector<TokioIo<Upgraded>>> {
-	let mut addr = String::from(domain);
-    addr.push_str(":443");
+async fn connect(base_url: &str, category: &str) -> Result<FragmentCollector<TokioIo<Upgraded>>> {
+	let base = url::Url::parse(base_url)?;
+	let host = base.host_str().context("websocket url has no host")?;
+	let port = base.port_or_known_default().context("websocket url has no port")?;
+	let addr = format!("{host}:{port}");
 
 	let tcp_stream: TcpStream = TcpStream::connect(&addr).await?;
-	let tls_connector: TlsConnector = tls_connector().unwrap();
-	let domain: tokio_rustls::rustls::ServerName =
-	tokio_rustls::rustls::ServerName::try_from(domain).map_err(|_| {
-		std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid dnsname")
-	})?;
 
-	let tls_stream: tokio_rustls::client::TlsStream<TcpStream> = tls_connector.connect(domain, tcp_stream).await?;
-
-    let url = "wss://stream.bybit.com/v5/public/linear".to_string();
+	let url = format!("{base_url}/v5/public/{category}");
 
 	let req: Request<Empty<Bytes>> = Request::builder()
 	.method("GET")
@@ -278,7 +327,21 @@ async fn connect(domain: &str) -> Result<FragmentCollector<TokioIo<Upgraded>>> {
 	.header("Sec-WebSocket-Version", "13")
 	.body(Empty::<Bytes>::new())?;
 
-	let (ws, _) = fastwebsockets::handshake::client(&SpawnExecutor, req, tls_stream).await?;
+	// plain ws is only there for local mock servers
+	let (ws, _) = if base.scheme() == "wss" {
+		let tls_connector: TlsConnector = tls_connector().unwrap();
+		let domain: tokio_rustls::rustls::ServerName =
+		tokio_rustls::rustls::ServerName::try_from(host).map_err(|_| {
+			std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid dnsname")
+		})?;
+
+		let tls_stream: tokio_rustls::client::TlsStream<TcpStream> = tls_connector.connect(domain, tcp_stream).await?;
+
+		fastwebsockets::handshake::client(&SpawnExecutor, req, tls_stream).await?
+	} else {
+		fastwebsockets::handshake::client(&SpawnExecutor, req, tcp_stream).await?
+	};
+
 	Ok(FragmentCollector::new(ws))
 }
 struct SpawnExecutor;
@@ -293,6 +356,27 @@ where
   }
 }
 
+/// Bybit takes symbols as they're listed, e.g. BTCUSDT
+pub fn symbol(ticker: Ticker) -> String {
+    ticker.as_str().to_string()
+}
+
+// inverse klines have their `volume` in USD, `turnover` is the base coin volume there
+fn volume_field<'a>(exchange: Exchange, volume: &'a str, turnover: &'a str) -> &'a str {
+    match exchange.market_type() {
+        MarketType::InversePerps => turnover,
+        MarketType::Spot | MarketType::LinearPerps => volume,
+    }
+}
+
+// [startTime, open, high, low, close, volume, turnover]
+fn volume_index(exchange: Exchange) -> usize {
+    match exchange.market_type() {
+        MarketType::InversePerps => 6,
+        MarketType::Spot | MarketType::LinearPerps => 5,
+    }
+}
+
 fn str_f32_parse(s: &str) -> f32 {
     s.parse::<f32>().unwrap_or_else(|e| {
         log::error!("Failed to parse float: {}, error: {}", s, e);
@@ -300,250 +384,342 @@ fn str_f32_parse(s: &str) -> f32 {
     })
 }
 
-fn string_to_timeframe(interval: &str) -> Option<Timeframe> {
-    Timeframe::ALL.iter().find(|&tf| tf.to_string() == format!("{}m", interval)).copied()
+fn str_price_parse(s: &str) -> Price {
+    s.parse::<Price>().unwrap_or_else(|e| {
+        log::error!("Failed to parse price: {}, error: {}", s, e);
+        Price::default()
+    })
+}
+
+fn timeframe_to_interval(timeframe: Timeframe) -> &'static str {
+    match timeframe {
+        Timeframe::M1 => "1",
+        Timeframe::M3 => "3",
+        Timeframe::M5 => "5",
+        Timeframe::M15 => "15",
+        Timeframe::M30 => "30",
+        Timeframe::H1 => "60",
+        Timeframe::H4 => "240",
+        Timeframe::D1 => "D",
+    }
+}
+
+fn depth_topic(exchange: Exchange, ticker: Ticker, resolution: Option<DepthResolution>) -> String {
+    let depth_levels = match DepthResolution::resolve(resolution, exchange) {
+        DepthResolution::Levels(levels) => levels,
+        // only ever set from the exchange's options, which are all level counts
+        DepthResolution::UpdateSpeed(_) => 200,
+    };
+
+    format!("orderbook.{depth_levels}.{}", symbol(ticker))
+}
+
+fn kline_topic(ticker: Ticker, timeframe: Timeframe) -> String {
+    format!("kline.{}.{}", timeframe_to_interval(timeframe), symbol(ticker))
 }
 
-pub fn connect_market_stream(ticker: Ticker) -> impl Stream<Item = Event> {
-    stream::channel (
-        100,
-        move |mut output| async move {
-            let mut state: State = State::Disconnected;  
+/// A depth stream's book, kept from the snapshot a subscription starts with and the deltas after it
+#[derive(Default)]
+struct Book {
+    orderbook: LocalDepthCache,
+    /// Cross sequence of the last applied message, only ever increases
+    last_seq: u64,
+    /// Waiting on the snapshot of a resubscribe
+    resyncing: bool,
+    trades: Vec<Trade>,
+    trade_latencies: Vec<i64>,
+}
 
-            let mut trades_buffer: Vec<Trade> = Vec::new();    
+impl Book {
+    fn apply(&mut self, exchange: Exchange, stream: StreamType, de_depth: &SonicDepth, data_type: &str, time: i64, ctx: &mut connection::Context<MarketFeed>) {
+        let StreamType::DepthAndTrades { ticker, resolution, .. } = stream else {
+            return;
+        };
+
+        let depth_latency = clock::now(exchange) - time;
+
+        let depth_update = DepthUpdate {
+            last_update_id: de_depth.update_id as i64,
+            time,
+            bids: de_depth.bids.iter().map(|x| {
+                let price = str_price_parse(&x.price);
+                Order { price, qty: base_qty(exchange, price, str_f32_parse(&x.qty)) }
+            }).collect(),
+            asks: de_depth.asks.iter().map(|x| {
+                let price = str_price_parse(&x.price);
+                Order { price, qty: base_qty(exchange, price, str_f32_parse(&x.qty)) }
+            }).collect(),
+        };
+
+        // `u` of 1 is a snapshot too, sent after a restart on their end
+        if (data_type == "snapshot") || (depth_update.last_update_id == 1) {
+            self.orderbook.fetched(depth_update);
+            self.last_seq = de_depth.seq;
+
+            if self.resyncing {
+                self.resyncing = false;
+                ctx.send(Event::Connection(vec![stream], ConnectionState::Live));
+            }
+        } else if data_type == "delta" {
+            let last_update_id = self.orderbook.get_fetch_id();
 
-            let selected_ticker = ticker;
+            if last_update_id == 0 {
+                // another stream on the topic got the snapshot before this one started
+                if !self.resyncing {
+                    self.resync(stream, depth_topic(exchange, ticker, resolution), ctx);
+                }
+                return;
+            }
 
-            let symbol_str = selected_ticker.get_string().to_uppercase();
-            
-            let stream_1 = format!("publicTrade.{symbol_str}");
-            let stream_2 = format!("orderbook.500.{symbol_str}");
+            let gap = depth_update.last_update_id != last_update_id + 1
+                || de_depth.seq < self.last_seq;
 
-            let mut orderbook: LocalDepthCache = LocalDepthCache::new();
+            if !gap {
+                self.orderbook.update_depth_cache(depth_update);
+                self.last_seq = de_depth.seq;
+            }
 
-            let mut trade_latencies: Vec<i64> = Vec::new();
+            if gap || self.orderbook.is_crossed() {
+                log::warn!(
+                    "Out of sync on {ticker}, expected u {}, got {} (crossed: {}). Resubscribing...",
+                    last_update_id + 1, de_depth.update_id, self.orderbook.is_crossed()
+                );
 
-            loop {
-                match &mut state {
-                    State::Disconnected => {        
-                        let domain: &str = "stream.bybit.com";
+                self.resync(stream, depth_topic(exchange, ticker, resolution), ctx);
+                return;
+            }
 
-                        if let Ok(mut websocket) = connect(domain
-                        )
-                        .await {
-                            let subscribe_message: String = serde_json::json!({
-                                "op": "subscribe",
-                                "args": [stream_1, stream_2]
-                            }).to_string();
-    
-                            if let Err(e) = websocket.write_frame(Frame::text(fastwebsockets::Payload::Borrowed(subscribe_message.as_bytes()))).await {
-                                let _ = output.send(Event::Disconnected(
-                                    format!("Failed subscribing: {}", e)
-                                )).await;
-
-                                continue;
-                            }
-
-                            state = State::Connected(websocket);
-                            let _ = output.send(Event::Connected(Connection)).await; 
-                        } else {
-                            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-                            let _ = output.send(Event::Disconnected(
-                                "Failed to connect to websocket".to_string()
-                            )).await;
-                        }
-                    },
-                    State::Connected(websocket) => {
-                        let feed_latency: FeedLatency;
-
-                        match websocket.read_frame().await {
-                            Ok(msg) => match msg.opcode {
-                                OpCode::Text => {       
-                                    let json_bytes: Bytes = Bytes::from(msg.payload.to_vec());
-
-                                    if let Ok(data) = feed_de(&json_bytes) {
-                                        match data {
-                                            StreamData::Trade(de_trade_vec) => {
-                                                for de_trade in de_trade_vec.iter() {
-                                                    let trade = Trade {
-                                                        time: de_trade.time as i64,
-                                                        is_sell: de_trade.is_sell == "Sell",
-                                                        price: str_f32_parse(&de_trade.price),
-                                                        qty: str_f32_parse(&de_trade.qty),
-                                                    };
-
-                                                    trade_latencies.push(
-                                                        chrono::Utc::now().timestamp_millis() - trade.time
-                                                    );
-
-                                                    trades_buffer.push(trade);
-                                                }                                             
-                                            },
-                                            StreamData::Depth(de_depth, data_type, time) => {                                            
-                                                let depth_latency = chrono::Utc::now().timestamp_millis() - time;
-
-                                                let depth_update = LocalDepthCache {
-                                                    last_update_id: de_depth.update_id as i64,
-                                                    time,
-                                                    bids: de_depth.bids.iter().map(
-                                                        |x| Order { price: str_f32_parse(&x.price), qty: str_f32_parse(&x.qty) }
-                                                    ).collect(),
-                                                    asks: de_depth.asks.iter().map(
-                                                        |x| Order { price: str_f32_parse(&x.price), qty: str_f32_parse(&x.qty) }
-                                                    ).collect(),
-                                                };
-
-                                                if (data_type == "snapshot") || (depth_update.last_update_id == 1) {
-                                                    orderbook.fetched(depth_update);
-
-                                                } else if data_type == "delta" {
-                                                    orderbook.update_depth_cache(depth_update);
-
-                                                    let avg_trade_latency = if !trade_latencies.is_empty() {
-                                                        let avg = trade_latencies.iter().sum::<i64>() / trade_latencies.len() as i64;
-                                                        trade_latencies.clear();
-                                                        Some(avg)
-                                                    } else {
-                                                        None
-                                                    };
-                                                    feed_latency = FeedLatency {
-                                                        time,
-                                                        depth_latency,
-                                                        trade_latency: avg_trade_latency,
-                                                    };
-
-                                                    let _ = output.send(
-                                                        Event::DepthReceived(
-                                                            selected_ticker,
-                                                            feed_latency,
-                                                            time, 
-                                                            orderbook.get_depth(),
-                                                            std::mem::take(&mut trades_buffer)
-                                                        )
-                                                    ).await;
-                                                }
-                                            },
-                                            _ => {
-                                                log::warn!("Unknown data: {:?}", &data);
-                                            }
-                                        }
-                                    }
-                                }
-                                OpCode::Close => {
-                                    state = State::Disconnected;
-                                    let _ = output.send(
-                                        Event::Disconnected("Connection closed".to_string())
-                                    ).await;
-                                }
-                                _ => {}
-                            },
-                            Err(e) => {
-                                state = State::Disconnected;        
-                                let _ = output.send(
-                                    Event::Disconnected("Error reading frame: ".to_string() + &e.to_string())
-                                ).await;
-                            }
-                        }
-                    }
+            let avg_trade_latency = if !self.trade_latencies.is_empty() {
+                let avg = self.trade_latencies.iter().sum::<i64>() / self.trade_latencies.len() as i64;
+                self.trade_latencies.clear();
+                Some(avg)
+            } else {
+                None
+            };
+            let feed_latency = FeedLatency {
+                time,
+                depth_latency,
+                trade_latency: avg_trade_latency,
+            };
+
+            ctx.send(
+                Event::DepthReceived(
+                    stream,
+                    feed_latency,
+                    time,
+                    self.orderbook.get_depth(),
+                    std::mem::take(&mut self.trades)
+                )
+            );
+        }
+    }
+
+    // a fresh subscription starts over with a snapshot
+    fn resync(&mut self, stream: StreamType, topic: String, ctx: &mut connection::Context<MarketFeed>) {
+        self.orderbook = LocalDepthCache::new();
+        self.resyncing = true;
+
+        ctx.resubscribe(topic);
+        ctx.send(Event::Connection(vec![stream], ConnectionState::Resyncing));
+    }
+}
+
+enum StreamState {
+    Depth(Book),
+    /// Deltas are merged into this, it only goes out once a snapshot filled it in
+    Stats(Option<MarketStats>),
+    Kline,
+    Liquidations,
+}
+
+/// Every stream of one of the exchange's categories over a single public connection
+pub struct MarketFeed {
+    exchange: Exchange,
+    streams: HashMap<StreamType, StreamState>,
+}
+
+impl MarketFeed {
+    pub fn new(exchange: Exchange) -> Self {
+        Self {
+            exchange,
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl Feed for MarketFeed {
+    type Topic = String;
+    type Message = StreamData;
+    // everything comes over the socket
+    type Fetched = std::convert::Infallible;
+
+    // bybit drops connections that don't ping, 20s is what they recommend
+    const PING_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(20);
+
+    fn exchange(&self) -> Exchange {
+        self.exchange
+    }
+
+    fn ping() -> Frame<'static> {
+        Frame::text(fastwebsockets::Payload::Borrowed(br#"{"op":"ping"}"#))
+    }
+
+    fn topics(&self, stream: &StreamType) -> Vec<String> {
+        match *stream {
+            StreamType::DepthAndTrades { ticker, resolution, .. } => vec![
+                format!("publicTrade.{}", symbol(ticker)),
+                depth_topic(self.exchange, ticker, resolution),
+            ],
+            StreamType::Kline { ticker, timeframe, .. } => vec![kline_topic(ticker, timeframe)],
+            StreamType::MarketStats { ticker, .. } => vec![format!("tickers.{}", symbol(ticker))],
+            // every liquidation of the symbol, the older `liquidation` topic only pushed one per second
+            StreamType::Liquidations { ticker, .. } => vec![format!("allLiquidation.{}", symbol(ticker))],
+            StreamType::None => vec![],
+        }
+    }
+
+    fn connect(&self, topics: Vec<String>) -> BoxFuture<'static, Result<Socket>> {
+        let base_url = self.exchange.base_urls().ws;
+        let category = category(self.exchange);
+        let messages = self.subscription_messages(&topics, true);
+
+        async move {
+            let mut websocket = connect(&base_url, category).await?;
+
+            for message in messages {
+                websocket.write_frame(Frame::text(fastwebsockets::Payload::Borrowed(message.as_bytes()))).await
+                    .context("Failed subscribing")?;
+            }
+
+            Ok(websocket)
+        }
+        .boxed()
+    }
+
+    // a request takes at most 10 topics
+    fn subscription_messages(&self, topics: &[String], subscribe: bool) -> Vec<String> {
+        let op = if subscribe { "subscribe" } else { "unsubscribe" };
+
+        topics.chunks(10)
+            .map(|args| serde_json::json!({
+                "op": op,
+                "args": args,
+            }).to_string())
+            .collect()
+    }
+
+    fn parse(&self, payload: &[u8]) -> Option<(String, StreamData)> {
+        feed_de(&Bytes::copy_from_slice(payload)).ok()
+    }
+
+    fn start(&mut self, stream: StreamType, _ctx: &mut connection::Context<Self>) -> ConnectionState {
+        let state = match stream {
+            StreamType::DepthAndTrades { .. } => StreamState::Depth(Book::default()),
+            StreamType::MarketStats { .. } => StreamState::Stats(None),
+            StreamType::Kline { .. } => StreamState::Kline,
+            StreamType::Liquidations { .. } => StreamState::Liquidations,
+            StreamType::None => return ConnectionState::Live,
+        };
+
+        self.streams.insert(stream, state);
+
+        ConnectionState::Live
+    }
+
+    fn stop(&mut self, stream: &StreamType) {
+        self.streams.remove(stream);
+    }
+
+    fn on_message(&mut self, stream: StreamType, message: &StreamData, ctx: &mut connection::Context<Self>) {
+        let exchange = self.exchange;
+
+        match (self.streams.get_mut(&stream), message) {
+            (Some(StreamState::Depth(book)), StreamData::Trade(de_trade_vec)) => {
+                for de_trade in de_trade_vec.iter() {
+                    let price = str_price_parse(&de_trade.price);
+
+                    let trade = Trade {
+                        time: de_trade.time as i64,
+                        is_sell: de_trade.is_sell == "Sell",
+                        price,
+                        qty: base_qty(exchange, price, str_f32_parse(&de_trade.qty)),
+                    };
+
+                    book.trade_latencies.push(
+                        clock::now(exchange) - trade.time
+                    );
+
+                    book.trades.push(trade);
                 }
             }
-        },
-    )
-}
- 
-pub fn connect_kline_stream(streams: Vec<(Ticker, Timeframe)>) -> impl Stream<Item = Event> {
-    stream::channel (
-        100,
-        move |mut output| async move {
-            let mut state = State::Disconnected;    
-
-            let stream_str = streams.iter().map(|(ticker, timeframe)| {
-                let symbol_str = ticker.get_string().to_uppercase();
-                let timeframe_str = match timeframe {
-                    Timeframe::M1 => "1",
-                    Timeframe::M3 => "3",
-                    Timeframe::M5 => "5",
-                    Timeframe::M15 => "15",
-                    Timeframe::M30 => "30",
-                };
-                format!("kline.{timeframe_str}.{symbol_str}")
-            }).collect::<Vec<String>>();
- 
-            loop {
-                match &mut state {
-                    State::Disconnected => {
-                        let domain = "stream.bybit.com";
-                        
-                        if let Ok(mut websocket) = connect(
-                            domain,
-                        )
-                        .await {
-                            let subscribe_message = serde_json::json!({
-                                "op": "subscribe",
-                                "args": stream_str 
-                            }).to_string();
-    
-                            if let Err(e) = websocket.write_frame(Frame::text(fastwebsockets::Payload::Borrowed(subscribe_message.as_bytes()))).await {
-                                let _ = output.send(Event::Disconnected
-                                    (format!("Failed subscribing: {}", e))
-                                ).await;
-
-                                continue;
-                            }
-
-                            state = State::Connected(websocket);
-                            let _ = output.send(Event::Connected(Connection)).await;
-                        } else {
-                            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-
-                            let _ = output.send(Event::Disconnected(
-                                "Failed to connect to websocket".to_string()
-                            )).await;
-                        }
-                    }
-                    State::Connected(websocket) => {
-                        match websocket.read_frame().await {
-                            Ok(msg) => match msg.opcode {
-                                OpCode::Text => {                    
-                                    let json_bytes: Bytes = Bytes::from(msg.payload.to_vec());
-                    
-                                    if let Ok(StreamData::Kline(ticker, de_kline_vec)) = feed_de(&json_bytes) {
-                                        for de_kline in de_kline_vec.iter() {
-                                            let kline = Kline {
-                                                time: de_kline.time,
-                                                open: str_f32_parse(&de_kline.open),
-                                                high: str_f32_parse(&de_kline.high),
-                                                low: str_f32_parse(&de_kline.low),
-                                                close: str_f32_parse(&de_kline.close),
-                                                volume: (-1.0, str_f32_parse(&de_kline.volume)),
-                                            };
-
-                                            if let Some(timeframe) = string_to_timeframe(&de_kline.interval) {
-                                                let _ = output.send(Event::KlineReceived(ticker, kline, timeframe)).await;
-                                            } else {
-                                                log::error!("Failed to find timeframe: {}, {:?}", &de_kline.interval, streams);
-                                            }
-                                        }
-                                         
-                                    } else {
-                                        log::error!("\nUnknown data: {:?}", &json_bytes);
-                                    }
-                                }
-                                _ => {}
-                            },
-                            Err(e) => {   
-                                state = State::Disconnected;             
-                                let _ = output.send(
-                                    Event::Disconnected("Error reading frame: ".to_string() + &e.to_string())
-                                ).await;
-                            }
-                        }
-                    }
+            (Some(StreamState::Depth(book)), StreamData::Depth(de_depth, data_type, time)) => {
+                book.apply(exchange, stream, de_depth, data_type, *time, ctx);
+            }
+            (Some(StreamState::Stats(stats)), StreamData::Ticker(de_ticker, data_type, time)) => {
+                if stats.is_none() && data_type != "snapshot" {
+                    return;
+                }
+
+                let current = stats.get_or_insert_with(MarketStats::default);
+
+                current.time = *time;
+
+                if let Some(mark_price) = &de_ticker.mark_price {
+                    current.mark_price = str_f32_parse(mark_price);
+                }
+                if let Some(index_price) = &de_ticker.index_price {
+                    current.index_price = str_f32_parse(index_price);
+                }
+                if let Some(funding_rate) = &de_ticker.funding_rate {
+                    current.funding_rate = str_f32_parse(funding_rate);
+                }
+                if let Some(next_funding_time) = &de_ticker.next_funding_time {
+                    current.next_funding_time = next_funding_time.parse::<i64>().unwrap_or_default();
+                }
+                if let Some(open_interest) = &de_ticker.open_interest {
+                    current.open_interest = Some(str_f32_parse(open_interest));
+                }
+
+                ctx.send(Event::MarketStatsReceived(stream, *current));
+            }
+            (Some(StreamState::Kline), StreamData::Kline(de_kline_vec)) => {
+                for de_kline in de_kline_vec.iter() {
+                    let kline = Kline {
+                        time: de_kline.time,
+                        open: str_price_parse(&de_kline.open),
+                        high: str_price_parse(&de_kline.high),
+                        low: str_price_parse(&de_kline.low),
+                        close: str_price_parse(&de_kline.close),
+                        volume: (-1.0, str_f32_parse(volume_field(exchange, &de_kline.volume, &de_kline.turnover))),
+                    };
+
+                    ctx.send(Event::KlineReceived(stream, kline));
                 }
             }
-        },
-    )
+            (Some(StreamState::Liquidations), StreamData::Liquidation(de_liquidations)) => {
+                let liquidations = de_liquidations.iter()
+                    .map(|de_liquidation| {
+                        let price = str_price_parse(&de_liquidation.price);
+
+                        Liquidation {
+                            time: de_liquidation.time,
+                            price,
+                            qty: base_qty(exchange, price, str_f32_parse(&de_liquidation.qty)),
+                            // a liquidated long gets sold off
+                            is_sell: de_liquidation.position_side == "Buy",
+                        }
+                    })
+                    .collect();
+
+                ctx.send(Event::LiquidationsReceived(stream, liquidations));
+            }
+            _ => {}
+        }
+    }
+
+    fn on_fetched(&mut self, _stream: StreamType, fetched: Self::Fetched, _ctx: &mut connection::Context<Self>) {
+        match fetched {}
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -562,17 +738,15 @@ struct ApiResult {
     list: Vec<Vec<Value>>,
 }
 
-pub async fn fetch_klines(ticker: Ticker, timeframe: Timeframe) -> Result<Vec<Kline>> {
-    let symbol_str = ticker.get_string().to_uppercase();
-    let timeframe_str = match timeframe {
-        Timeframe::M1 => "1",
-        Timeframe::M3 => "3",
-        Timeframe::M5 => "5",
-        Timeframe::M15 => "15",
-        Timeframe::M30 => "30",
-    };
+pub async fn fetch_klines(exchange: Exchange, ticker: Ticker, timeframe: Timeframe, end_time: Option<i64>) -> Result<Vec<Kline>> {
+    let symbol_str = symbol(ticker);
+    let timeframe_str = timeframe_to_interval(timeframe);
+
+    let mut url: String = format!("{}/v5/market/kline?category={}&symbol={symbol_str}&interval={timeframe_str}&limit=720", exchange.base_urls().rest, category(exchange));
 
-    let url: String = format!("https://api.bybit.com/v5/market/kline?category=linear&symbol={symbol_str}&interval={timeframe_str}&limit=720");
+    if let Some(end_time) = end_time {
+        url.push_str(&format!("&end={end_time}"));
+    }
 
     let response: reqwest::Response = reqwest::get(&url).await
         .context("Failed to send request")?;
@@ -587,18 +761,18 @@ pub async fn fetch_klines(ticker: Ticker, timeframe: Timeframe) -> Result<Vec<Kl
             .and_then(|s| s.parse::<u64>()
             .context("Failed to parse time as u64"));
         let open = kline[1].as_str().ok_or_else(|| anyhow!("Missing open value"))
-            .and_then(|s| s.parse::<f32>()
-            .context("Failed to parse open as f32"));
+            .and_then(|s| s.parse::<Price>()
+            .map_err(|e| anyhow!("Failed to parse open: {e}")));
         let high = kline[2].as_str().ok_or_else(|| anyhow!("Missing high value"))
-            .and_then(|s| s.parse::<f32>()
-            .context("Failed to parse high as f32"));
+            .and_then(|s| s.parse::<Price>()
+            .map_err(|e| anyhow!("Failed to parse high: {e}")));
         let low = kline[3].as_str().ok_or_else(|| anyhow!("Missing low value"))
-            .and_then(|s| s.parse::<f32>()
-            .context("Failed to parse low as f32"));
+            .and_then(|s| s.parse::<Price>()
+            .map_err(|e| anyhow!("Failed to parse low: {e}")));
         let close = kline[4].as_str().ok_or_else(|| anyhow!("Missing close value"))
-            .and_then(|s| s.parse::<f32>()
-            .context("Failed to parse close as f32"));
-        let volume = kline[5].as_str().ok_or_else(|| anyhow!("Missing volume value"))
+            .and_then(|s| s.parse::<Price>()
+            .map_err(|e| anyhow!("Failed to parse close: {e}")));
+        let volume = kline[volume_index(exchange)].as_str().ok_or_else(|| anyhow!("Missing volume value"))
             .and_then(|s| s.parse::<f32>()
             .context("Failed to parse volume as f32"));
     
@@ -615,10 +789,75 @@ pub async fn fetch_klines(ticker: Ticker, timeframe: Timeframe) -> Result<Vec<Kl
     klines
 }
 
-pub async fn fetch_ticksize(ticker: Ticker) -> Result<f32> {
-    let symbol_str = ticker.get_string().to_uppercase();
+/// Bybit has no paginated trade history, only the latest 1000 trades (60 on spot),
+/// so candles older than that are left to the OHLCV data
+pub async fn fetch_trades(exchange: Exchange, ticker: Ticker, from_time: i64, to_time: i64) -> Result<Vec<Trade>> {
+    let symbol_str = symbol(ticker);
+    let limit = match exchange.market_type() {
+        MarketType::Spot => 60,
+        MarketType::LinearPerps | MarketType::InversePerps => 1000,
+    };
+
+    let url = format!("{}/v5/market/recent-trade?category={}&symbol={symbol_str}&limit={limit}", exchange.base_urls().rest, category(exchange));
+
+    let response: reqwest::Response = reqwest::get(&url).await
+        .context("Failed to send request")?;
+    let text: String = response.text().await
+        .context("Failed to read response text")?;
+
+    let recent_trades: Value = sonic_rs::from_str(&text)
+        .context("Failed to parse JSON")?;
+
+    let result_list: &Vec<Value> = recent_trades["result"]["list"].as_array()
+        .context("Result list is not an array")?;
+
+    let mut trades: Vec<Trade> = result_list.iter()
+        .filter_map(|item| {
+            let time = item["time"].as_str()?.parse::<i64>().ok()?;
+            let price = item["price"].as_str()?.parse::<Price>().ok()?;
+            let qty = item["size"].as_str()?.parse::<f32>().ok()?;
+
+            Some(Trade {
+                time,
+                is_sell: item["side"] == "Sell",
+                price,
+                qty: base_qty(exchange, price, qty),
+            })
+        })
+        .filter(|trade| trade.time >= from_time && trade.time < to_time)
+        .collect();
+
+    // newest first on the wire
+    trades.reverse();
+
+    Ok(trades)
+}
+
+/// The exchange's clock in milliseconds
+pub async fn fetch_server_time(exchange: Exchange) -> Result<i64> {
+    let url = format!("{}/v5/market/time", exchange.base_urls().rest);
+
+    let response: reqwest::Response = reqwest::get(&url).await
+        .context("Failed to send request")?;
+    let text: String = response.text().await
+        .context("Failed to read response text")?;
+
+    let server_time: Value = sonic_rs::from_str(&text)
+        .context("Failed to parse JSON")?;
+
+    let time_nano: &str = server_time["result"]["timeNano"].as_str()
+        .context("Server time not found")?;
+
+    let time_nano = time_nano.parse::<i64>()
+        .context("Failed to parse server time")?;
+
+    Ok(time_nano / 1_000_000)
+}
+
+pub async fn fetch_ticksize(exchange: Exchange, ticker: Ticker) -> Result<Price> {
+    let symbol_str = symbol(ticker);
 
-    let url = format!("https://api.bybit.com/v5/market/instruments-info?category=linear&symbol={}", symbol_str);
+    let url = format!("{}/v5/market/instruments-info?category={}&symbol={}", exchange.base_urls().rest, category(exchange), symbol_str);
 
     let response: reqwest::Response = reqwest::get(&url).await
         .context("Failed to send request")?;
@@ -639,10 +878,110 @@ pub async fn fetch_ticksize(ticker: Ticker) -> Result<f32> {
             let tick_size_str: &str = price_filter.get("tickSize").context("Tick size not found")?.as_str()
                 .context("Tick size is not a string")?;
 
-            return tick_size_str.parse::<f32>()
-                .context("Failed to parse tick size");
+            return tick_size_str.parse::<Price>()
+                .map_err(|e| anyhow!("Failed to parse tick size: {e}"));
         }
     }
 
     anyhow::bail!("Tick size not found for symbol {}", symbol_str)
-}
\ No newline at end of file
+}
+
+/// Fetches every spot pair or perpetual contract that is currently trading in the given category
+pub async fn fetch_ticker_list(exchange: Exchange) -> Result<Vec<Ticker>> {
+    let mut tickers: Vec<Ticker> = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let mut url = format!("{}/v5/market/instruments-info?category={}&limit=1000", exchange.base_urls().rest, category(exchange));
+
+        if let Some(cursor) = &cursor {
+            url.push_str(&format!("&cursor={cursor}"));
+        }
+
+        let response: reqwest::Response = reqwest::get(&url).await
+            .context("Failed to send request")?;
+        let text: String = response.text().await
+            .context("Failed to read response text")?;
+
+        let exchange_info: Value = sonic_rs::from_str(&text)
+            .context("Failed to parse JSON")?;
+
+        let result_list: &Vec<Value> = exchange_info["result"]["list"].as_array()
+            .context("Result list is not an array")?;
+
+        tickers.extend(
+            result_list.iter()
+                .filter(|item| item["status"] == "Trading")
+                .filter(|item| match exchange.market_type() {
+                    MarketType::Spot => true,
+                    MarketType::LinearPerps => item["contractType"] == "LinearPerpetual",
+                    MarketType::InversePerps => item["contractType"] == "InversePerpetual",
+                })
+                .filter_map(|item| item["symbol"].as_str())
+                .filter(|symbol| Ticker::is_valid_symbol(symbol))
+                .map(Ticker::new)
+        );
+
+        match exchange_info["result"]["nextPageCursor"].as_str() {
+            Some(next_cursor) if !next_cursor.is_empty() => cursor = Some(next_cursor.to_string()),
+            _ => break,
+        }
+    }
+
+    tickers.sort();
+
+    Ok(tickers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_providers::mock::{next_event, MockServer, Script, Step};
+
+    const SNAPSHOT: &str = r#"{"topic":"orderbook.500.BTCUSDT","type":"snapshot","ts":1718095353114,"data":{"s":"BTCUSDT","b":[["67000.0","1.5"]],"a":[["67000.1","2"]],"u":1000,"seq":5000},"cts":1718095353110}"#;
+    const DELTA: &str = r#"{"topic":"orderbook.500.BTCUSDT","type":"delta","ts":1718095353214,"data":{"s":"BTCUSDT","b":[["67000.0","3"]],"a":[],"u":1001,"seq":5001},"cts":1718095353210}"#;
+    // skips u 1002 to 1004
+    const GAPPED_DELTA: &str = r#"{"topic":"orderbook.500.BTCUSDT","type":"delta","ts":1718095353314,"data":{"s":"BTCUSDT","b":[],"a":[["67000.1","1"]],"u":1005,"seq":5009},"cts":1718095353310}"#;
+
+    #[tokio::test]
+    async fn resubscribes_on_sequence_gap() {
+        let exchange = Exchange::BybitLinear;
+
+        let mock = MockServer::start(
+            Script::new().session(vec![
+                Step::Receive,
+                Step::Send(SNAPSHOT.to_string()),
+                Step::Send(DELTA.to_string()),
+                Step::Send(GAPPED_DELTA.to_string()),
+                Step::Receive,
+                Step::Receive,
+                Step::Send(SNAPSHOT.to_string()),
+            ])
+        ).await;
+        mock.serve(exchange);
+
+        let stream = StreamType::DepthAndTrades { exchange, ticker: Ticker::new("BTCUSDT"), resolution: None };
+        let streams = tokio::sync::watch::Sender::new(std::collections::HashSet::from([stream]));
+        let mut events = Box::pin(connection::connect(MarketFeed::new(exchange), streams.subscribe()));
+
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Connecting)));
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Live)));
+
+        match next_event(&mut events).await {
+            Event::DepthReceived(_, _, time, depth, _) => {
+                assert_eq!(time, 1718095353210);
+                assert_eq!(depth.best_bid().map(|order| order.qty), Some(3.0));
+            }
+            other => panic!("expected depth, got {other:?}"),
+        }
+
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Resyncing)));
+        assert!(matches!(next_event(&mut events).await, Event::Connection(_, ConnectionState::Live)));
+
+        let received = mock.received();
+        assert!(received[0].contains(r#""op":"subscribe""#));
+        assert!(received[1].contains(r#""op":"unsubscribe""#) && received[1].contains("orderbook.500.BTCUSDT"));
+        assert!(received[2].contains(r#""op":"subscribe""#));
+        assert_eq!(mock.connections(), 1);
+    }
+}
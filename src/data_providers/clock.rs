@@ -0,0 +1,125 @@
+//! Offset of each exchange's clock from the local one, so feed latencies and signed request
+//! timestamps hold up on a machine whose clock drifts
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+
+use super::{binance, bybit, Exchange};
+
+/// Exchanges whose server time gets polled, every other market shares one of their clocks
+pub const SOURCES: [Exchange; 3] = [Exchange::BinanceFutures, Exchange::BinanceSpot, Exchange::BybitLinear];
+
+/// How often `sync` should be called for each source
+pub const SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The clock a market's timestamps come from, None for those that aren't polled
+pub fn source(exchange: Exchange) -> Option<Exchange> {
+    match exchange {
+        Exchange::BinanceFutures | Exchange::AggregatedLinear => Some(Exchange::BinanceFutures),
+        Exchange::BinanceSpot => Some(Exchange::BinanceSpot),
+        Exchange::BybitLinear | Exchange::BybitInverse | Exchange::BybitSpot => Some(Exchange::BybitLinear),
+        Exchange::OkxSwap => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    round_trip: i64,
+    offset: i64,
+}
+
+/// The last few measurements of a clock, trusting the one with the shortest round trip
+/// since the server's timestamp is then pinned down the closest
+#[derive(Debug, Default)]
+struct OffsetEstimate {
+    samples: VecDeque<Sample>,
+}
+
+impl OffsetEstimate {
+    const MAX_SAMPLES: usize = 8;
+
+    fn insert(&mut self, sample: Sample) {
+        self.samples.push_back(sample);
+
+        while self.samples.len() > Self::MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    fn offset(&self) -> Option<i64> {
+        self.samples.iter()
+            .min_by_key(|sample| sample.round_trip)
+            .map(|sample| sample.offset)
+    }
+}
+
+static ESTIMATES: RwLock<Option<HashMap<Exchange, OffsetEstimate>>> = RwLock::new(None);
+
+/// Milliseconds the exchange's clock is ahead of the local one, None until it has been measured
+pub fn offset(exchange: Exchange) -> Option<i64> {
+    let source = source(exchange)?;
+
+    ESTIMATES.read()
+        .ok()
+        .and_then(|estimates| estimates.as_ref()?.get(&source)?.offset())
+}
+
+/// Current time in milliseconds on the exchange's clock, the local time if it hasn't been measured
+pub fn now(exchange: Exchange) -> i64 {
+    chrono::Utc::now().timestamp_millis() + offset(exchange).unwrap_or(0)
+}
+
+/// Measures the source's clock once, taking its server time as of halfway through the round trip,
+/// and returns the updated offset
+pub async fn sync(source: Exchange) -> Result<i64, String> {
+    let sent = chrono::Utc::now().timestamp_millis();
+
+    let server_time = match source {
+        Exchange::BinanceFutures | Exchange::BinanceSpot => binance::market_data::fetch_server_time(source).await
+            .map_err(|err| err.to_string())?,
+        Exchange::BybitLinear => bybit::market_data::fetch_server_time(source).await
+            .map_err(|err| err.to_string())?,
+        _ => return Err(format!("{source} has no server time to sync with")),
+    };
+
+    let received = chrono::Utc::now().timestamp_millis();
+
+    let sample = Sample {
+        round_trip: received - sent,
+        offset: server_time - (sent + received) / 2,
+    };
+
+    let mut estimates = ESTIMATES.write()
+        .map_err(|_| "Clock estimates lock poisoned".to_string())?;
+
+    let estimate = estimates.get_or_insert_with(HashMap::new)
+        .entry(source)
+        .or_default();
+
+    estimate.insert(sample);
+
+    Ok(estimate.offset().unwrap_or(sample.offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trusts_shortest_round_trip() {
+        let mut estimate = OffsetEstimate::default();
+
+        estimate.insert(Sample { round_trip: 300, offset: 900 });
+        estimate.insert(Sample { round_trip: 40, offset: 1200 });
+        estimate.insert(Sample { round_trip: 120, offset: 1100 });
+
+        assert_eq!(estimate.offset(), Some(1200));
+
+        // the best sample ages out after enough newer ones
+        for _ in 0..OffsetEstimate::MAX_SAMPLES {
+            estimate.insert(Sample { round_trip: 80, offset: 1150 });
+        }
+
+        assert_eq!(estimate.offset(), Some(1150));
+    }
+}
@@ -0,0 +1,374 @@
+//! One long-lived websocket per exchange carrying every stream the panes want. Streams get
+//! subscribed and unsubscribed on the open socket as panes come and go, exchanges only
+//! describe their topics and messages through `Feed`
+
+use std::collections::{HashMap, HashSet};
+
+use fastwebsockets::{Frame, FragmentCollector, OpCode};
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures::sink::SinkExt;
+use futures::stream::{FuturesUnordered, Stream, StreamExt};
+use futures::FutureExt;
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use iced::stream;
+
+use super::{throughput, Backoff, ConnectionState, Event, Exchange, Heartbeat, StreamType, SubscribedStreams};
+
+pub type Socket = FragmentCollector<TokioIo<Upgraded>>;
+
+/// An exchange's websocket API, and whatever its streams keep between messages
+pub trait Feed: Send + Sized + 'static {
+    /// A channel on the socket. Streams needing the same one share it, e.g. the trades of two depth resolutions
+    type Topic: Clone + Eq + std::hash::Hash + Send + Sync + std::fmt::Debug;
+    /// A text frame parsed, handed to every stream on its topic
+    type Message;
+    /// What a request made off the socket for a stream comes back with, e.g. a depth snapshot
+    type Fetched: Send + 'static;
+
+    const PING_INTERVAL: std::time::Duration;
+
+    fn exchange(&self) -> Exchange;
+
+    /// The exchange's ping, a ping frame or a text message depending on the venue
+    fn ping() -> Frame<'static>;
+
+    /// Whether the stream goes over this connection, for exchanges serving some streams on another endpoint
+    fn carries(&self, _stream: &StreamType) -> bool {
+        true
+    }
+
+    fn topics(&self, stream: &StreamType) -> Vec<Self::Topic>;
+
+    /// Opens a socket already subscribed to `topics`
+    fn connect(&self, topics: Vec<Self::Topic>) -> BoxFuture<'static, anyhow::Result<Socket>>;
+
+    /// Requests subscribing or unsubscribing `topics` on an open socket
+    fn subscription_messages(&self, topics: &[Self::Topic], subscribe: bool) -> Vec<String>;
+
+    /// The topic of a text frame and what it says, `None` for pongs and replies to our requests
+    fn parse(&self, payload: &[u8]) -> Option<(Self::Topic, Self::Message)>;
+
+    /// Sets up a stream whose topics were just subscribed, returning the state it starts in
+    fn start(&mut self, stream: StreamType, ctx: &mut Context<Self>) -> ConnectionState;
+
+    fn stop(&mut self, stream: &StreamType);
+
+    fn on_message(&mut self, stream: StreamType, message: &Self::Message, ctx: &mut Context<Self>);
+
+    fn on_fetched(&mut self, stream: StreamType, fetched: Self::Fetched, ctx: &mut Context<Self>);
+}
+
+/// What a feed asks of the connection while handling a stream
+pub struct Context<F: Feed> {
+    events: Vec<Event>,
+    fetches: Vec<(StreamType, BoxFuture<'static, F::Fetched>)>,
+    resubscribe: Vec<F::Topic>,
+}
+
+impl<F: Feed> Context<F> {
+    fn new() -> Self {
+        Self {
+            events: vec![],
+            fetches: vec![],
+            resubscribe: vec![],
+        }
+    }
+
+    pub fn send(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Runs `fetch` alongside the socket, what it returns goes to `Feed::on_fetched` unless the stream was stopped by then
+    pub fn fetch(&mut self, stream: StreamType, fetch: BoxFuture<'static, F::Fetched>) {
+        self.fetches.push((stream, fetch));
+    }
+
+    /// Unsubscribes and subscribes the topic again, for exchanges that start a subscription with a snapshot
+    pub fn resubscribe(&mut self, topic: F::Topic) {
+        self.resubscribe.push(topic);
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State {
+    Disconnected,
+    Connected(Socket),
+}
+
+enum Step<F: Feed> {
+    Read(Result<Option<Frame<'static>>, String>),
+    StreamsChanged,
+    Fetched(StreamType, u64, F::Fetched),
+}
+
+/// Carries every stream in `streams` that the feed is for, subscribing and unsubscribing as it changes
+pub fn connect<F: Feed>(feed: F, mut streams: SubscribedStreams) -> impl Stream<Item = Event> {
+    stream::channel (
+        100,
+        move |output| async move {
+            let mut heartbeat = Heartbeat::new(F::PING_INTERVAL, feed.exchange().stale_feed_timeout(), F::ping);
+            let mut backoff = Backoff::default();
+            let mut state = State::Disconnected;
+
+            let mut connection = Connection {
+                feed,
+                output,
+                streams: HashSet::new(),
+                topics: HashMap::new(),
+                generations: HashMap::new(),
+                generation: 0,
+                fetches: FuturesUnordered::new(),
+            };
+
+            loop {
+                match &mut state {
+                    State::Disconnected => {
+                        // nothing to carry, e.g. the exchange's last pane was just closed
+                        if connection.wanted(&mut streams).is_empty() {
+                            if streams.changed().await.is_err() {
+                                return;
+                            }
+                            continue;
+                        }
+
+                        backoff.wait().await;
+
+                        let wanted = connection.wanted(&mut streams);
+                        connection.follow(wanted);
+
+                        connection.send_states(
+                            connection.streams.iter().map(|stream| (*stream, ConnectionState::Connecting)).collect()
+                        ).await;
+
+                        let topics = connection.topics.keys().cloned().collect();
+
+                        let connected = match connection.feed.connect(topics).await {
+                            Ok(mut socket) => connection.start_all(&mut socket).await.map(|()| socket),
+                            Err(e) => Err(format!("Failed to connect to websocket: {e}")),
+                        };
+
+                        match connected {
+                            Ok(socket) => {
+                                state = State::Connected(socket);
+                                backoff.connected();
+                                heartbeat.reset();
+                            }
+                            Err(reason) => connection.disconnect(reason).await,
+                        }
+                    }
+                    State::Connected(socket) => {
+                        let step = tokio::select! {
+                            read = heartbeat.read_frame(socket) => Step::<F>::Read(read),
+                            Ok(()) = streams.changed() => Step::StreamsChanged,
+                            Some((stream, generation, fetched)) = connection.fetches.next() => Step::Fetched(stream, generation, fetched),
+                        };
+
+                        let result = match step {
+                            Step::Read(Ok(Some(frame))) => connection.on_frame(socket, frame).await,
+                            Step::Read(Ok(None)) => Ok(()),
+                            Step::Read(Err(reason)) => Err(reason),
+                            Step::StreamsChanged => {
+                                let wanted = connection.wanted(&mut streams);
+                                connection.update(socket, wanted).await
+                            }
+                            Step::Fetched(stream, generation, fetched) => connection.on_fetched(socket, stream, generation, fetched).await,
+                        };
+
+                        if let Err(reason) = result {
+                            state = State::Disconnected;
+                            connection.disconnect(reason).await;
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+type Fetch<F> = BoxFuture<'static, (StreamType, u64, <F as Feed>::Fetched)>;
+
+struct Connection<F: Feed> {
+    feed: F,
+    output: mpsc::Sender<Event>,
+    /// What the socket carries, catches up with the wanted streams without reconnecting
+    streams: HashSet<StreamType>,
+    topics: HashMap<F::Topic, Vec<StreamType>>,
+    /// Bumped each time a stream starts, so what it fetched before it was stopped gets dropped
+    generations: HashMap<StreamType, u64>,
+    generation: u64,
+    fetches: FuturesUnordered<Fetch<F>>,
+}
+
+impl<F: Feed> Connection<F> {
+    fn wanted(&self, streams: &mut SubscribedStreams) -> HashSet<StreamType> {
+        streams.borrow_and_update().iter()
+            .filter(|stream| self.feed.carries(stream))
+            .copied()
+            .collect()
+    }
+
+    fn follow(&mut self, streams: HashSet<StreamType>) {
+        let mut topics: HashMap<F::Topic, Vec<StreamType>> = HashMap::new();
+
+        for stream in &streams {
+            for topic in self.feed.topics(stream) {
+                topics.entry(topic).or_default().push(*stream);
+            }
+        }
+
+        self.streams = streams;
+        self.topics = topics;
+    }
+
+    fn start(&mut self, stream: StreamType, ctx: &mut Context<F>) -> ConnectionState {
+        self.generation += 1;
+        self.generations.insert(stream, self.generation);
+
+        self.feed.start(stream, ctx)
+    }
+
+    /// Starts every stream on a socket that was just opened
+    async fn start_all(&mut self, socket: &mut Socket) -> Result<(), String> {
+        let mut ctx = Context::new();
+
+        let streams: Vec<StreamType> = self.streams.iter().copied().collect();
+        let states = streams.into_iter()
+            .map(|stream| (stream, self.start(stream, &mut ctx)))
+            // every stream was reported connecting before the socket opened
+            .filter(|(_, state)| *state != ConnectionState::Connecting)
+            .collect();
+
+        self.send_states(states).await;
+        self.flush(socket, ctx).await
+    }
+
+    /// Subscribes the topics only `wanted` needs and unsubscribes the ones nothing needs anymore
+    async fn update(&mut self, socket: &mut Socket, wanted: HashSet<StreamType>) -> Result<(), String> {
+        for stream in self.streams.difference(&wanted) {
+            self.feed.stop(stream);
+            self.generations.remove(stream);
+        }
+
+        let added: Vec<StreamType> = wanted.difference(&self.streams).copied().collect();
+        let before: HashSet<F::Topic> = self.topics.keys().cloned().collect();
+
+        self.follow(wanted);
+
+        let after: HashSet<F::Topic> = self.topics.keys().cloned().collect();
+
+        let subscribe: Vec<F::Topic> = after.difference(&before).cloned().collect();
+        let unsubscribe: Vec<F::Topic> = before.difference(&after).cloned().collect();
+
+        for (topics, subscribing) in [(subscribe, true), (unsubscribe, false)] {
+            if !topics.is_empty() {
+                write(socket, self.feed.subscription_messages(&topics, subscribing)).await
+                    .map_err(|e| format!("Failed updating subscriptions: {e}"))?;
+            }
+        }
+
+        let mut ctx = Context::new();
+
+        let states = added.into_iter()
+            .map(|stream| (stream, self.start(stream, &mut ctx)))
+            .collect();
+
+        self.send_states(states).await;
+        self.flush(socket, ctx).await
+    }
+
+    async fn on_frame(&mut self, socket: &mut Socket, frame: Frame<'static>) -> Result<(), String> {
+        if frame.opcode != OpCode::Text {
+            return Ok(());
+        }
+
+        let Some((topic, message)) = self.feed.parse(&frame.payload) else {
+            log::debug!("\nUnknown data: {:?}", String::from_utf8_lossy(&frame.payload));
+            return Ok(());
+        };
+
+        let mut ctx = Context::new();
+
+        for stream in self.topics.get(&topic).cloned().unwrap_or_default() {
+            throughput::record(stream, frame.payload.len());
+
+            self.feed.on_message(stream, &message, &mut ctx);
+        }
+
+        self.flush(socket, ctx).await
+    }
+
+    async fn on_fetched(&mut self, socket: &mut Socket, stream: StreamType, generation: u64, fetched: F::Fetched) -> Result<(), String> {
+        if self.generations.get(&stream) != Some(&generation) {
+            return Ok(());
+        }
+
+        let mut ctx = Context::new();
+        self.feed.on_fetched(stream, fetched, &mut ctx);
+
+        self.flush(socket, ctx).await
+    }
+
+    /// Carries out what the feed asked for
+    async fn flush(&mut self, socket: &mut Socket, ctx: Context<F>) -> Result<(), String> {
+        for (stream, fetch) in ctx.fetches {
+            if let Some(generation) = self.generations.get(&stream).copied() {
+                self.fetches.push(fetch.map(move |fetched| (stream, generation, fetched)).boxed());
+            }
+        }
+
+        if !ctx.resubscribe.is_empty() {
+            let messages = [false, true].into_iter()
+                .flat_map(|subscribing| self.feed.subscription_messages(&ctx.resubscribe, subscribing))
+                .collect();
+
+            write(socket, messages).await
+                .map_err(|e| format!("Failed resubscribing: {e}"))?;
+        }
+
+        for event in ctx.events {
+            let _ = self.output.send(event).await;
+        }
+
+        Ok(())
+    }
+
+    /// Reports every stream down and forgets them, the next connection starts them over
+    async fn disconnect(&mut self, reason: String) {
+        let streams: Vec<StreamType> = self.streams.drain().collect();
+
+        for stream in &streams {
+            self.feed.stop(stream);
+        }
+        self.topics.clear();
+        self.generations.clear();
+        self.fetches = FuturesUnordered::new();
+
+        self.send_states(streams.into_iter().map(|stream| (stream, ConnectionState::Down(reason.clone()))).collect()).await;
+    }
+
+    /// One event per distinct state
+    async fn send_states(&mut self, states: Vec<(StreamType, ConnectionState)>) {
+        let mut grouped: Vec<(ConnectionState, Vec<StreamType>)> = vec![];
+
+        for (stream, state) in states {
+            match grouped.iter_mut().find(|(grouped_state, _)| *grouped_state == state) {
+                Some((_, streams)) => streams.push(stream),
+                None => grouped.push((state, vec![stream])),
+            }
+        }
+
+        for (state, streams) in grouped {
+            let _ = self.output.send(Event::Connection(streams, state)).await;
+        }
+    }
+}
+
+async fn write(socket: &mut Socket, messages: Vec<String>) -> Result<(), fastwebsockets::WebSocketError> {
+    for message in messages {
+        socket.write_frame(Frame::text(fastwebsockets::Payload::Borrowed(message.as_bytes()))).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! Scripted stand-in for an exchange's websocket and REST APIs, served on localhost so
+//! providers can be run end to end without network by pointing their base URLs at it
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use fastwebsockets::{Frame, FragmentCollector, OpCode, Payload};
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+
+use crate::data_providers::{override_endpoints, BaseUrls, Endpoints, Event, Exchange};
+
+/// What the server does on a websocket connection, one step after another
+#[derive(Debug, Clone)]
+pub enum Step {
+    Send(String),
+    /// Waits for the next text frame from the client, e.g. a subscribe request
+    Receive,
+    Close,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    /// REST responses by path in the order they're served, the last one repeats. Queries are ignored
+    rest: HashMap<String, Vec<String>>,
+    /// Steps for each websocket connection in the order they come in, the last one repeats for any after
+    sessions: Vec<Vec<Step>>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rest(mut self, path: &str, body: &str) -> Self {
+        self.rest.entry(path.to_string()).or_default().push(body.to_string());
+        self
+    }
+
+    pub fn session(mut self, steps: Vec<Step>) -> Self {
+        self.sessions.push(steps);
+        self
+    }
+}
+
+struct Shared {
+    script: Script,
+    connections: AtomicUsize,
+    received: Mutex<Vec<String>>,
+    /// Path and query of each REST request
+    requested: Mutex<Vec<String>>,
+}
+
+pub struct MockServer {
+    pub base_urls: BaseUrls,
+    shared: Arc<Shared>,
+}
+
+impl MockServer {
+    pub async fn start(script: Script) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await
+            .expect("Failed to bind mock server");
+        let addr = listener.local_addr()
+            .expect("Mock server has no local address");
+
+        let shared = Arc::new(Shared {
+            script,
+            connections: AtomicUsize::new(0),
+            received: Mutex::new(vec![]),
+            requested: Mutex::new(vec![]),
+        });
+
+        let server_shared = Arc::clone(&shared);
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let shared = Arc::clone(&server_shared);
+
+                tokio::spawn(async move {
+                    let service = hyper::service::service_fn(move |req| {
+                        let shared = Arc::clone(&shared);
+                        async move { handle(req, shared).await }
+                    });
+
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .with_upgrades()
+                        .await;
+                });
+            }
+        });
+
+        Self {
+            base_urls: BaseUrls {
+                ws: format!("ws://{addr}"),
+                rest: format!("http://{addr}"),
+            },
+            shared,
+        }
+    }
+
+    /// Points the exchange's provider at this server
+    pub fn serve(&self, exchange: Exchange) {
+        override_endpoints(Endpoints {
+            exchanges: HashMap::from([(exchange, self.base_urls.clone())]),
+            ..Endpoints::default()
+        });
+    }
+
+    /// Websocket connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.shared.connections.load(Ordering::SeqCst)
+    }
+
+    /// Text frames the clients sent, in order
+    pub fn received(&self) -> Vec<String> {
+        self.shared.received.lock().expect("mock server lock poisoned").clone()
+    }
+
+    /// REST requests made so far, with their queries
+    pub fn requested(&self) -> Vec<String> {
+        self.shared.requested.lock().expect("mock server lock poisoned").clone()
+    }
+}
+
+async fn handle(mut req: Request<Incoming>, shared: Arc<Shared>) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    if let Some(bodies) = shared.script.rest.get(req.uri().path()) {
+        let mut requested = shared.requested.lock().expect("mock server lock poisoned");
+
+        let served = requested.iter()
+            .filter(|uri| uri.split('?').next() == Some(req.uri().path()))
+            .count();
+
+        requested.push(req.uri().to_string());
+
+        let body = bodies.get(served).or(bodies.last()).cloned().unwrap_or_default();
+
+        return Ok(Response::new(Full::new(Bytes::from(body))));
+    }
+
+    if fastwebsockets::upgrade::is_upgrade_request(&req) {
+        let Ok((response, upgrade)) = fastwebsockets::upgrade::upgrade(&mut req) else {
+            return Ok(status(StatusCode::BAD_REQUEST));
+        };
+
+        let connection = shared.connections.fetch_add(1, Ordering::SeqCst);
+
+        tokio::spawn(async move {
+            if let Ok(websocket) = upgrade.await {
+                run_session(FragmentCollector::new(websocket), connection, &shared).await;
+            }
+        });
+
+        return Ok(response.map(|_| Full::default()));
+    }
+
+    Ok(status(StatusCode::NOT_FOUND))
+}
+
+fn status(code: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = code;
+    response
+}
+
+async fn run_session(
+    mut websocket: FragmentCollector<TokioIo<hyper::upgrade::Upgraded>>,
+    connection: usize,
+    shared: &Shared,
+) {
+    let sessions = &shared.script.sessions;
+    let steps = sessions.get(connection).or(sessions.last()).cloned().unwrap_or_default();
+
+    for step in steps {
+        let result = match step {
+            Step::Send(text) => websocket.write_frame(Frame::text(Payload::Owned(text.into_bytes()))).await,
+            Step::Receive => match read_text(&mut websocket).await {
+                Some(text) => {
+                    shared.received.lock().expect("mock server lock poisoned").push(text);
+                    Ok(())
+                }
+                None => return,
+            },
+            Step::Close => {
+                let _ = websocket.write_frame(Frame::close(1000, b"")).await;
+                return;
+            }
+        };
+
+        if result.is_err() {
+            return;
+        }
+    }
+
+    // keep the connection open, answering pings, until the client goes away
+    while let Some(text) = read_text(&mut websocket).await {
+        shared.received.lock().expect("mock server lock poisoned").push(text);
+    }
+}
+
+async fn read_text(websocket: &mut FragmentCollector<TokioIo<hyper::upgrade::Upgraded>>) -> Option<String> {
+    loop {
+        let frame = websocket.read_frame().await.ok()?;
+
+        match frame.opcode {
+            OpCode::Text => return Some(String::from_utf8_lossy(&frame.payload).into_owned()),
+            OpCode::Close => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Next event off a provider's stream, failing the test if it takes more than a few seconds
+pub async fn next_event(stream: &mut (impl Stream<Item = Event> + Unpin)) -> Event {
+    tokio::time::timeout(std::time::Duration::from_secs(5), stream.next()).await
+        .expect("timed out waiting for an event")
+        .expect("stream ended")
+}
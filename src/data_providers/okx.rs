@@ -0,0 +1,47 @@
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt, TryFutureExt};
+
+use super::{connection, Event, Kline, MarketDataProvider, Price, SubscribedStreams, Ticker, Timeframe, TradesBatch};
+
+pub mod market_data;
+
+pub struct Provider;
+
+impl MarketDataProvider for Provider {
+    fn normalize_symbol(&self, ticker: Ticker) -> String {
+        market_data::symbol(ticker)
+    }
+
+    fn connect(&self, streams: SubscribedStreams) -> BoxStream<'static, Event> {
+        futures::stream::select(
+            connection::connect(market_data::MarketFeed::public(), streams.clone()),
+            connection::connect(market_data::MarketFeed::business(), streams),
+        )
+        .boxed()
+    }
+
+    fn fetch_klines(&self, ticker: Ticker, timeframe: Timeframe, end_time: Option<i64>) -> BoxFuture<'static, Result<Vec<Kline>, String>> {
+        market_data::fetch_klines(ticker, timeframe, end_time)
+            .map_err(|err| format!("{err}"))
+            .boxed()
+    }
+
+    fn fetch_ticksize(&self, ticker: Ticker) -> BoxFuture<'static, Result<Price, String>> {
+        market_data::fetch_ticksize(ticker)
+            .map_err(|err| format!("{err}"))
+            .boxed()
+    }
+
+    fn fetch_trades(&self, ticker: Ticker, from_time: i64, to_time: i64) -> BoxStream<'static, Result<TradesBatch, String>> {
+        market_data::fetch_trades(ticker, from_time, to_time)
+            .map_ok(|trades| TradesBatch { trades, progress: 1.0 })
+            .map_err(|err| format!("{err}"))
+            .into_stream()
+            .boxed()
+    }
+
+    fn fetch_ticker_list(&self) -> BoxFuture<'static, Result<Vec<Ticker>, String>> {
+        market_data::fetch_ticker_list()
+            .map_err(|err| format!("{err}"))
+            .boxed()
+    }
+}
@@ -0,0 +1,1038 @@
+use std::collections::HashMap;
+
+use iced::futures;
+use futures::future::{BoxFuture, FutureExt};
+
+use serde_json::Value;
+use bytes::Bytes;
+
+use sonic_rs::{JsonValueTrait, Deserialize, Serialize};
+use sonic_rs::to_object_iter_unchecked;
+
+use anyhow::anyhow;
+use anyhow::{Context, Result};
+
+use fastwebsockets::{Frame, FragmentCollector};
+use http_body_util::Empty;
+use hyper::header::{CONNECTION, UPGRADE};
+use hyper::upgrade::Upgraded;
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor};
+use tokio_rustls::TlsConnector;
+
+use crate::data_providers::{
+    ConnectionState, DepthUpdate, Event, Exchange, FeedLatency, Kline, Liquidation, LocalDepthCache, MarketStats, Order, Price, StreamType, Trade,
+    connection::{self, Feed, Socket},
+};
+use crate::{Ticker, Timeframe};
+
+const EXCHANGE: Exchange = Exchange::OkxSwap;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SonicArg {
+    #[serde(rename = "channel")]
+    pub channel: String,
+    // channels covering a whole instrument type, like liquidations, have none
+    #[serde(rename = "instId", default)]
+    pub inst_id: String,
+    #[serde(rename = "instType", default)]
+    pub inst_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SonicDepth {
+    #[serde(rename = "ts")]
+    pub time: String,
+    #[serde(rename = "seqId")]
+    pub seq_id: i64,
+    #[serde(rename = "prevSeqId")]
+    pub prev_seq_id: i64,
+    // [price, size in contracts, deprecated, number of orders]
+    #[serde(rename = "bids")]
+    pub bids: Vec<Vec<String>>,
+    #[serde(rename = "asks")]
+    pub asks: Vec<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SonicTrade {
+    #[serde(rename = "ts")]
+    pub time: String,
+    #[serde(rename = "px")]
+    pub price: String,
+    #[serde(rename = "sz")]
+    pub qty: String,
+    #[serde(rename = "side")]
+    pub side: String,
+}
+
+/// Any of the mark price, index, funding rate and open interest channels, each only fills in its own fields
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SonicStats {
+    #[serde(rename = "ts")]
+    pub time: Option<String>,
+    #[serde(rename = "markPx")]
+    pub mark_price: Option<String>,
+    #[serde(rename = "idxPx")]
+    pub index_price: Option<String>,
+    #[serde(rename = "fundingRate")]
+    pub funding_rate: Option<String>,
+    // when `fundingRate` gets settled, `nextFundingTime` is the one after that
+    #[serde(rename = "fundingTime")]
+    pub funding_time: Option<String>,
+    #[serde(rename = "oiCcy")]
+    pub open_interest: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SonicLiquidationDetail {
+    #[serde(rename = "ts")]
+    pub time: String,
+    // bankruptcy price of the position
+    #[serde(rename = "bkPx")]
+    pub price: String,
+    #[serde(rename = "sz")]
+    pub qty: String,
+    #[serde(rename = "side")]
+    pub side: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SonicLiquidations {
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+    #[serde(rename = "details")]
+    pub details: Vec<SonicLiquidationDetail>,
+}
+
+#[derive(Debug)]
+pub enum StreamData {
+    Trade(Vec<SonicTrade>),
+    Depth(SonicDepth, String),
+    // [ts, open, high, low, close, vol, volCcy, volCcyQuote, confirm]
+    Kline(Vec<Vec<String>>),
+    Stats(Vec<SonicStats>),
+    Liquidation(Vec<SonicLiquidations>),
+}
+
+#[derive(Debug)]
+enum StreamName {
+    Depth,
+    Trade,
+    Kline,
+    Stats,
+    Liquidation,
+    Unknown,
+}
+impl StreamName {
+    fn from_arg(arg: &SonicArg) -> Self {
+        if arg.channel == "liquidation-orders" {
+            return StreamName::Liquidation;
+        }
+
+        if !Ticker::is_valid_symbol(&arg.inst_id) {
+            return StreamName::Unknown;
+        }
+
+        match arg.channel.as_str() {
+            "books" => StreamName::Depth,
+            "trades" => StreamName::Trade,
+            "mark-price" | "index-tickers" | "funding-rate" | "open-interest" => StreamName::Stats,
+            channel => match channel.strip_prefix("candle").and_then(string_to_timeframe) {
+                Some(_) => StreamName::Kline,
+                None => StreamName::Unknown,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+enum StreamWrapper {
+    Trade,
+    Depth,
+    Kline,
+    Stats,
+    Liquidation,
+}
+
+/// The message along with the topic it came on, see `MarketFeed::Topic`
+fn feed_de(bytes: &Bytes) -> Result<((String, String), StreamData)> {
+    let mut stream_type: Option<StreamWrapper> = None;
+
+    let mut topic = (String::new(), String::new());
+
+    let mut action: String = String::new();
+
+    // the reply to our `ping` is the only frame that isn't JSON, which the unchecked iterator relies on
+    if bytes.as_ref() == PONG {
+        return Err(anyhow!("Pong"));
+    }
+
+    let iter: sonic_rs::ObjectJsonIter = unsafe { to_object_iter_unchecked(bytes) };
+
+    for elem in iter {
+        let (k, v) = elem.context("Error parsing stream")?;
+
+        if k == "arg" {
+            let arg: SonicArg = sonic_rs::from_str(&v.as_raw_faststr())
+                .context("Error parsing arg")?;
+
+            topic = (
+                arg.channel.clone(),
+                if arg.inst_id.is_empty() { arg.inst_type.clone() } else { arg.inst_id.clone() },
+            );
+
+            match StreamName::from_arg(&arg) {
+                StreamName::Depth => {
+                    stream_type = Some(StreamWrapper::Depth);
+                },
+                StreamName::Trade => {
+                    stream_type = Some(StreamWrapper::Trade);
+                },
+                StreamName::Kline => {
+                    stream_type = Some(StreamWrapper::Kline);
+                },
+                StreamName::Stats => {
+                    stream_type = Some(StreamWrapper::Stats);
+                },
+                StreamName::Liquidation => {
+                    stream_type = Some(StreamWrapper::Liquidation);
+                },
+                StreamName::Unknown => {
+                    log::error!("Unknown stream name: {:?}", arg);
+                }
+            }
+        } else if k == "action" {
+            if let Some(val) = v.as_str() {
+                val.clone_into(&mut action);
+            }
+        } else if k == "data" {
+            match stream_type {
+                Some(StreamWrapper::Trade) => {
+                    let trade_wrap: Vec<SonicTrade> = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing trade")?;
+
+                    return Ok((topic, StreamData::Trade(trade_wrap)));
+                },
+                Some(StreamWrapper::Depth) => {
+                    let depth_wrap: Vec<SonicDepth> = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing depth")?;
+
+                    let depth = depth_wrap.into_iter().next()
+                        .context("Empty depth data")?;
+
+                    return Ok((topic, StreamData::Depth(depth, action)));
+                },
+                Some(StreamWrapper::Kline) => {
+                    let kline_wrap: Vec<Vec<String>> = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing kline")?;
+
+                    return Ok((topic, StreamData::Kline(kline_wrap)));
+                },
+                Some(StreamWrapper::Stats) => {
+                    let stats_wrap: Vec<SonicStats> = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing stats")?;
+
+                    return Ok((topic, StreamData::Stats(stats_wrap)));
+                },
+                Some(StreamWrapper::Liquidation) => {
+                    let liquidation_wrap: Vec<SonicLiquidations> = sonic_rs::from_str(&v.as_raw_faststr())
+                        .context("Error parsing liquidations")?;
+
+                    return Ok((topic, StreamData::Liquidation(liquidation_wrap)));
+                },
+                None => {
+                    log::error!("Unknown stream type");
+                }
+            }
+        }
+    }
+
+    Err(anyhow::anyhow!("Unknown data"))
+}
+
+fn tls_connector() -> Result<TlsConnector> {
+    let mut root_store = tokio_rustls::rustls::RootCertStore::empty();
+
+    root_store.add_trust_anchors(
+        webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+            )
+        }),
+    );
+
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    Ok(TlsConnector::from(std::sync::Arc::new(config)))
+}
+
+async fn connect(base_url: &str, path: &str) -> Result<FragmentCollector<TokioIo<Upgraded>>> {
+    let base = url::Url::parse(base_url)?;
+    let host = base.host_str().context("websocket url has no host")?;
+    let port = base.port_or_known_default().context("websocket url has no port")?;
+    let addr = format!("{host}:{port}");
+
+    let tcp_stream: TcpStream = TcpStream::connect(&addr).await?;
+
+    let url = format!("{base_url}{path}");
+
+    let req: Request<Empty<Bytes>> = Request::builder()
+    .method("GET")
+    .uri(url)
+    .header("Host", &addr)
+    .header(UPGRADE, "websocket")
+    .header(CONNECTION, "upgrade")
+    .header(
+        "Sec-WebSocket-Key",
+        fastwebsockets::handshake::generate_key(),
+    )
+    .header("Sec-WebSocket-Version", "13")
+    .body(Empty::<Bytes>::new())?;
+
+    // plain ws is only there for local mock servers
+    let (ws, _) = if base.scheme() == "wss" {
+        let tls_connector: TlsConnector = tls_connector().unwrap();
+        let domain: tokio_rustls::rustls::ServerName =
+        tokio_rustls::rustls::ServerName::try_from(host).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid dnsname")
+        })?;
+
+        let tls_stream: tokio_rustls::client::TlsStream<TcpStream> = tls_connector.connect(domain, tcp_stream).await?;
+
+        fastwebsockets::handshake::client(&SpawnExecutor, req, tls_stream).await?
+    } else {
+        fastwebsockets::handshake::client(&SpawnExecutor, req, tcp_stream).await?
+    };
+
+    Ok(FragmentCollector::new(ws))
+}
+struct SpawnExecutor;
+
+impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+where
+  Fut: std::future::Future + Send + 'static,
+  Fut::Output: Send + 'static,
+{
+  fn execute(&self, fut: Fut) {
+    tokio::task::spawn(fut);
+  }
+}
+
+/// OKX instrument ids are used as is, e.g. BTC-USDT-SWAP
+pub fn symbol(ticker: Ticker) -> String {
+    ticker.as_str().to_string()
+}
+
+fn str_f32_parse(s: &str) -> f32 {
+    s.parse::<f32>().unwrap_or_else(|e| {
+        log::error!("Failed to parse float: {}, error: {}", s, e);
+        0.0
+    })
+}
+
+fn str_price_parse(s: &str) -> Price {
+    s.parse::<Price>().unwrap_or_else(|e| {
+        log::error!("Failed to parse price: {}, error: {}", s, e);
+        Price::default()
+    })
+}
+
+fn str_i64_parse(s: &str) -> i64 {
+    s.parse::<i64>().unwrap_or_else(|e| {
+        log::error!("Failed to parse integer: {}, error: {}", s, e);
+        0
+    })
+}
+
+fn string_to_timeframe(interval: &str) -> Option<Timeframe> {
+    Timeframe::ALL.iter().find(|&tf| timeframe_to_bar(*tf) == interval).copied()
+}
+
+fn timeframe_to_bar(timeframe: Timeframe) -> &'static str {
+    match timeframe {
+        Timeframe::M1 => "1m",
+        Timeframe::M3 => "3m",
+        Timeframe::M5 => "5m",
+        Timeframe::M15 => "15m",
+        Timeframe::M30 => "30m",
+        Timeframe::H1 => "1H",
+        Timeframe::H4 => "4H",
+        // plain `1D` closes on Hong Kong time
+        Timeframe::D1 => "1Dutc",
+    }
+}
+
+/// Converts a book level of contracts into an order sized in the base currency
+fn level_to_order(level: &[String], contract_size: f32) -> Option<Order> {
+    match level {
+        [price, qty, ..] => Some(Order {
+            price: str_price_parse(price),
+            qty: str_f32_parse(qty) * contract_size,
+        }),
+        _ => None,
+    }
+}
+
+/// OKX answers a text `ping` with a plain text `pong`
+const PONG: &[u8] = b"pong";
+
+const PUBLIC_PATH: &str = "/ws/v5/public";
+// candles are only served here
+const BUSINESS_PATH: &str = "/ws/v5/business";
+
+fn fetch_size(ticker: Ticker) -> BoxFuture<'static, Result<f32, String>> {
+    async move { fetch_contract_size(ticker).await.map_err(|e| e.to_string()) }.boxed()
+}
+
+/// A depth stream's book, starting over from the snapshot each (re)subscription of `books` begins with
+struct Book {
+    orderbook: LocalDepthCache,
+    /// Waiting on the snapshot of a resubscribe
+    resyncing: bool,
+    trades: Vec<Trade>,
+    trade_latencies: Vec<i64>,
+}
+
+impl Book {
+    fn new() -> Self {
+        Self {
+            orderbook: LocalDepthCache::new(),
+            resyncing: false,
+            trades: vec![],
+            trade_latencies: vec![],
+        }
+    }
+
+    fn apply(&mut self, stream: StreamType, de_depth: &SonicDepth, action: &str, contract_size: f32, ctx: &mut connection::Context<MarketFeed>) {
+        let time = str_i64_parse(&de_depth.time);
+
+        let depth_latency = chrono::Utc::now().timestamp_millis() - time;
+
+        let depth_update = DepthUpdate {
+            last_update_id: de_depth.seq_id,
+            time,
+            bids: de_depth.bids.iter().filter_map(
+                |x| level_to_order(x, contract_size)
+            ).collect(),
+            asks: de_depth.asks.iter().filter_map(
+                |x| level_to_order(x, contract_size)
+            ).collect(),
+        };
+
+        if action == "snapshot" {
+            self.orderbook.fetched(depth_update);
+
+            if self.resyncing {
+                self.resyncing = false;
+                ctx.send(Event::Connection(vec![stream], ConnectionState::Live));
+            }
+        } else if action == "update" {
+            // updates before a snapshot, e.g. the one of the subscription went to another stream on the topic
+            if self.orderbook.get_fetch_id() == 0 {
+                if !self.resyncing {
+                    self.resync(stream, ctx);
+                }
+                return;
+            }
+
+            if de_depth.prev_seq_id != self.orderbook.get_fetch_id() {
+                log::warn!(
+                    "Out of sync on {stream:?}, expected prevSeqId {}, got {}. Resubscribing...",
+                    self.orderbook.get_fetch_id(), de_depth.prev_seq_id
+                );
+
+                self.resync(stream, ctx);
+                return;
+            }
+
+            self.orderbook.update_depth_cache(depth_update);
+
+            let avg_trade_latency = if !self.trade_latencies.is_empty() {
+                let avg = self.trade_latencies.iter().sum::<i64>() / self.trade_latencies.len() as i64;
+                self.trade_latencies.clear();
+                Some(avg)
+            } else {
+                None
+            };
+            let feed_latency = FeedLatency {
+                time,
+                depth_latency,
+                trade_latency: avg_trade_latency,
+            };
+
+            ctx.send(
+                Event::DepthReceived(
+                    stream,
+                    feed_latency,
+                    time,
+                    self.orderbook.get_depth(),
+                    std::mem::take(&mut self.trades)
+                )
+            );
+        }
+    }
+
+    fn resync(&mut self, stream: StreamType, ctx: &mut connection::Context<MarketFeed>) {
+        self.resubscribe(stream, ctx);
+        ctx.send(Event::Connection(vec![stream], ConnectionState::Resyncing));
+    }
+
+    // a fresh subscription starts over with a snapshot, the stream goes live again with it
+    fn resubscribe(&mut self, stream: StreamType, ctx: &mut connection::Context<MarketFeed>) {
+        let StreamType::DepthAndTrades { ticker, .. } = stream else {
+            return;
+        };
+
+        self.orderbook = LocalDepthCache::new();
+        self.resyncing = true;
+
+        ctx.resubscribe(("books".to_string(), symbol(ticker)));
+    }
+}
+
+enum StreamState {
+    Depth(Book),
+    /// Every channel updates its own part, nothing goes out before the mark price and funding are in
+    Stats(MarketStats),
+    Kline,
+    Liquidations,
+}
+
+/// OKX serves candles on another endpoint than the rest, so each exchange connection is a pair of these
+pub struct MarketFeed {
+    path: &'static str,
+    streams: HashMap<StreamType, StreamState>,
+    /// Trade, book and liquidation sizes are given in contracts. Streams wait on this before going live
+    contract_sizes: HashMap<Ticker, f32>,
+}
+
+impl MarketFeed {
+    pub fn public() -> Self {
+        Self::new(PUBLIC_PATH)
+    }
+
+    pub fn business() -> Self {
+        Self::new(BUSINESS_PATH)
+    }
+
+    fn new(path: &'static str) -> Self {
+        Self {
+            path,
+            streams: HashMap::new(),
+            contract_sizes: HashMap::new(),
+        }
+    }
+
+    fn contract_size(&self, stream: &StreamType) -> Option<f32> {
+        match stream {
+            StreamType::DepthAndTrades { ticker, .. } | StreamType::Liquidations { ticker, .. } => {
+                self.contract_sizes.get(ticker).copied()
+            }
+            _ => Some(1.0),
+        }
+    }
+}
+
+impl Feed for MarketFeed {
+    /// The channel and its `instId`, or the `instType` for channels covering every swap at once
+    type Topic = (String, String);
+    type Message = StreamData;
+    type Fetched = Result<f32, String>;
+
+    // OKX drops the connection if nothing was sent or received for 30 seconds
+    const PING_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(20);
+
+    fn exchange(&self) -> Exchange {
+        EXCHANGE
+    }
+
+    fn ping() -> Frame<'static> {
+        Frame::text(fastwebsockets::Payload::Borrowed(b"ping"))
+    }
+
+    fn carries(&self, stream: &StreamType) -> bool {
+        matches!(stream, StreamType::Kline { .. }) == (self.path == BUSINESS_PATH)
+    }
+
+    fn topics(&self, stream: &StreamType) -> Vec<(String, String)> {
+        match *stream {
+            // the 400 level `books` channel is the only one offered, the resolution only sets the stream's identity
+            StreamType::DepthAndTrades { ticker, .. } => vec![
+                ("books".to_string(), symbol(ticker)),
+                ("trades".to_string(), symbol(ticker)),
+            ],
+            StreamType::Kline { ticker, timeframe, .. } => vec![
+                (format!("candle{}", timeframe_to_bar(timeframe)), symbol(ticker)),
+            ],
+            StreamType::MarketStats { ticker, .. } => {
+                let inst_id = symbol(ticker);
+                // the index is quoted per pair, e.g. BTC-USDT for BTC-USDT-SWAP
+                let index_id = inst_id.trim_end_matches("-SWAP").to_string();
+
+                vec![
+                    ("mark-price".to_string(), inst_id.clone()),
+                    ("index-tickers".to_string(), index_id),
+                    ("funding-rate".to_string(), inst_id.clone()),
+                    ("open-interest".to_string(), inst_id),
+                ]
+            }
+            // OKX only has liquidations of every swap at once, the ones of other instruments get dropped
+            StreamType::Liquidations { .. } => vec![("liquidation-orders".to_string(), "SWAP".to_string())],
+            StreamType::None => vec![],
+        }
+    }
+
+    fn connect(&self, topics: Vec<(String, String)>) -> BoxFuture<'static, Result<Socket>> {
+        let path = self.path;
+        let messages = self.subscription_messages(&topics, true);
+
+        async move {
+            let mut websocket = connect(&EXCHANGE.base_urls().ws, path).await?;
+
+            for message in messages {
+                websocket.write_frame(Frame::text(fastwebsockets::Payload::Borrowed(message.as_bytes()))).await
+                    .context("Failed subscribing")?;
+            }
+
+            Ok(websocket)
+        }
+        .boxed()
+    }
+
+    fn subscription_messages(&self, topics: &[(String, String)], subscribe: bool) -> Vec<String> {
+        let args: Vec<serde_json::Value> = topics.iter()
+            .map(|(channel, id)| if channel == "liquidation-orders" {
+                serde_json::json!({ "channel": channel, "instType": id })
+            } else {
+                serde_json::json!({ "channel": channel, "instId": id })
+            })
+            .collect();
+
+        vec![serde_json::json!({
+            "op": if subscribe { "subscribe" } else { "unsubscribe" },
+            "args": args,
+        }).to_string()]
+    }
+
+    fn parse(&self, payload: &[u8]) -> Option<((String, String), StreamData)> {
+        feed_de(&Bytes::copy_from_slice(payload)).ok()
+    }
+
+    fn start(&mut self, stream: StreamType, ctx: &mut connection::Context<Self>) -> ConnectionState {
+        let state = match stream {
+            StreamType::DepthAndTrades { .. } => StreamState::Depth(Book::new()),
+            StreamType::MarketStats { .. } => StreamState::Stats(MarketStats::default()),
+            StreamType::Kline { .. } => StreamState::Kline,
+            StreamType::Liquidations { .. } => StreamState::Liquidations,
+            StreamType::None => return ConnectionState::Live,
+        };
+
+        self.streams.insert(stream, state);
+
+        match (self.contract_size(&stream), stream) {
+            (None, StreamType::DepthAndTrades { ticker, .. } | StreamType::Liquidations { ticker, .. }) => {
+                ctx.fetch(stream, fetch_size(ticker));
+                ConnectionState::Connecting
+            }
+            _ => ConnectionState::Live,
+        }
+    }
+
+    fn stop(&mut self, stream: &StreamType) {
+        self.streams.remove(stream);
+    }
+
+    fn on_message(&mut self, stream: StreamType, message: &StreamData, ctx: &mut connection::Context<Self>) {
+        // dropped until the contract size is in, the book gets resubscribed then
+        let Some(contract_size) = self.contract_size(&stream) else {
+            return;
+        };
+
+        match (self.streams.get_mut(&stream), message) {
+            (Some(StreamState::Depth(book)), StreamData::Trade(de_trade_vec)) => {
+                for de_trade in de_trade_vec.iter() {
+                    let trade = Trade {
+                        time: str_i64_parse(&de_trade.time),
+                        is_sell: de_trade.side == "sell",
+                        price: str_price_parse(&de_trade.price),
+                        qty: str_f32_parse(&de_trade.qty) * contract_size,
+                    };
+
+                    book.trade_latencies.push(
+                        chrono::Utc::now().timestamp_millis() - trade.time
+                    );
+
+                    book.trades.push(trade);
+                }
+            }
+            (Some(StreamState::Depth(book)), StreamData::Depth(de_depth, action)) => {
+                book.apply(stream, de_depth, action, contract_size, ctx);
+            }
+            (Some(StreamState::Stats(stats)), StreamData::Stats(de_stats_vec)) => {
+                for de_stats in de_stats_vec.iter() {
+                    if let Some(time) = &de_stats.time {
+                        stats.time = stats.time.max(str_i64_parse(time));
+                    }
+                    if let Some(mark_price) = &de_stats.mark_price {
+                        stats.mark_price = str_f32_parse(mark_price);
+                    }
+                    if let Some(index_price) = &de_stats.index_price {
+                        stats.index_price = str_f32_parse(index_price);
+                    }
+                    if let Some(funding_rate) = &de_stats.funding_rate {
+                        stats.funding_rate = str_f32_parse(funding_rate);
+                    }
+                    if let Some(funding_time) = &de_stats.funding_time {
+                        stats.next_funding_time = str_i64_parse(funding_time);
+                    }
+                    if let Some(open_interest) = &de_stats.open_interest {
+                        stats.open_interest = Some(str_f32_parse(open_interest));
+                    }
+                }
+
+                if stats.mark_price > 0.0 && stats.next_funding_time > 0 {
+                    ctx.send(Event::MarketStatsReceived(stream, *stats));
+                }
+            }
+            (Some(StreamState::Kline), StreamData::Kline(de_kline_vec)) => {
+                for de_kline in de_kline_vec.iter() {
+                    match parse_kline(de_kline) {
+                        Ok(kline) => ctx.send(Event::KlineReceived(stream, kline)),
+                        Err(e) => log::error!("Failed to parse kline: {e}, {:?}", de_kline),
+                    }
+                }
+            }
+            (Some(StreamState::Liquidations), StreamData::Liquidation(de_liquidations)) => {
+                let StreamType::Liquidations { ticker, .. } = stream else {
+                    return;
+                };
+                let inst_id = symbol(ticker);
+
+                let liquidations: Vec<Liquidation> = de_liquidations.iter()
+                    .filter(|de_liquidation| de_liquidation.inst_id == inst_id)
+                    .flat_map(|de_liquidation| de_liquidation.details.iter())
+                    .map(|detail| Liquidation {
+                        time: str_i64_parse(&detail.time),
+                        price: str_price_parse(&detail.price),
+                        qty: str_f32_parse(&detail.qty) * contract_size,
+                        is_sell: detail.side == "sell",
+                    })
+                    .collect();
+
+                if !liquidations.is_empty() {
+                    ctx.send(Event::LiquidationsReceived(stream, liquidations));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_fetched(&mut self, stream: StreamType, contract_size: Result<f32, String>, ctx: &mut connection::Context<Self>) {
+        let (StreamType::DepthAndTrades { ticker, .. } | StreamType::Liquidations { ticker, .. }) = stream else {
+            return;
+        };
+
+        let contract_size = contract_size.unwrap_or_else(|e| {
+            log::error!("Failed to fetch contract size for {ticker}: {e}");
+            1.0
+        });
+        self.contract_sizes.insert(ticker, contract_size);
+
+        match self.streams.get_mut(&stream) {
+            // the snapshot it began with was dropped while the size wasn't known
+            Some(StreamState::Depth(book)) => book.resubscribe(stream, ctx),
+            Some(_) => ctx.send(Event::Connection(vec![stream], ConnectionState::Live)),
+            None => {}
+        }
+    }
+}
+
+/// Parses a candle array, same layout on the websocket and on the REST api
+///
+/// Volume is taken in the base currency (`volCcy`) rather than in contracts
+fn parse_kline(kline: &[String]) -> Result<Kline> {
+    match kline {
+        [time, open, high, low, close, _, volume, ..] => Ok(Kline {
+            time: time.parse::<u64>().context("Failed to parse time as u64")?,
+            open: open.parse::<Price>().map_err(|e| anyhow!("Failed to parse open: {e}"))?,
+            high: high.parse::<Price>().map_err(|e| anyhow!("Failed to parse high: {e}"))?,
+            low: low.parse::<Price>().map_err(|e| anyhow!("Failed to parse low: {e}"))?,
+            close: close.parse::<Price>().map_err(|e| anyhow!("Failed to parse close: {e}"))?,
+            volume: (-1.0, volume.parse::<f32>().context("Failed to parse volume as f32")?),
+        }),
+        _ => Err(anyhow!("Unexpected candle length: {}", kline.len())),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiResponse<T> {
+    #[serde(rename = "code")]
+    code: String,
+    #[serde(rename = "msg")]
+    msg: String,
+    data: T,
+}
+
+/// Older pages come from `history-candles`, `candles` only keeps the most recent 1440
+pub async fn fetch_klines(ticker: Ticker, timeframe: Timeframe, end_time: Option<i64>) -> Result<Vec<Kline>> {
+    let inst_id = symbol(ticker);
+    let bar = timeframe_to_bar(timeframe);
+
+    let url: String = match end_time {
+        Some(end_time) => format!("{}/api/v5/market/history-candles?instId={inst_id}&bar={bar}&after={end_time}&limit=100", EXCHANGE.base_urls().rest),
+        None => format!("{}/api/v5/market/candles?instId={inst_id}&bar={bar}&limit=300", EXCHANGE.base_urls().rest),
+    };
+
+    let response: reqwest::Response = reqwest::get(&url).await
+        .context("Failed to send request")?;
+    let text: String = response.text().await
+        .context("Failed to read response text")?;
+
+    let api_response: ApiResponse<Vec<Vec<String>>> = sonic_rs::from_str(&text)
+        .context("Failed to parse JSON")?;
+
+    if api_response.code != "0" {
+        anyhow::bail!("Failed to fetch klines for {inst_id}: {}", api_response.msg);
+    }
+
+    api_response.data.iter()
+        .map(|kline| parse_kline(kline))
+        .collect()
+}
+
+/// Only the latest 500 trades are served here, older candles are left to the OHLCV data
+pub async fn fetch_trades(ticker: Ticker, from_time: i64, to_time: i64) -> Result<Vec<Trade>> {
+    let inst_id = symbol(ticker);
+    let contract_size = fetch_contract_size(ticker).await?;
+
+    let url: String = format!("{}/api/v5/market/trades?instId={inst_id}&limit=500", EXCHANGE.base_urls().rest);
+
+    let response: reqwest::Response = reqwest::get(&url).await
+        .context("Failed to send request")?;
+    let text: String = response.text().await
+        .context("Failed to read response text")?;
+
+    let api_response: ApiResponse<Vec<Value>> = sonic_rs::from_str(&text)
+        .context("Failed to parse JSON")?;
+
+    if api_response.code != "0" {
+        anyhow::bail!("Failed to fetch trades for {inst_id}: {}", api_response.msg);
+    }
+
+    let mut trades: Vec<Trade> = api_response.data.iter()
+        .filter_map(|item| Some(Trade {
+            time: str_i64_parse(item["ts"].as_str()?),
+            is_sell: item["side"] == "sell",
+            price: str_price_parse(item["px"].as_str()?),
+            qty: str_f32_parse(item["sz"].as_str()?) * contract_size,
+        }))
+        .filter(|trade| trade.time >= from_time && trade.time < to_time)
+        .collect();
+
+    // newest first on the wire
+    trades.reverse();
+
+    Ok(trades)
+}
+
+async fn fetch_instrument(ticker: Ticker) -> Result<Value> {
+    let inst_id = symbol(ticker);
+
+    let url = format!("{}/api/v5/public/instruments?instType=SWAP&instId={inst_id}", EXCHANGE.base_urls().rest);
+
+    let response: reqwest::Response = reqwest::get(&url).await
+        .context("Failed to send request")?;
+    let text: String = response.text().await
+        .context("Failed to read response text")?;
+
+    let exchange_info: Value = sonic_rs::from_str(&text)
+        .context("Failed to parse JSON")?;
+
+    let data: &Vec<Value> = exchange_info["data"].as_array()
+        .context("Data is not an array")?;
+
+    data.iter()
+        .find(|item| item["instId"] == inst_id)
+        .cloned()
+        .with_context(|| format!("Instrument not found for symbol {inst_id}"))
+}
+
+pub async fn fetch_ticksize(ticker: Ticker) -> Result<Price> {
+    let instrument = fetch_instrument(ticker).await?;
+
+    let tick_size_str: &str = instrument["tickSz"].as_str()
+        .context("Tick size not found")?;
+
+    tick_size_str.parse::<Price>()
+        .map_err(|e| anyhow!("Failed to parse tick size: {e}"))
+}
+
+/// Amount of the base currency a single contract represents
+async fn fetch_contract_size(ticker: Ticker) -> Result<f32> {
+    let instrument = fetch_instrument(ticker).await?;
+
+    let contract_size_str: &str = instrument["ctVal"].as_str()
+        .context("Contract value not found")?;
+
+    contract_size_str.parse::<f32>()
+        .context("Failed to parse contract value")
+}
+
+/// Fetches every linear (USDT/USDC margined) perpetual swap that is currently live on OKX
+pub async fn fetch_ticker_list() -> Result<Vec<Ticker>> {
+    let url = format!("{}/api/v5/public/instruments?instType=SWAP", EXCHANGE.base_urls().rest);
+
+    let response: reqwest::Response = reqwest::get(url).await
+        .context("Failed to send request")?;
+    let text: String = response.text().await
+        .context("Failed to read response text")?;
+
+    let exchange_info: Value = sonic_rs::from_str(&text)
+        .context("Failed to parse JSON")?;
+
+    let data: &Vec<Value> = exchange_info["data"].as_array()
+        .context("Data is not an array")?;
+
+    let mut tickers: Vec<Ticker> = data.iter()
+        .filter(|item| item["ctType"] == "linear" && item["state"] == "live")
+        .filter_map(|item| item["instId"].as_str())
+        .filter(|symbol| Ticker::is_valid_symbol(symbol))
+        .map(Ticker::new)
+        .collect();
+
+    tickers.sort();
+
+    Ok(tickers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // recorded from wss://ws.okx.com:8443/ws/v5/public and /ws/v5/business
+    const BOOKS_SNAPSHOT: &str = r#"{"arg":{"channel":"books","instId":"BTC-USDT-SWAP"},"action":"snapshot","data":[{"asks":[["67120.1","52","0","6"],["67120.5","3","0","1"]],"bids":[["67120","318","0","14"],["67119.9","1","0","1"]],"ts":"1718095353114","checksum":-1046236537,"prevSeqId":-1,"seqId":24563481604}]}"#;
+    const BOOKS_UPDATE: &str = r#"{"arg":{"channel":"books","instId":"BTC-USDT-SWAP"},"action":"update","data":[{"asks":[["67120.5","0","0","0"]],"bids":[["67120","320","0","15"]],"ts":"1718095353214","checksum":1559123451,"prevSeqId":24563481604,"seqId":24563481611}]}"#;
+    const TRADES: &str = r#"{"arg":{"channel":"trades","instId":"BTC-USDT-SWAP"},"data":[{"instId":"BTC-USDT-SWAP","tradeId":"1231412053","px":"67120.1","sz":"4","side":"buy","ts":"1718095353207","count":"2"}]}"#;
+    const CANDLE: &str = r#"{"arg":{"channel":"candle5m","instId":"ETH-USDT-SWAP"},"data":[["1718095200000","3612.45","3614.1","3609.88","3611.02","20315","2031.5","7337584.5275","0"]]}"#;
+    const FUNDING_RATE: &str = r#"{"arg":{"channel":"funding-rate","instId":"BTC-USDT-SWAP"},"data":[{"fundingRate":"0.0001","fundingTime":"1718121600000","instId":"BTC-USDT-SWAP","instType":"SWAP","method":"current_period","nextFundingRate":"","nextFundingTime":"1718150400000","ts":"1718095353301"}]}"#;
+    const OPEN_INTEREST: &str = r#"{"arg":{"channel":"open-interest","instId":"BTC-USDT-SWAP"},"data":[{"instId":"BTC-USDT-SWAP","instType":"SWAP","oi":"2834512.1","oiCcy":"28345.121","ts":"1718095353412"}]}"#;
+    const LIQUIDATIONS: &str = r#"{"arg":{"channel":"liquidation-orders","instType":"SWAP"},"data":[{"details":[{"bkLoss":"0","bkPx":"67012.3","ccy":"","posSide":"long","side":"sell","sz":"12","ts":"1718095354010"}],"instFamily":"BTC-USDT","instId":"BTC-USDT-SWAP","instType":"SWAP","uly":"BTC-USDT"}]}"#;
+    const SUBSCRIBE_ACK: &str = r#"{"event":"subscribe","arg":{"channel":"books","instId":"BTC-USDT-SWAP"},"connId":"a4d3ae55"}"#;
+
+    fn de(msg: &str) -> Result<StreamData> {
+        feed_de(&Bytes::from(msg.to_string())).map(|(_, data)| data)
+    }
+
+    fn topic(msg: &str) -> (String, String) {
+        feed_de(&Bytes::from(msg.to_string())).unwrap().0
+    }
+
+    #[test]
+    fn parses_books_snapshot_and_update() {
+        match de(BOOKS_SNAPSHOT).unwrap() {
+            StreamData::Depth(depth, action) => {
+                assert_eq!(action, "snapshot");
+                assert_eq!(depth.seq_id, 24563481604);
+                assert_eq!(depth.prev_seq_id, -1);
+                assert_eq!(depth.time, "1718095353114");
+
+                let best_bid = level_to_order(&depth.bids[0], 0.01).unwrap();
+                assert_eq!(best_bid.price.to_string(), "67120");
+                assert!((best_bid.qty - 3.18).abs() < 1e-4);
+                assert_eq!(depth.asks.len(), 2);
+            },
+            other => panic!("expected depth, got {other:?}"),
+        }
+
+        match de(BOOKS_UPDATE).unwrap() {
+            StreamData::Depth(depth, action) => {
+                assert_eq!(action, "update");
+                assert_eq!(depth.prev_seq_id, 24563481604);
+                assert_eq!(level_to_order(&depth.asks[0], 0.01).unwrap().qty, 0.0);
+            },
+            other => panic!("expected depth, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_trades() {
+        match de(TRADES).unwrap() {
+            StreamData::Trade(trades) => {
+                assert_eq!(trades.len(), 1);
+                assert_eq!(trades[0].time, "1718095353207");
+                assert_eq!(trades[0].price, "67120.1");
+                assert_eq!(trades[0].qty, "4");
+                assert_eq!(trades[0].side, "buy");
+            },
+            other => panic!("expected trades, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_candle() {
+        match de(CANDLE).unwrap() {
+            StreamData::Kline(klines) => {
+                assert_eq!(topic(CANDLE), ("candle5m".to_string(), "ETH-USDT-SWAP".to_string()));
+
+                let kline = parse_kline(&klines[0]).unwrap();
+                assert_eq!(kline.time, 1718095200000);
+                assert_eq!(kline.open.to_string(), "3612.45");
+                assert_eq!(kline.close.to_string(), "3611.02");
+                assert_eq!(kline.volume, (-1.0, 2031.5));
+            },
+            other => panic!("expected kline, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_stats_channels() {
+        match de(FUNDING_RATE).unwrap() {
+            StreamData::Stats(stats) => {
+                assert_eq!(stats[0].funding_rate.as_deref(), Some("0.0001"));
+                assert_eq!(stats[0].funding_time.as_deref(), Some("1718121600000"));
+                assert!(stats[0].mark_price.is_none());
+                assert!(stats[0].open_interest.is_none());
+            },
+            other => panic!("expected stats, got {other:?}"),
+        }
+
+        match de(OPEN_INTEREST).unwrap() {
+            StreamData::Stats(stats) => {
+                assert_eq!(stats[0].open_interest.as_deref(), Some("28345.121"));
+                assert_eq!(stats[0].time.as_deref(), Some("1718095353412"));
+                assert!(stats[0].funding_rate.is_none());
+            },
+            other => panic!("expected stats, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_liquidations_without_inst_id_in_arg() {
+        match de(LIQUIDATIONS).unwrap() {
+            StreamData::Liquidation(liquidations) => {
+                assert_eq!(topic(LIQUIDATIONS), ("liquidation-orders".to_string(), "SWAP".to_string()));
+                assert_eq!(liquidations[0].inst_id, "BTC-USDT-SWAP");
+
+                let detail = &liquidations[0].details[0];
+                assert_eq!(detail.price, "67012.3");
+                assert_eq!(detail.qty, "12");
+                assert_eq!(detail.side, "sell");
+            },
+            other => panic!("expected liquidations, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_non_data_messages() {
+        assert!(de(SUBSCRIBE_ACK).is_err());
+        assert!(de("pong").is_err());
+    }
+}
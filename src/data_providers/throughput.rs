@@ -0,0 +1,35 @@
+//! Messages and bytes each stream has received, counted by the providers as frames come in
+//! and collected by the feed monitor
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::StreamType;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Throughput {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+static COUNTERS: Mutex<Option<HashMap<StreamType, Throughput>>> = Mutex::new(None);
+
+/// Counts one message of `bytes` on the stream
+pub fn record(stream_type: StreamType, bytes: usize) {
+    if let Ok(mut counters) = COUNTERS.lock() {
+        let throughput = counters.get_or_insert_with(HashMap::new)
+            .entry(stream_type)
+            .or_default();
+
+        throughput.messages += 1;
+        throughput.bytes += bytes as u64;
+    }
+}
+
+/// What each stream received since the last call
+pub fn take() -> HashMap<StreamType, Throughput> {
+    COUNTERS.lock()
+        .ok()
+        .and_then(|mut counters| counters.take())
+        .unwrap_or_default()
+}
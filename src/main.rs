@@ -5,6 +5,8 @@ mod charts;
 mod style;
 mod screen;
 mod logger;
+mod recorder;
+mod replay;
 
 use style::{ICON_FONT, ICON_BYTES, Icon};
 
@@ -15,14 +17,13 @@ use screen::dashboard::{
     PaneContent, PaneSettings, PaneState, 
     SerializableDashboard, 
 };
-use data_providers::{binance, bybit, Exchange, MarketEvents, Ticker, Timeframe, StreamType};
+use data_providers::{Exchange, Ticker, Timeframe, StreamType};
 
 use charts::footprint::FootprintChart;
-use charts::heatmap::HeatmapChart;
 use charts::candlestick::CandlestickChart;
 use charts::timeandsales::TimeAndSales;
 
-use std::{collections::{HashMap, VecDeque}, vec};
+use std::{collections::{HashMap, HashSet}, vec};
 
 use iced::{
     alignment, widget::{
@@ -42,6 +43,8 @@ fn main() -> iced::Result {
                 last_active_layout: state.last_active_layout,
                 window_size: state.window_size,
                 window_position: state.window_position,
+                record_market_data: state.record_market_data,
+                endpoints: state.endpoints,
             };
 
             fn configuration(pane: SerializablePane) -> Configuration<PaneState> {
@@ -59,18 +62,19 @@ fn main() -> iced::Result {
                         Configuration::Pane(PaneState::new(Uuid::new_v4(), vec![], PaneSettings::default()))
                     },
                     SerializablePane::CandlestickChart { stream_type, settings } => {
-                        let timeframe = settings.selected_timeframe
-                            .unwrap()
-                            .to_minutes();
+                        let basis = settings.basis();
+
+                        let mut chart = CandlestickChart::new(vec![], basis);
+                        chart.set_volume_profile(settings.volume_profile);
+
+                        // layouts saved before candlesticks had a volume profile have no tick size, it's fetched again
+                        if let (Some(tick_multiply), Some(min_tick_size)) = (settings.tick_multiply, settings.min_tick_size) {
+                            chart.change_tick_size(tick_multiply.multiply_with_min_tick_size(min_tick_size));
+                        }
 
                         Configuration::Pane(
                             PaneState::from_config(
-                                PaneContent::Candlestick(
-                                    CandlestickChart::new(
-                                        vec![], 
-                                        timeframe
-                                    )
-                                ),
+                                PaneContent::Candlestick(chart),
                                 stream_type,
                                 settings
                             )
@@ -84,20 +88,14 @@ fn main() -> iced::Result {
                                     .expect("No min tick size found, deleting dashboard_state.json probably fixes this")
                             );
                     
-                        let timeframe = settings.selected_timeframe
-                            .unwrap()
-                            .to_minutes();
+                        let basis = settings.basis();
+
+                        let mut chart = FootprintChart::new(basis, ticksize, vec![], vec![]);
+                        chart.set_volume_profile(settings.volume_profile);
 
                         Configuration::Pane(
                             PaneState::from_config(
-                                PaneContent::Footprint(
-                                    FootprintChart::new(
-                                        timeframe,
-                                        ticksize,
-                                        vec![], 
-                                        vec![]
-                                    )
-                                ),
+                                PaneContent::Footprint(chart),
                                 stream_type,
                                 settings
                             )
@@ -114,7 +112,7 @@ fn main() -> iced::Result {
                         Configuration::Pane(
                             PaneState::from_config(
                                 PaneContent::Heatmap(
-                                    HeatmapChart::new(ticksize)
+                                    pane::new_heatmap(ticksize, &settings)
                                 ),
                                 stream_type,
                                 settings
@@ -132,6 +130,11 @@ fn main() -> iced::Result {
                             )
                         )
                     },
+                    SerializablePane::FeedMonitor => {
+                        Configuration::Pane(
+                            PaneState::from_config(PaneContent::FeedMonitor, vec![], PaneSettings::default())
+                        )
+                    },
                 }
             }
 
@@ -184,24 +187,47 @@ pub enum Message {
     ShowLayoutModal,
     HideLayoutModal,
 
-    MarketWsEvent(MarketEvents),
+    MarketWsEvent(data_providers::Event),
     
     Event(Event),
     SaveAndExit(window::Id, Option<Size>, Option<Point>),
 
     ToggleLayoutLock,
+    ToggleRecording(bool),
+    ReplaySelected(replay::Recording),
+    ReplayLoaded(Result<replay::Replay, String>),
+    ReplayTick(iced::time::Instant),
+    ReplayTogglePlay,
+    ReplaySpeedSelected(replay::Speed),
+    ReplaySeek(i64),
+    StopReplay,
     ResetCurrentLayout,
     LayoutSelected(LayoutId),
     Dashboard(dashboard::Message),
+
+    TickersFetched(Exchange, Result<Vec<Ticker>, String>),
+
+    SyncClocks,
+    ClockSynced(Exchange, Result<i64, String>),
+
+    SampleFeeds,
 }
 
 struct State {
     layouts: HashMap<LayoutId, Dashboard>,
     last_active_layout: LayoutId,
     show_layout_modal: bool,
-    exchange_latency: Option<(u32, u32)>,
-    feed_latency_cache: VecDeque<data_providers::FeedLatency>,
     notification: Option<Notification>,
+    tickers: HashMap<Exchange, Vec<Ticker>>,
+    recorder: Option<recorder::Recorder>,
+    /// While set, the dashboard is fed from the recording instead of the live streams
+    replay: Option<replay::Replay>,
+    recordings: Vec<replay::Recording>,
+    endpoints: data_providers::Endpoints,
+    /// Streams each exchange's single connection should be subscribed to
+    stream_subscriptions: data_providers::StreamSubscriptions,
+    /// Milliseconds each polled exchange clock is ahead of the local one, shown in the layout settings
+    clock_offsets: HashMap<Exchange, i64>,
 }
 
 impl State {
@@ -216,14 +242,30 @@ impl State {
         );
         tasks.push(wait_and_fetch);
 
+        // before anything connects or fetches
+        data_providers::override_endpoints(saved_state.endpoints.clone());
+
+        for exchange in Exchange::ALL {
+            tasks.push(fetch_ticker_list_task(exchange));
+        }
+
+        for source in data_providers::clock::SOURCES {
+            tasks.push(sync_clock_task(source));
+        }
+
         (
             Self { 
                 layouts: saved_state.layouts,
                 last_active_layout,
                 show_layout_modal: false,
-                exchange_latency: None,
-                feed_latency_cache: VecDeque::new(),
                 notification: None,
+                tickers: HashMap::new(),
+                recorder: saved_state.record_market_data.then(recorder::Recorder::start),
+                replay: None,
+                recordings: vec![],
+                endpoints: saved_state.endpoints,
+                stream_subscriptions: data_providers::StreamSubscriptions::new(),
+                clock_offsets: HashMap::new(),
             },
             Task::batch(tasks)
         )
@@ -232,71 +274,124 @@ impl State {
     fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::MarketWsEvent(event) => {
-                let dashboard = self.get_mut_dashboard();
+                // live streams get dropped while replaying, some may still be in flight
+                if self.replay.is_some() {
+                    return Task::none();
+                }
 
-                match event {
-                    MarketEvents::Binance(event) => match event {
-                        binance::market_data::Event::Connected(connection) => {
-                            log::info!("a stream connected to Binance WS: {connection:?}");
-                        }
-                        binance::market_data::Event::Disconnected(event) => {
-                            log::info!("a stream disconnected from Binance WS: {event:?}");
-                        }
-                        binance::market_data::Event::DepthReceived(ticker, feed_latency, depth_update_t, depth, trades_buffer) => {                            
-                            let stream_type = StreamType::DepthAndTrades {
-                                exchange: Exchange::BinanceFutures,
-                                ticker,
-                            };
-                            
-                            if let Err(err) = dashboard.update_depth_and_trades(stream_type, depth_update_t, depth, trades_buffer) {
-                                log::error!("{err}, {stream_type:?}");
-                            }
-                        }
-                        binance::market_data::Event::KlineReceived(ticker, kline, timeframe) => {
-                            let stream_type = StreamType::Kline {
-                                exchange: Exchange::BinanceFutures,
-                                ticker,
-                                timeframe,
-                            };
-
-                            if let Err(err) = dashboard.update_latest_klines(&stream_type, &kline) {
-                                log::error!("{err}, {stream_type:?}");
-                            }
-                        }
+                if let Some(recorder) = &self.recorder {
+                    recorder.record(&event);
+                }
+
+                self.distribute_market_event(event);
+                // stats and liquidation streams come and go with the tickers panes get data for
+                self.sync_stream_subscriptions();
+
+                Task::none()
+            },
+            Message::ReplaySelected(recording) => {
+                self.show_layout_modal = false;
+
+                Task::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || replay::Replay::load(recording))
+                            .await
+                            .map_err(|e| e.to_string())?
+                            .map_err(|e| e.to_string())
                     },
-                    MarketEvents::Bybit(event) => match event {
-                        bybit::market_data::Event::Connected(_) => {
-                            log::info!("a stream connected to Bybit WS");
-                        }
-                        bybit::market_data::Event::Disconnected(event) => {
-                            log::info!("a stream disconnected from Bybit WS: {event:?}");
-                        }
-                        bybit::market_data::Event::DepthReceived(ticker, feed_latency, depth_update_t, depth, trades_buffer) => {
-                            let stream_type = StreamType::DepthAndTrades {
-                                exchange: Exchange::BybitLinear,
-                                ticker,
-                            };
-                            
-                            if let Err(err) = dashboard.update_depth_and_trades(stream_type, depth_update_t, depth, trades_buffer) {
-                                log::error!("{err}, {stream_type:?}");
-                            }
-                        }
-                        bybit::market_data::Event::KlineReceived(ticker, kline, timeframe) => {
-                            let stream_type = StreamType::Kline {
-                                exchange: Exchange::BybitLinear,
-                                ticker,
-                                timeframe,
-                            };
-
-                            if let Err(err) = dashboard.update_latest_klines(&stream_type, &kline) {
-                                log::error!("{err}, {stream_type:?}");
+                    Message::ReplayLoaded
+                )
+            },
+            Message::ReplayLoaded(result) => {
+                match result {
+                    Ok(replay) => {
+                        let replay::Recording { exchange, ticker, .. } = replay.recording;
+                        let info = format!("Replaying {} into panes showing {ticker}", replay.recording);
+
+                        let dashboard = self.get_mut_dashboard();
+                        dashboard.reset_replayed_panes(exchange, ticker, &HashMap::new());
+
+                        let streams = dashboard.panes.iter()
+                            .flat_map(|(_, pane_state)| pane_state.stream.iter().copied())
+                            .collect();
+                        dashboard.set_stream_state(streams, data_providers::ConnectionState::Live);
+
+                        self.replay = Some(replay);
+
+                        Task::done(Message::Notification(Notification::Info(info)))
+                    }
+                    Err(err) => {
+                        log::error!("Failed to load recording: {err}");
+
+                        Task::done(Message::Notification(Notification::Error(format!("Failed to load recording: {err}"))))
+                    }
+                }
+            },
+            Message::ReplayTick(now) => {
+                let events = match &mut self.replay {
+                    Some(replay) => replay.tick(now),
+                    None => return Task::none(),
+                };
+
+                let depth_streams = self.replayed_depth_streams();
+
+                for event in events {
+                    match event {
+                        // a recording has the one book, played into the panes at whatever depth resolution they're set to
+                        data_providers::Event::DepthReceived(_, feed_latency, time, depth, trades) => {
+                            for stream_type in &depth_streams {
+                                self.distribute_market_event(data_providers::Event::DepthReceived(
+                                    *stream_type, feed_latency, time, depth.clone(), trades.clone()
+                                ));
                             }
                         }
-                    },
+                        event => self.distribute_market_event(event),
+                    }
+                }
+
+                Task::none()
+            },
+            Message::ReplayTogglePlay => {
+                if let Some(replay) = &mut self.replay {
+                    replay.toggle_play();
                 }
 
                 Task::none()
             },
+            Message::ReplaySpeedSelected(speed) => {
+                if let Some(replay) = &mut self.replay {
+                    replay.set_speed(speed);
+                }
+
+                Task::none()
+            },
+            Message::ReplaySeek(time) => {
+                if let Some(replay) = &mut self.replay {
+                    replay.seek(time);
+
+                    let replay::Recording { exchange, ticker, .. } = replay.recording;
+                    let klines = replay.klines_until_position();
+
+                    self.get_mut_dashboard().reset_replayed_panes(exchange, ticker, &klines);
+                }
+
+                Task::none()
+            },
+            Message::StopReplay => {
+                let Some(replay) = self.replay.take() else {
+                    return Task::none();
+                };
+                let replay::Recording { exchange, ticker, .. } = replay.recording;
+
+                let dashboard = self.get_mut_dashboard();
+                dashboard.reset_replayed_panes(exchange, ticker, &HashMap::new());
+
+                // refetches what the live charts start from
+                let task = dashboard.layout_changed().map(Message::Dashboard);
+                self.sync_stream_subscriptions();
+
+                task
+            },
             Message::ToggleLayoutLock => {
                 let dashboard = self.get_mut_dashboard();
 
@@ -306,6 +401,12 @@ impl State {
 
                 Task::none()
             },
+            Message::ToggleRecording(enabled) => {
+                // dropping the recorder flushes and closes its files
+                self.recorder = enabled.then(recorder::Recorder::start);
+
+                Task::none()
+            },
             Message::Debug(msg) => {
                 println!("{msg}");
                 
@@ -351,7 +452,9 @@ impl State {
                     layouts,
                     self.last_active_layout,
                     size,
-                    position
+                    position,
+                    self.recorder.is_some(),
+                    self.endpoints.clone(),
                 );
             
                 match serde_json::to_string(&layout) {
@@ -369,6 +472,7 @@ impl State {
             },
             Message::ShowLayoutModal => {
                 self.show_layout_modal = true;
+                self.recordings = replay::Recording::list();
                 iced::widget::focus_next()
             },
             Message::HideLayoutModal => {
@@ -383,6 +487,54 @@ impl State {
                     move |_| Message::ClearNotification
                 )
             },
+            Message::TickersFetched(exchange, result) => {
+                match result {
+                    Ok(tickers) => {
+                        log::info!("Fetched {} tickers for {exchange}", tickers.len());
+
+                        self.tickers.insert(exchange, tickers);
+
+                        Task::none()
+                    },
+                    Err(err) => Task::perform(
+                        async {},
+                        move |_| Message::ErrorOccurred(
+                            Error::FetchError(format!("{exchange} tickers: {err}"))
+                        )
+                    ),
+                }
+            },
+            Message::SampleFeeds => {
+                let dashboard = self.get_mut_dashboard();
+
+                let active_streams: Vec<StreamType> = dashboard.pane_streams.values()
+                    .flat_map(|tickers| tickers.values().flatten().copied())
+                    .collect();
+
+                dashboard.feed_monitor.sample(
+                    chrono::Utc::now().timestamp_millis(),
+                    data_providers::throughput::take(),
+                    &active_streams,
+                );
+
+                Task::none()
+            },
+            Message::SyncClocks => {
+                Task::batch(
+                    data_providers::clock::SOURCES.map(sync_clock_task)
+                )
+            },
+            Message::ClockSynced(exchange, result) => {
+                match result {
+                    Ok(offset) => {
+                        self.clock_offsets.insert(exchange, offset);
+                    },
+                    // keeps the last estimate, the next sync will likely get through
+                    Err(err) => log::warn!("Failed to sync {exchange} clock: {err}"),
+                }
+
+                Task::none()
+            },
             Message::ErrorOccurred(err) => {
                 match err {
                     Error::FetchError(err) => {
@@ -446,6 +598,7 @@ impl State {
                 let new_dashboard = Dashboard::empty();
 
                 self.layouts.insert(self.last_active_layout, new_dashboard);
+                self.sync_stream_subscriptions();
 
                 Task::perform(
                     async {},
@@ -460,6 +613,7 @@ impl State {
                 let dashboard = self.get_mut_dashboard();
 
                 let layout_fetch_command = dashboard.layout_changed();
+                self.sync_stream_subscriptions();
             
                 Task::batch(vec![
                     layout_fetch_command.map(Message::Dashboard),
@@ -471,6 +625,7 @@ impl State {
                 let command = dashboard.update(
                     message,
                 );
+                self.sync_stream_subscriptions();
 
                 Task::batch(vec![
                     command.map(Message::Dashboard),
@@ -563,6 +718,36 @@ impl State {
             }
         }
 
+        if let Some(replay) = &self.replay {
+            let position = chrono::DateTime::from_timestamp_millis(replay.position())
+                .map_or(String::new(), |time| time.format("%H:%M:%S").to_string());
+
+            ws_controls = ws_controls.push(
+                Row::new()
+                    .spacing(8)
+                    .align_y(Alignment::Center)
+                    .push(
+                        button(if replay.is_playing() { "Pause" } else { "Play" })
+                            .on_press(Message::ReplayTogglePlay)
+                    )
+                    .push(
+                        pick_list(&replay::Speed::ALL[..], Some(replay.speed()), Message::ReplaySpeedSelected)
+                            .style(style::picklist_primary)
+                            .menu_style(style::picklist_menu_primary)
+                    )
+                    .push(
+                        Slider::new(
+                            replay.start_time() as f64..=replay.end_time() as f64,
+                            replay.position() as f64,
+                            |time| Message::ReplaySeek(time as i64)
+                        )
+                        .width(iced::Pixels(300.0))
+                    )
+                    .push(Text::new(format!("{} {position}", replay.recording)).size(14))
+                    .push(button("Exit replay").on_press(Message::StopReplay))
+            );
+        }
+
         let content = Column::new()
             .padding(10)
             .spacing(10)
@@ -576,7 +761,7 @@ impl State {
                     .push(layout_controls)
             )
             .push(
-                dashboard.view().map(Message::Dashboard)
+                dashboard.view(&self.tickers).map(Message::Dashboard)
             );
 
         if self.show_layout_modal {
@@ -655,7 +840,51 @@ impl State {
                             .spacing(8)
                             .push(add_pane_button)
                             .push(replace_pane_button)
-                    )       
+                    )
+                    .push(
+                        Column::new()
+                            .align_x(Alignment::Center)
+                            .push(Text::new("Data"))
+                            .padding([8, 0])
+                            .spacing(8)
+                            .push(
+                                tooltip(
+                                    checkbox("Record market data", self.recorder.is_some())
+                                        .on_toggle(Message::ToggleRecording),
+                                    "Depth, trades and klines of open panes go to the recordings folder", 
+                                    tooltip::Position::Top
+                                ).style(style::tooltip)
+                            )
+                            .push(
+                                pick_list(&self.recordings[..], None::<replay::Recording>, Message::ReplaySelected)
+                                    .placeholder("Replay a recording...")
+                                    .style(style::picklist_primary)
+                                    .menu_style(style::picklist_menu_primary)
+                            )
+                    )
+                    .push(
+                        data_providers::clock::SOURCES.iter().fold(
+                            Column::new()
+                                .align_x(Alignment::Center)
+                                .push(
+                                    tooltip(
+                                        Text::new("Clock offset"),
+                                        "How far each exchange's clock is ahead of this machine's, corrected for in latencies and signed requests",
+                                        tooltip::Position::Top
+                                    ).style(style::tooltip)
+                                )
+                                .padding([8, 0])
+                                .spacing(4),
+                            |column, source| {
+                                let offset = match self.clock_offsets.get(source) {
+                                    Some(offset) => format!("{offset:+} ms"),
+                                    None => "not synced".to_string(),
+                                };
+
+                                column.push(Text::new(format!("{source}: {offset}")).size(12))
+                            }
+                        )
+                    )
                     .push(
                         button("Close")
                             .on_press(Message::HideLayoutModal)
@@ -674,63 +903,113 @@ impl State {
 
     fn subscription(&self) -> Subscription<Message> {
         let mut all_subscriptions = Vec::new();
-    
-        for (exchange, stream) in &self.get_dashboard().pane_streams {
-            let mut depth_streams: Vec<Subscription<Message>> = Vec::new();
-            let mut kline_streams: Vec<(Ticker, Timeframe)> = Vec::new();
-    
-            for stream_types in stream.values() {
-                for stream_type in stream_types {
-                    match stream_type {
-                        StreamType::Kline { ticker, timeframe, .. } => {
-                            kline_streams.push((*ticker, *timeframe));
-                        },
-                        StreamType::DepthAndTrades { ticker, .. } => {
-                            let ticker = *ticker;
-
-                            let depth_stream = match exchange {
-                                Exchange::BinanceFutures => {
-                                    Subscription::run_with_id(ticker, binance::market_data::connect_market_stream(ticker))
-                                        .map(|event| Message::MarketWsEvent(MarketEvents::Binance(event)))
-                                },
-                                Exchange::BybitLinear => {
-                                    Subscription::run_with_id(ticker, bybit::market_data::connect_market_stream(ticker))
-                                        .map(|event| Message::MarketWsEvent(MarketEvents::Bybit(event)))
-                                },
-                            };
-                            depth_streams.push(depth_stream);
-                        },
-                        _ => {}
-                    }
-                }
-            }
-    
-            if !kline_streams.is_empty() {
-                let kline_streams_id = kline_streams.clone();
 
-                let kline_subscription = match exchange {
-                    Exchange::BinanceFutures => {
-                        Subscription::run_with_id(kline_streams_id, binance::market_data::connect_kline_stream(kline_streams))
-                            .map(|event| Message::MarketWsEvent(MarketEvents::Binance(event)))
-                    },
-                    Exchange::BybitLinear => {
-                        Subscription::run_with_id(kline_streams_id, bybit::market_data::connect_kline_stream(kline_streams))
-                            .map(|event| Message::MarketWsEvent(MarketEvents::Bybit(event)))
-                    },
-                };
-                all_subscriptions.push(kline_subscription);
+        all_subscriptions.push(events().map(Message::Event));
+
+        all_subscriptions.push(
+            iced::time::every(data_providers::clock::SYNC_INTERVAL).map(|_| Message::SyncClocks)
+        );
+
+        all_subscriptions.push(
+            iced::time::every(std::time::Duration::from_secs(1)).map(|_| Message::SampleFeeds)
+        );
+
+        if let Some(replay) = &self.replay {
+            if replay.is_playing() {
+                all_subscriptions.push(
+                    iced::time::every(std::time::Duration::from_millis(50)).map(Message::ReplayTick)
+                );
             }
+
+            return Subscription::batch(all_subscriptions);
+        }
     
-            if !depth_streams.is_empty() {
-                all_subscriptions.push(Subscription::batch(depth_streams));
+        // the connection outlives pane changes, only its subscriptions follow them, see `sync_stream_subscriptions`
+        for (exchange, streams) in &self.get_dashboard().pane_streams {
+            if streams.values().all(HashSet::is_empty) {
+                continue;
             }
+
+            all_subscriptions.push(
+                Subscription::run_with_id(
+                    *exchange,
+                    data_providers::provider(*exchange).connect(self.stream_subscriptions.streams(*exchange))
+                )
+                .map(Message::MarketWsEvent)
+            );
         }
 
-        all_subscriptions.push(events().map(Message::Event));
-    
         Subscription::batch(all_subscriptions)
     }    
     
+    fn distribute_market_event(&mut self, event: data_providers::Event) {
+        let dashboard = self.get_mut_dashboard();
+
+        match event {
+            data_providers::Event::Connection(streams, state) => {
+                if let data_providers::ConnectionState::Down(reason) = &state {
+                    log::info!("streams disconnected {streams:?}: {reason}");
+                }
+                dashboard.set_stream_state(streams, state);
+            }
+            data_providers::Event::DepthReceived(stream_type, feed_latency, depth_update_t, depth, trades_buffer) => {
+                dashboard.feed_monitor.record_latency(stream_type, feed_latency);
+
+                if let Err(err) = dashboard.update_depth_and_trades(stream_type, depth_update_t, depth, trades_buffer) {
+                    log::error!("{err}, {stream_type:?}");
+                }
+            }
+            data_providers::Event::KlineReceived(stream_type, kline) => {
+                if let Err(err) = dashboard.update_latest_klines(&stream_type, &kline) {
+                    log::error!("{err}, {stream_type:?}");
+                }
+            }
+            data_providers::Event::MarketStatsReceived(stream_type, stats) => {
+                if let Err(err) = dashboard.update_market_stats(&stream_type, stats) {
+                    log::error!("{err}, {stream_type:?}");
+                }
+            }
+            data_providers::Event::LiquidationsReceived(stream_type, liquidations) => {
+                if let Err(err) = dashboard.update_liquidations(&stream_type, &liquidations) {
+                    log::error!("{err}, {stream_type:?}");
+                }
+            }
+        }
+    }
+
+    /// Depth streams of the panes showing the ticker being replayed
+    fn replayed_depth_streams(&self) -> Vec<StreamType> {
+        let Some(replay) = &self.replay else {
+            return vec![];
+        };
+        let replay::Recording { exchange, ticker, .. } = replay.recording;
+
+        self.get_dashboard().pane_streams
+            .get(&exchange)
+            .and_then(|streams| streams.get(&ticker))
+            .map(|streams| streams.iter()
+                .filter(|stream| matches!(stream, StreamType::DepthAndTrades { .. }))
+                .copied()
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Points each exchange's connection at the streams the active layout's panes show,
+    /// called wherever the layout's `pane_streams` may have changed
+    fn sync_stream_subscriptions(&self) {
+        let pane_streams = &self.get_dashboard().pane_streams;
+
+        for exchange in Exchange::ALL {
+            let streams: HashSet<StreamType> = pane_streams.get(&exchange)
+                .into_iter()
+                .flat_map(|streams| streams.values().flatten())
+                .copied()
+                .collect();
+
+            self.stream_subscriptions.set(exchange, streams);
+        }
+    }
+
     fn get_mut_dashboard(&mut self) -> &mut Dashboard {
         self.layouts
             .get_mut(&self.last_active_layout)
@@ -742,43 +1021,20 @@ impl State {
             .get(&self.last_active_layout)
             .expect("No active layout")
     }
+}
 
-    fn update_exchange_latency(&mut self) {
-        let mut depth_latency_sum: i64 = 0;
-        let mut depth_latency_count: i64 = 0;
-        let mut trade_latency_sum: i64 = 0;
-        let mut trade_latency_count: i64 = 0;
-
-        for feed_latency in self.feed_latency_cache.iter() {
-            depth_latency_sum += feed_latency.depth_latency;
-            depth_latency_count += 1;
-
-            if let Some(trade_latency) = feed_latency.trade_latency {
-                trade_latency_sum += trade_latency;
-                trade_latency_count += 1;
-            }
-        }
-
-        let average_depth_latency: Option<i64> = if depth_latency_count > 0 {
-            Some(depth_latency_sum / depth_latency_count)
-        } else {
-            None
-        };
-
-        let average_trade_latency: Option<i64> = if trade_latency_count > 0 {
-            Some(trade_latency_sum / trade_latency_count)
-        } else {
-            None
-        };
-
-        if let (Some(average_depth_latency), Some(average_trade_latency)) = (average_depth_latency, average_trade_latency) {
-            self.exchange_latency = Some((average_depth_latency as u32, average_trade_latency as u32));
-        }
+fn fetch_ticker_list_task(exchange: Exchange) -> Task<Message> {
+    Task::perform(
+        data_providers::provider(exchange).fetch_ticker_list(),
+        move |result| Message::TickersFetched(exchange, result)
+    )
+}
 
-        while self.feed_latency_cache.len() > 100 {
-            self.feed_latency_cache.pop_front();
-        }
-    }
+fn sync_clock_task(source: Exchange) -> Task<Message> {
+    Task::perform(
+        data_providers::clock::sync(source),
+        move |result| Message::ClockSynced(source, result)
+    )
 }
 
 fn modal<'a, Message>(
@@ -858,6 +1114,8 @@ struct SavedState {
     last_active_layout: LayoutId,
     window_size: Option<(f32, f32)>,
     window_position: Option<(f32, f32)>,
+    record_market_data: bool,
+    endpoints: data_providers::Endpoints,
 }
 impl Default for SavedState {
     fn default() -> Self {
@@ -872,6 +1130,8 @@ impl Default for SavedState {
             last_active_layout: LayoutId::Layout1,
             window_size: None,
             window_position: None,
+            record_market_data: false,
+            endpoints: data_providers::Endpoints::default(),
         }
     }
 }
@@ -903,6 +1163,11 @@ struct SerializableState {
     pub last_active_layout: LayoutId,
     pub window_size: Option<(f32, f32)>,
     pub window_position: Option<(f32, f32)>,
+    #[serde(default)]
+    pub record_market_data: bool,
+    /// Base URL and stale-feed timeout overrides per exchange, for mirrors, slow venues or a local mock server
+    #[serde(default)]
+    pub endpoints: data_providers::Endpoints,
 }
 impl SerializableState {
     fn from_parts(
@@ -910,12 +1175,16 @@ impl SerializableState {
         last_active_layout: LayoutId,
         size: Option<Size>,
         position: Option<Point>,
+        record_market_data: bool,
+        endpoints: data_providers::Endpoints,
     ) -> Self {
         SerializableState {
             layouts,
             last_active_layout,
             window_size: size.map(|s| (s.width, s.height)),
             window_position: position.map(|p| (p.x, p.y)),
+            record_market_data,
+            endpoints,
         }
     }
 }
\ No newline at end of file
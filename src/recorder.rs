@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime};
+
+use chrono::NaiveDate;
+
+use crate::data_providers::{level_changes, Depth, DepthResolution, Event, Exchange, Kline, Order, StreamType, Ticker, Timeframe, Trade};
+
+pub const RECORDINGS_DIR: &str = "recordings";
+
+/// A day's file rolls over into a new part once it grows past this
+const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+/// The oldest files get deleted once all recordings together go past this
+const MAX_TOTAL_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Exchange time between full books, replays seek from the nearest one instead of the start of the day
+const SNAPSHOT_INTERVAL: i64 = 60 * 1000;
+
+// every file starts with the magic and format version, followed by tagged records in little endian
+pub const MAGIC: &[u8; 4] = b"ITRC";
+/// Version 2 stores kline prices as fixed-point units like trade and depth prices
+pub const VERSION: u8 = 2;
+
+/// Full book, always the first depth record of a file and then once every `SNAPSHOT_INTERVAL`
+pub const TAG_DEPTH_SNAPSHOT: u8 = 1;
+/// Only the levels that changed since the previous depth record, a zero qty removes the level
+pub const TAG_DEPTH_DIFF: u8 = 2;
+pub const TAG_TRADE: u8 = 3;
+pub const TAG_KLINE: u8 = 4;
+
+enum Record {
+    Depth(Exchange, Ticker, Option<DepthResolution>, i64, Depth, Vec<Trade>),
+    Kline(Exchange, Ticker, Timeframe, Kline),
+}
+
+impl Record {
+    fn source(&self) -> (Exchange, Ticker) {
+        match self {
+            Record::Depth(exchange, ticker, ..) | Record::Kline(exchange, ticker, ..) => (*exchange, *ticker),
+        }
+    }
+
+    /// Exchange timestamp the record is filed under
+    fn time(&self) -> i64 {
+        match self {
+            Record::Depth(_, _, _, time, ..) => *time,
+            Record::Kline(_, _, _, kline) => kline.time as i64,
+        }
+    }
+}
+
+/// Hands market events over to a writer thread, so disk IO never blocks the UI
+pub struct Recorder {
+    sender: mpsc::Sender<Record>,
+}
+
+impl Recorder {
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || run(receiver, PathBuf::from(RECORDINGS_DIR)));
+
+        Self { sender }
+    }
+
+    pub fn record(&self, event: &Event) {
+        let record = match event {
+            Event::DepthReceived(StreamType::DepthAndTrades { exchange, ticker, resolution }, _, time, depth, trades) => {
+                Record::Depth(*exchange, *ticker, *resolution, *time, depth.clone(), trades.clone())
+            }
+            Event::KlineReceived(StreamType::Kline { exchange, ticker, timeframe }, kline) => {
+                Record::Kline(*exchange, *ticker, *timeframe, *kline)
+            }
+            _ => return,
+        };
+
+        // only fails if the writer thread is gone, which already got logged there
+        let _ = self.sender.send(record);
+    }
+}
+
+struct DayFile {
+    path: PathBuf,
+    date: NaiveDate,
+    part: u32,
+    writer: BufWriter<File>,
+    written: u64,
+    last_depth: Option<Depth>,
+    last_snapshot: i64,
+    /// Panes on the same ticker at different depth resolutions each get the trades too,
+    /// so only the first resolution seen in a file gets recorded
+    depth_resolution: Option<Option<DepthResolution>>,
+}
+
+fn run(receiver: mpsc::Receiver<Record>, dir: PathBuf) {
+    let mut files: HashMap<(Exchange, Ticker), DayFile> = HashMap::new();
+
+    loop {
+        match receiver.recv_timeout(FLUSH_INTERVAL) {
+            Ok(record) => {
+                if let Err(e) = write_record(&mut files, &dir, record) {
+                    log::error!("Failed to record market data: {e}");
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => flush_all(&mut files),
+            Err(RecvTimeoutError::Disconnected) => {
+                flush_all(&mut files);
+                break;
+            }
+        }
+    }
+}
+
+fn flush_all(files: &mut HashMap<(Exchange, Ticker), DayFile>) {
+    for file in files.values_mut() {
+        if let Err(e) = file.writer.flush() {
+            log::error!("Failed to flush {}: {e}", file.path.display());
+        }
+    }
+}
+
+fn write_record(
+    files: &mut HashMap<(Exchange, Ticker), DayFile>,
+    dir: &Path,
+    record: Record,
+) -> io::Result<()> {
+    let (exchange, ticker) = record.source();
+
+    let date = chrono::DateTime::from_timestamp_millis(record.time())
+        .map(|time| time.date_naive())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "timestamp out of range"))?;
+
+    let rotate = files.get(&(exchange, ticker))
+        .is_none_or(|file| file.date != date || file.written >= MAX_FILE_SIZE);
+
+    if rotate {
+        let first_part = files.get(&(exchange, ticker))
+            .filter(|file| file.date == date)
+            .map_or(0, |file| file.part + 1);
+
+        if let Some(mut old) = files.remove(&(exchange, ticker)) {
+            old.writer.flush()?;
+        }
+
+        let file = open_day_file(dir, exchange, ticker, date, first_part)?;
+        files.insert((exchange, ticker), file);
+
+        let open_paths: Vec<&Path> = files.values().map(|file| file.path.as_path()).collect();
+        prune(dir, &open_paths);
+    }
+
+    let file = files.get_mut(&(exchange, ticker))
+        .expect("day file was just opened");
+
+    let mut buf = Vec::new();
+
+    match record {
+        Record::Depth(_, _, resolution, time, depth, trades) => {
+            if *file.depth_resolution.get_or_insert(resolution) != resolution {
+                return Ok(());
+            }
+
+            let is_snapshot_due = time - file.last_snapshot >= SNAPSHOT_INTERVAL;
+
+            match &file.last_depth {
+                Some(last) if !is_snapshot_due => {
+                    buf.push(TAG_DEPTH_DIFF);
+                    buf.extend_from_slice(&time.to_le_bytes());
+                    encode_levels(&mut buf, &level_changes(last.bids(), depth.bids()));
+                    encode_levels(&mut buf, &level_changes(last.asks(), depth.asks()));
+                }
+                _ => {
+                    buf.push(TAG_DEPTH_SNAPSHOT);
+                    buf.extend_from_slice(&time.to_le_bytes());
+                    encode_levels(&mut buf, &depth.bids().collect::<Vec<_>>());
+                    encode_levels(&mut buf, &depth.asks().collect::<Vec<_>>());
+
+                    file.last_snapshot = time;
+                }
+            }
+            file.last_depth = Some(depth);
+
+            for trade in &trades {
+                buf.push(TAG_TRADE);
+                buf.extend_from_slice(&trade.time.to_le_bytes());
+                buf.extend_from_slice(&trade.price.units().to_le_bytes());
+                buf.extend_from_slice(&trade.qty.to_le_bytes());
+                buf.push(u8::from(trade.is_sell));
+            }
+        }
+        Record::Kline(_, _, timeframe, kline) => {
+            buf.push(TAG_KLINE);
+            buf.extend_from_slice(&timeframe.to_minutes().to_le_bytes());
+            buf.extend_from_slice(&kline.time.to_le_bytes());
+            for price in [kline.open, kline.high, kline.low, kline.close] {
+                buf.extend_from_slice(&price.units().to_le_bytes());
+            }
+            for volume in [kline.volume.0, kline.volume.1] {
+                buf.extend_from_slice(&volume.to_le_bytes());
+            }
+        }
+    }
+
+    file.writer.write_all(&buf)?;
+    file.written += buf.len() as u64;
+
+    Ok(())
+}
+
+/// Appends to the first part of the day that still has room, e.g. `recordings/BinanceFutures/BTCUSDT/2024-06-11.0.bin`
+fn open_day_file(dir: &Path, exchange: Exchange, ticker: Ticker, date: NaiveDate, first_part: u32) -> io::Result<DayFile> {
+    let ticker_dir = dir.join(format!("{exchange:?}")).join(ticker.to_string());
+    fs::create_dir_all(&ticker_dir)?;
+
+    let mut part = first_part;
+
+    loop {
+        let path = ticker_dir.join(format!("{}.{part}.bin", date.format("%Y-%m-%d")));
+        let written = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+
+        if written >= MAX_FILE_SIZE {
+            part += 1;
+            continue;
+        }
+
+        let mut writer = BufWriter::new(OpenOptions::new().create(true).append(true).open(&path)?);
+
+        let written = if written == 0 {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&[VERSION])?;
+            (MAGIC.len() + 1) as u64
+        } else {
+            written
+        };
+
+        return Ok(DayFile {
+            path,
+            date,
+            part,
+            writer,
+            written,
+            last_depth: None,
+            last_snapshot: 0,
+            depth_resolution: None,
+        });
+    }
+}
+
+fn encode_levels(buf: &mut Vec<u8>, levels: &[Order]) {
+    buf.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+
+    for level in levels {
+        buf.extend_from_slice(&level.price.units().to_le_bytes());
+        buf.extend_from_slice(&level.qty.to_le_bytes());
+    }
+}
+
+/// Deletes the oldest recordings until they fit under the size cap, leaving open files alone
+fn prune(dir: &Path, open_paths: &[&Path]) {
+    fn collect(dir: &Path, files: &mut Vec<(PathBuf, u64, SystemTime)>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => collect(&path, files),
+                Ok(metadata) => {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    files.push((path, metadata.len(), modified));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    collect(dir, &mut files);
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+
+    files.sort_by_key(|(_, _, modified)| *modified);
+
+    for (path, len, _) in files {
+        if total <= MAX_TOTAL_SIZE {
+            break;
+        }
+        if open_paths.contains(&path.as_path()) {
+            continue;
+        }
+
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                log::info!("Deleted {} to stay under the recordings size cap", path.display());
+                total = total.saturating_sub(len);
+            }
+            Err(e) => log::error!("Failed to delete {}: {e}", path.display()),
+        }
+    }
+}
@@ -0,0 +1,654 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDate;
+
+use crate::data_providers::{
+    DepthUpdate, Event, Exchange, FeedLatency, Kline, LocalDepthCache, Order, Price, StreamType, Ticker, Timeframe, Trade
+};
+use crate::recorder::{MAGIC, RECORDINGS_DIR, TAG_DEPTH_DIFF, TAG_DEPTH_SNAPSHOT, TAG_KLINE, TAG_TRADE, VERSION};
+
+/// A recorded day of one exchange's ticker, which may span several file parts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recording {
+    pub exchange: Exchange,
+    pub ticker: Ticker,
+    pub date: NaiveDate,
+}
+
+impl std::fmt::Display for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.exchange, self.ticker, self.date.format("%Y-%m-%d"))
+    }
+}
+
+impl Recording {
+    /// Every recorded day found under the recordings folder, newest first
+    pub fn list() -> Vec<Recording> {
+        let mut recordings = Vec::new();
+
+        for exchange in Exchange::ALL {
+            let Ok(tickers) = fs::read_dir(Path::new(RECORDINGS_DIR).join(format!("{exchange:?}"))) else {
+                continue;
+            };
+
+            for ticker_dir in tickers.flatten() {
+                let name = ticker_dir.file_name();
+                let Some(symbol) = name.to_str().filter(|symbol| Ticker::is_valid_symbol(symbol)) else {
+                    continue;
+                };
+                let ticker = Ticker::new(symbol);
+
+                let Ok(files) = fs::read_dir(ticker_dir.path()) else {
+                    continue;
+                };
+
+                for file in files.flatten() {
+                    let date = file.file_name().to_str()
+                        .and_then(|name| name.split('.').next())
+                        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+
+                    if let Some(date) = date {
+                        let recording = Recording { exchange, ticker, date };
+
+                        if !recordings.contains(&recording) {
+                            recordings.push(recording);
+                        }
+                    }
+                }
+            }
+        }
+
+        recordings.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.to_string().cmp(&b.to_string())));
+        recordings
+    }
+
+    /// Parts of the day in the order they were written
+    fn paths(&self) -> io::Result<Vec<PathBuf>> {
+        let ticker_dir = Path::new(RECORDINGS_DIR)
+            .join(format!("{:?}", self.exchange))
+            .join(self.ticker.to_string());
+        let date = self.date.format("%Y-%m-%d").to_string();
+
+        let mut parts: Vec<(u32, PathBuf)> = fs::read_dir(ticker_dir)?
+            .flatten()
+            .filter_map(|file| {
+                let name = file.file_name();
+                let part = name.to_str()?
+                    .strip_prefix(&date)?
+                    .strip_prefix('.')?
+                    .strip_suffix(".bin")?
+                    .parse()
+                    .ok()?;
+
+                Some((part, file.path()))
+            })
+            .collect();
+
+        parts.sort_by_key(|(part, _)| *part);
+
+        Ok(parts.into_iter().map(|(_, path)| path).collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Speed(pub f32);
+
+impl Speed {
+    pub const ALL: [Speed; 7] = [
+        Speed(0.5), Speed(1.0), Speed(2.0), Speed(5.0), Speed(10.0), Speed(25.0), Speed(50.0)
+    ];
+}
+
+impl std::fmt::Display for Speed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x", self.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Frame {
+    Depth {
+        snapshot: bool,
+        bids: Vec<Order>,
+        asks: Vec<Order>,
+        trades: Vec<Trade>,
+    },
+    Kline(Timeframe, Kline),
+}
+
+/// Where a full book was recorded
+#[derive(Debug, Clone, Copy)]
+struct Checkpoint {
+    time: i64,
+    part: usize,
+    offset: u64,
+}
+
+/// Plays a recording back as the same market events the live streams emit, on a clock of its own.
+/// Frames are read from disk as the clock gets to them, only the full books' positions and the klines are kept around
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub recording: Recording,
+    frames: FrameReader,
+    /// Read ahead of the clock, played once it gets there
+    pending: Option<(i64, Frame)>,
+    /// Seeks rebuild the book from the last one of these before the target
+    checkpoints: Vec<Checkpoint>,
+    /// Every kline as of its last update, along with the time that update gets played at
+    klines: HashMap<Timeframe, BTreeMap<u64, (i64, Kline)>>,
+    start_time: i64,
+    end_time: i64,
+    depth: LocalDepthCache,
+    position: i64,
+    playing: bool,
+    speed: Speed,
+    last_tick: Option<Instant>,
+}
+
+impl Replay {
+    pub fn load(recording: Recording) -> io::Result<Self> {
+        Self::from_parts(recording, recording.paths()?)
+    }
+
+    /// Goes through the parts once to find the time range, the full books and the klines
+    fn from_parts(recording: Recording, paths: Vec<PathBuf>) -> io::Result<Self> {
+        let mut frames = FrameReader::new(paths);
+        let mut checkpoints = Vec::new();
+        let mut klines: HashMap<Timeframe, BTreeMap<u64, (i64, Kline)>> = HashMap::new();
+        let mut range: Option<(i64, i64)> = None;
+
+        while let Some((time, frame)) = frames.next()? {
+            match frame {
+                Frame::Depth { snapshot: true, .. } => {
+                    let (part, offset) = frames.frame_start;
+                    checkpoints.push(Checkpoint { time, part, offset });
+                }
+                Frame::Depth { .. } => {}
+                Frame::Kline(timeframe, kline) => {
+                    klines.entry(timeframe).or_default().insert(kline.time, (time, kline));
+                }
+            }
+
+            range = Some(range.map_or((time, time), |(start, _)| (start, time)));
+        }
+
+        let Some((start_time, end_time)) = range else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "recording has no data"));
+        };
+
+        frames.seek(0, 0, 0);
+
+        Ok(Self {
+            recording,
+            frames,
+            pending: None,
+            checkpoints,
+            klines,
+            start_time,
+            end_time,
+            depth: LocalDepthCache::default(),
+            position: start_time,
+            playing: false,
+            speed: Speed(1.0),
+            last_tick: None,
+        })
+    }
+
+    pub fn start_time(&self) -> i64 {
+        self.start_time
+    }
+
+    pub fn end_time(&self) -> i64 {
+        self.end_time
+    }
+
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    pub fn toggle_play(&mut self) {
+        self.playing = !self.playing;
+        self.last_tick = None;
+
+        // playing again from the end starts over
+        if self.playing && self.position >= self.end_time() {
+            self.seek(self.start_time());
+        }
+    }
+
+    /// Moves the clock without emitting what's in between, the book still gets rebuilt up to that point.
+    /// It's rebuilt from the last full book before `time`, unless the book is already past that one
+    pub fn seek(&mut self, time: i64) {
+        let time = time.clamp(self.start_time(), self.end_time());
+
+        let checkpoint = self.checkpoints[..self.checkpoints.partition_point(|checkpoint| checkpoint.time <= time)]
+            .last()
+            .copied();
+
+        let can_resume = time >= self.position
+            && checkpoint.is_none_or(|checkpoint| checkpoint.time <= self.position);
+
+        if !can_resume {
+            match checkpoint {
+                Some(Checkpoint { time, part, offset }) => self.frames.seek(part, offset, time),
+                None => self.frames.seek(0, 0, 0),
+            }
+            self.pending = None;
+            self.depth = LocalDepthCache::default();
+        }
+        self.position = time;
+
+        while let Some((frame_time, frame)) = self.next_frame() {
+            if frame_time > time {
+                self.pending = Some((frame_time, frame));
+                break;
+            }
+            if let Frame::Depth { snapshot, bids, asks, .. } = frame {
+                apply_depth(&mut self.depth, frame_time, snapshot, bids, asks);
+            }
+        }
+    }
+
+    /// Advances the clock by the wall time since the last tick scaled by the speed, and returns the events that fall in it
+    pub fn tick(&mut self, now: Instant) -> Vec<Event> {
+        if !self.playing {
+            return vec![];
+        }
+
+        let elapsed = self.last_tick.map_or(Duration::ZERO, |last| now.duration_since(last));
+        self.last_tick = Some(now);
+
+        self.position = (self.position + (elapsed.as_secs_f32() * 1000.0 * self.speed.0) as i64)
+            .min(self.end_time());
+
+        let Recording { exchange, ticker, .. } = self.recording;
+
+        let mut events = vec![];
+
+        while let Some((time, frame)) = self.next_frame() {
+            if time > self.position {
+                self.pending = Some((time, frame));
+                break;
+            }
+
+            match frame {
+                Frame::Depth { snapshot, bids, asks, trades } => {
+                    apply_depth(&mut self.depth, time, snapshot, bids, asks);
+
+                    events.push(Event::DepthReceived(
+                        StreamType::DepthAndTrades { exchange, ticker, resolution: None },
+                        FeedLatency { time, depth_latency: 0, trade_latency: None },
+                        time,
+                        self.depth.get_depth(),
+                        trades,
+                    ));
+                }
+                Frame::Kline(timeframe, kline) => {
+                    events.push(Event::KlineReceived(
+                        StreamType::Kline { exchange, ticker, timeframe },
+                        kline,
+                    ));
+                }
+            }
+        }
+
+        if self.pending.is_none() {
+            self.playing = false;
+        }
+
+        events
+    }
+
+    /// Klines whose last update got played by now, for seeding the charts after a seek.
+    /// The one still forming at the position comes along with its next update
+    pub fn klines_until_position(&self) -> HashMap<Timeframe, Vec<Kline>> {
+        self.klines.iter()
+            .map(|(timeframe, klines)| {
+                let played = klines.values()
+                    .filter(|(time, _)| *time <= self.position)
+                    .map(|(_, kline)| *kline)
+                    .collect();
+
+                (*timeframe, played)
+            })
+            .collect()
+    }
+
+    fn next_frame(&mut self) -> Option<(i64, Frame)> {
+        if let Some(frame) = self.pending.take() {
+            return Some(frame);
+        }
+
+        self.frames.next().unwrap_or_else(|e| {
+            log::error!("Failed to read recording {}: {e}", self.recording);
+            None
+        })
+    }
+}
+
+fn apply_depth(depth: &mut LocalDepthCache, time: i64, snapshot: bool, bids: Vec<Order>, asks: Vec<Order>) {
+    let update = DepthUpdate {
+        last_update_id: 0,
+        time,
+        bids,
+        asks,
+    };
+
+    if snapshot {
+        depth.fetched(update);
+    } else {
+        depth.update_depth_cache(update);
+    }
+}
+
+/// Reads the frames of a recording's parts one after the other
+#[derive(Debug)]
+struct FrameReader {
+    paths: Vec<PathBuf>,
+    part: usize,
+    /// Byte offset of the next record in the current part
+    offset: u64,
+    /// Part and offset of the frame read last
+    frame_start: (usize, u64),
+    /// Time of the last depth record, klines are played at it
+    last_time: i64,
+    /// Opened on the first read after a seek
+    reader: Option<Reader<BufReader<File>>>,
+}
+
+/// The copy opens the file again where the original was at
+impl Clone for FrameReader {
+    fn clone(&self) -> Self {
+        Self {
+            paths: self.paths.clone(),
+            part: self.part,
+            offset: self.offset,
+            frame_start: self.frame_start,
+            last_time: self.last_time,
+            reader: None,
+        }
+    }
+}
+
+impl FrameReader {
+    fn new(paths: Vec<PathBuf>) -> Self {
+        Self {
+            paths,
+            part: 0,
+            offset: 0,
+            frame_start: (0, 0),
+            last_time: 0,
+            reader: None,
+        }
+    }
+
+    /// Continues from the record at `offset` of `part`, with `last_time` as the time of the depth record before it
+    fn seek(&mut self, part: usize, offset: u64, last_time: i64) {
+        self.part = part;
+        self.offset = offset;
+        self.last_time = last_time;
+        self.reader = None;
+    }
+
+    fn next(&mut self) -> io::Result<Option<(i64, Frame)>> {
+        while let Some(path) = self.paths.get(self.part) {
+            let with_path = |e: io::Error| io::Error::new(e.kind(), format!("{}: {e}", path.display()));
+
+            if self.reader.is_none() {
+                self.reader = Some(open_part(path, self.offset).map_err(with_path)?);
+            }
+            let reader = self.reader.as_mut().expect("part was just opened");
+            let start = reader.pos;
+
+            if !reader.is_empty().map_err(with_path)? {
+                match decode_frame(reader, &mut self.last_time) {
+                    Ok(frame) => {
+                        self.frame_start = (self.part, start);
+                        self.offset = reader.pos;
+                        return Ok(Some(frame));
+                    }
+                    // the file is still being written to, or the app went down mid-write
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        log::warn!("Recording ends with a partial record, skipping it");
+                    }
+                    Err(e) => return Err(with_path(e)),
+                }
+            }
+
+            self.part += 1;
+            self.offset = 0;
+            self.reader = None;
+        }
+
+        Ok(None)
+    }
+}
+
+/// Checks the header when starting from the top, else goes straight to the record at `offset`
+fn open_part(path: &Path, offset: u64) -> io::Result<Reader<BufReader<File>>> {
+    let mut file = BufReader::new(File::open(path)?);
+
+    if offset > 0 {
+        file.seek(SeekFrom::Start(offset))?;
+        return Ok(Reader { inner: file, pos: offset });
+    }
+
+    let mut reader = Reader { inner: file, pos: 0 };
+
+    if reader.array::<4>()? != *MAGIC {
+        return Err(invalid("not a recording"));
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(invalid(&format!("unsupported version {version}")));
+    }
+
+    Ok(reader)
+}
+
+/// Reads a depth record along with the trades that follow it, or a kline played at the time of the depth record before it
+fn decode_frame(reader: &mut Reader<impl BufRead>, last_time: &mut i64) -> io::Result<(i64, Frame)> {
+    match reader.u8()? {
+        tag @ (TAG_DEPTH_SNAPSHOT | TAG_DEPTH_DIFF) => {
+            let time = reader.i64()?;
+            let bids = reader.levels()?;
+            let asks = reader.levels()?;
+
+            let mut trades = vec![];
+
+            while reader.peek()? == Some(TAG_TRADE) {
+                reader.u8()?;
+
+                trades.push(Trade {
+                    time: reader.i64()?,
+                    price: Price::from_units(reader.i64()?),
+                    qty: reader.f32()?,
+                    is_sell: reader.u8()? != 0,
+                });
+            }
+
+            *last_time = time;
+
+            Ok((time, Frame::Depth {
+                snapshot: tag == TAG_DEPTH_SNAPSHOT,
+                bids,
+                asks,
+                trades,
+            }))
+        }
+        // trades always follow the depth record they came with
+        TAG_TRADE => Err(invalid("trade without a depth record")),
+        TAG_KLINE => {
+            let minutes = reader.u16()?;
+            let timeframe = Timeframe::ALL.into_iter()
+                .find(|timeframe| timeframe.to_minutes() == minutes)
+                .ok_or_else(|| invalid(&format!("unknown timeframe {minutes}")))?;
+
+            let time = reader.u64()?;
+            let mut prices = [Price::default(); 4];
+            for price in &mut prices {
+                *price = Price::from_units(reader.i64()?);
+            }
+            let [open, high, low, close] = prices;
+            let (buy_volume, sell_volume) = (reader.f32()?, reader.f32()?);
+
+            Ok(((*last_time).max(time as i64), Frame::Kline(timeframe, Kline {
+                time,
+                open,
+                high,
+                low,
+                close,
+                volume: (buy_volume, sell_volume),
+            })))
+        }
+        tag => Err(invalid(&format!("unknown record tag {tag}"))),
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Little endian values off a buffered file, counting the bytes read
+#[derive(Debug)]
+struct Reader<R> {
+    inner: R,
+    pos: u64,
+}
+
+impl<R: BufRead> Reader<R> {
+    fn is_empty(&mut self) -> io::Result<bool> {
+        Ok(self.peek()?.is_none())
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.inner.fill_buf()?.first().copied())
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.inner.read_exact(&mut bytes)?;
+        self.pos += N as u64;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn levels(&mut self) -> io::Result<Vec<Order>> {
+        let count = self.u32()?;
+
+        (0..count)
+            .map(|_| Ok(Order {
+                price: Price::from_units(self.i64()?),
+                qty: self.f32()?,
+            }))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth_record(buf: &mut Vec<u8>, tag: u8, time: i64, bid: (&str, f32)) {
+        buf.push(tag);
+        buf.extend_from_slice(&time.to_le_bytes());
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&bid.0.parse::<Price>().unwrap().units().to_le_bytes());
+        buf.extend_from_slice(&bid.1.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+    }
+
+    fn kline_record(buf: &mut Vec<u8>, time: u64, close: &str) {
+        buf.push(TAG_KLINE);
+        buf.extend_from_slice(&Timeframe::M1.to_minutes().to_le_bytes());
+        buf.extend_from_slice(&time.to_le_bytes());
+        for _ in 0..4 {
+            buf.extend_from_slice(&close.parse::<Price>().unwrap().units().to_le_bytes());
+        }
+        buf.extend_from_slice(&1.0f32.to_le_bytes());
+        buf.extend_from_slice(&2.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn seeks_back_from_the_nearest_full_book() {
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+
+        depth_record(&mut buf, TAG_DEPTH_SNAPSHOT, 0, ("100", 1.0));
+        kline_record(&mut buf, 0, "100");
+        depth_record(&mut buf, TAG_DEPTH_DIFF, 30_000, ("100", 2.0));
+        depth_record(&mut buf, TAG_DEPTH_SNAPSHOT, 60_000, ("101", 3.0));
+        kline_record(&mut buf, 60_000, "101");
+        depth_record(&mut buf, TAG_DEPTH_DIFF, 90_000, ("101", 4.0));
+        // cut off mid-write
+        buf.extend_from_slice(&[TAG_DEPTH_DIFF, 1, 2]);
+
+        let path = std::env::temp_dir().join(format!("replay-{}.0.bin", std::process::id()));
+        fs::write(&path, &buf).unwrap();
+
+        let recording = Recording {
+            exchange: Exchange::BinanceFutures,
+            ticker: Ticker::new("BTCUSDT"),
+            date: NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(),
+        };
+        let mut replay = Replay::from_parts(recording, vec![path.clone()]).unwrap();
+
+        assert_eq!((replay.start_time(), replay.end_time()), (0, 90_000));
+        assert_eq!(replay.checkpoints.iter().map(|checkpoint| checkpoint.time).collect::<Vec<_>>(), [0, 60_000]);
+
+        let best_bid = |replay: &Replay| replay.depth.best_bid().map(|order| (order.price.to_string(), order.qty));
+
+        replay.seek(90_000);
+        assert_eq!(best_bid(&replay), Some(("101".to_string(), 4.0)));
+
+        replay.seek(65_000);
+        assert_eq!(best_bid(&replay), Some(("101".to_string(), 3.0)));
+        assert_eq!(replay.klines_until_position()[&Timeframe::M1].len(), 2);
+
+        replay.seek(45_000);
+        assert_eq!(best_bid(&replay), Some(("100".to_string(), 2.0)));
+        assert_eq!(replay.klines_until_position()[&Timeframe::M1].len(), 1);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,19 +1,18 @@
 pub mod pane;
 
-use futures::TryFutureExt;
 use pane::SerializablePane;
 pub use pane::{Uuid, PaneState, PaneContent, PaneSettings};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    charts::{candlestick::CandlestickChart, footprint::FootprintChart, heatmap::HeatmapChart, timeandsales::TimeAndSales, Message as ChartMessage}, data_providers::{
-        binance, bybit, Depth, Exchange, Kline, TickMultiplier, Ticker, Timeframe, Trade
+    charts::{candlestick::CandlestickChart, feed_monitor::FeedMonitor, footprint::FootprintChart, timeandsales::TimeAndSales, Message as ChartMessage}, data_providers::{
+        self, ChartBasis, ConnectionState, Depth, Exchange, Kline, Liquidation, MarketStats, Price, TickMultiplier, Ticker, Timeframe, Trade, TradesBatch
     }, modal, style, StreamType
 };
 
 use super::{Error, Notification};
 
-use std::{collections::{HashMap, HashSet}, rc::Rc};
+use std::collections::{HashMap, HashSet};
 use iced::{widget::{button, container, pane_grid::{self, Configuration}, Column, PaneGrid, Text}, window, Alignment, Element, Length, Point, Size, Task};
 
 #[derive(Debug, Clone)]
@@ -22,8 +21,10 @@ pub enum Message {
     ErrorOccurred(Error),
     Notification(Notification),
     FetchEvent(Result<Vec<Kline>, String>, StreamType, Uuid),
+    OlderKlinesFetched(Result<Vec<Kline>, String>, StreamType, Uuid),
     FetchDistributeKlines(StreamType, Result<Vec<Kline>, String>),
-    FetchDistributeTicks(StreamType, Result<f32, String>),
+    FetchDistributeTicks(StreamType, Result<Price, String>),
+    TradesBackfilled(Uuid, StreamType, Result<TradesBatch, String>),
     FetchForLayout,
 }
 
@@ -33,6 +34,8 @@ pub struct Dashboard {
     pub layout_lock: bool,
     pub pane_streams: HashMap<Exchange, HashMap<Ticker, HashSet<StreamType>>>,
     pub notification: Option<Notification>,
+    pub stream_states: HashMap<StreamType, ConnectionState>,
+    pub feed_monitor: FeedMonitor,
 }
 impl Dashboard {
     pub fn empty() -> Self {
@@ -46,55 +49,25 @@ impl Dashboard {
                     axis: pane_grid::Axis::Vertical,
                     ratio: 0.5,
                     a: Box::new(Configuration::Pane(
-                        PaneState { 
-                            id: Uuid::new_v4(), 
-                            show_modal: false, 
-                            stream: vec![],
-                            content: PaneContent::Starter,
-                            settings: PaneSettings::default(),
-                        })
+                        PaneState::new(Uuid::new_v4(), vec![], PaneSettings::default()))
                     ),
                     b: Box::new(Configuration::Pane(
-                        PaneState { 
-                            id: Uuid::new_v4(), 
-                            show_modal: false, 
-                            stream: vec![],
-                            content: PaneContent::Starter,
-                            settings: PaneSettings::default(),
-                        })
+                        PaneState::new(Uuid::new_v4(), vec![], PaneSettings::default()))
                     ),
                 }),
                 b: Box::new(Configuration::Split {
                     axis: pane_grid::Axis::Vertical,
                     ratio: 0.5,
                     a: Box::new(Configuration::Pane(
-                        PaneState { 
-                            id: Uuid::new_v4(), 
-                            show_modal: false, 
-                            stream: vec![],
-                            content: PaneContent::Starter,
-                            settings: PaneSettings::default(),
-                        })                      
+                        PaneState::new(Uuid::new_v4(), vec![], PaneSettings::default()))
                     ),
                     b: Box::new(Configuration::Pane(
-                        PaneState { 
-                            id: Uuid::new_v4(), 
-                            show_modal: false, 
-                            stream: vec![],
-                            content: PaneContent::Starter,
-                            settings: PaneSettings::default(),
-                        })
+                        PaneState::new(Uuid::new_v4(), vec![], PaneSettings::default()))
                     ),
                 }),
             }),
             b: Box::new(Configuration::Pane(
-                PaneState { 
-                    id: Uuid::new_v4(), 
-                    show_modal: false, 
-                    stream: vec![],
-                    content: PaneContent::Starter,
-                    settings: PaneSettings::default(),
-                })
+                PaneState::new(Uuid::new_v4(), vec![], PaneSettings::default()))
             ),
         };
         
@@ -104,6 +77,8 @@ impl Dashboard {
             layout_lock: false,
             pane_streams: HashMap::new(),
             notification: None,
+            stream_states: HashMap::new(),
+            feed_monitor: FeedMonitor::default(),
         }
     }
 
@@ -114,6 +89,8 @@ impl Dashboard {
             layout_lock: false,
             pane_streams: HashMap::new(),
             notification: None,
+            stream_states: HashMap::new(),
+            feed_monitor: FeedMonitor::default(),
         }
     }
 
@@ -150,6 +127,10 @@ impl Dashboard {
                         }
                     },
                     pane::Message::ClosePane(pane) => {
+                        if let Some(pane_state) = self.panes.get_mut(pane) {
+                            pane_state.abort_backfill();
+                        }
+
                         if let Some((_, sibling)) = self.panes.close(pane) {
                             self.focus = Some(sibling);
                         }
@@ -165,6 +146,109 @@ impl Dashboard {
                             settings.selected_ticker = Some(ticker);
                         }
                     },
+                    pane::Message::TickerSearchChanged(query, pane_id) => {
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.ticker_search = query.clone();
+                            }
+                        }
+                    },
+                    pane::Message::ToggleMarketStats(pane_id) => {
+                        if let Ok(pane_settings) = self.get_pane_settings_mut(pane_id) {
+                            pane_settings.show_market_stats = !pane_settings.show_market_stats;
+                        }
+                    },
+                    pane::Message::DepthResolutionSelected(resolution, pane_id) => {
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.settings.depth_resolution = Some(resolution);
+
+                                // the charts carry on with the same data, only the depth stream gets swapped
+                                for stream in pane_state.stream.iter_mut() {
+                                    if let StreamType::DepthAndTrades { exchange, ticker, .. } = *stream {
+                                        *stream = StreamType::DepthAndTrades {
+                                            exchange,
+                                            ticker,
+                                            resolution: pane_state.settings.depth_resolution(exchange),
+                                        };
+                                    }
+                                }
+                            }
+                        }
+
+                        self.pane_streams = self.get_all_diff_streams();
+                    },
+                    pane::Message::AggregationSelected(aggregation, pane_id) => {
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.settings.aggregation = aggregation;
+
+                                if let PaneContent::Heatmap(chart) = &mut pane_state.content {
+                                    chart.set_aggregation(aggregation);
+                                }
+                            }
+                        }
+                    },
+                    pane::Message::ColorScaleSelected(color_scale, pane_id) => {
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.settings.color_scale = color_scale;
+
+                                if let PaneContent::Heatmap(chart) = &mut pane_state.content {
+                                    chart.set_color_scale(color_scale);
+                                }
+                            }
+                        }
+                    },
+                    pane::Message::ColorMapSelected(color_map, pane_id) => {
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.settings.color_map = color_map;
+
+                                if let PaneContent::Heatmap(chart) = &mut pane_state.content {
+                                    chart.set_color_map(color_map);
+                                }
+                            }
+                        }
+                    },
+                    pane::Message::ToggleTickerSearch(pane_id) => {
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.show_ticker_search = !pane_state.show_ticker_search;
+                                pane_state.ticker_search.clear();
+                            }
+                        }
+                    },
+                    pane::Message::TickerSwitched(ticker, pane_id) => {
+                        let mut content_change = None;
+
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.show_ticker_search = false;
+                                pane_state.ticker_search.clear();
+                                pane_state.settings.selected_ticker = Some(ticker);
+
+                                let basis = pane_state.settings.basis();
+
+                                if let (Some(label), Some(exchange)) = (pane_state.content.label(), pane_state.stream_exchange()) {
+                                    let resolution = pane_state.settings.depth_resolution(exchange);
+
+                                    content_change = Some((label, pane::content_streams(label, exchange, ticker, basis, resolution)));
+                                }
+                            }
+                        }
+
+                        // re-initialize the pane's content with the new ticker, same as picking it from the starter
+                        if let Some((label, pane_stream)) = content_change {
+                            let task = self.update(
+                                Message::Pane(pane::Message::PaneContentSelected(label.to_string(), pane_id, pane_stream))
+                            );
+
+                            self.pane_streams = self.get_all_diff_streams();
+
+                            return task;
+                        }
+                    },
                     pane::Message::ExchangeSelected(exchange, pane_id) => {
                         if let Ok(settings) = self.get_pane_settings_mut(pane_id) {
                             settings.selected_exchange = Some(exchange);
@@ -187,7 +271,7 @@ impl Dashboard {
                     },
                     pane::Message::ChartUserUpdate(message, pane_id) => {
                         match self.update_chart_state(pane_id, message) {
-                            Ok(_) => return Task::none(),
+                            Ok(_) => return self.fetch_older_klines_task(pane_id),
                             Err(err) => {      
                                 return Task::perform(
                                     async { err },
@@ -213,24 +297,31 @@ impl Dashboard {
                     },
                     pane::Message::PaneContentSelected(content, pane_id, pane_stream) => {        
                         let mut tasks = vec![];
+
+                        let settings = self.get_pane_settings_mut(pane_id)
+                            .map_or(PaneSettings::default(), |settings| *settings);
+                        let basis = settings.basis();
                             
                         let pane_content = match content.as_str() {
                             "Heatmap chart" => PaneContent::Heatmap(
-                                HeatmapChart::new(1.0)
+                                pane::new_heatmap(Price::from_f32(1.0), &settings)
                             ),
                             "Footprint chart" => {
-                                PaneContent::Footprint(
-                                    FootprintChart::new(1, 1.0, vec![], vec![])
-                                )
+                                let mut chart = FootprintChart::new(basis, Price::from_f32(1.0), vec![], vec![]);
+                                chart.set_volume_profile(settings.volume_profile);
+
+                                PaneContent::Footprint(chart)
                             },
                             "Candlestick chart" => {
-                                PaneContent::Candlestick(
-                                    CandlestickChart::new(vec![], 1)
-                                )
+                                let mut chart = CandlestickChart::new(vec![], basis);
+                                chart.set_volume_profile(settings.volume_profile);
+
+                                PaneContent::Candlestick(chart)
                             },
                             "Time&Sales" => PaneContent::TimeAndSales(
                                 TimeAndSales::new()
                             ),
+                            "Feed monitor" => PaneContent::FeedMonitor,
                             _ => return Task::none(),
                         };
         
@@ -250,7 +341,10 @@ impl Dashboard {
                         // prepare unique streams for websocket
                         for stream in pane_stream.iter() {
                             match stream {
-                                StreamType::Kline { exchange, ticker, .. } | StreamType::DepthAndTrades { exchange, ticker } => {
+                                StreamType::Kline { exchange, ticker, .. }
+                                | StreamType::DepthAndTrades { exchange, ticker, .. }
+                                | StreamType::MarketStats { exchange, ticker }
+                                | StreamType::Liquidations { exchange, ticker } => {
                                     self.pane_streams
                                         .entry(*exchange)
                                         .or_default()
@@ -271,23 +365,61 @@ impl Dashboard {
                                     StreamType::Kline { exchange, ticker, .. } => {
                                         if ["Candlestick chart", "Footprint chart"].contains(&content.as_str()) {
                                             tasks.push(create_fetch_klines_task(*stream, pane_id));
-                                            
-                                            if content == "Footprint chart" {
-                                                tasks.push(create_fetch_ticksize_task(exchange, ticker, pane_id));
-                                            }
+                                            tasks.push(create_fetch_ticksize_task(exchange, ticker, pane_id));
                                         }
                                     },
-                                    StreamType::DepthAndTrades { exchange, ticker } => {
+                                    StreamType::DepthAndTrades { exchange, ticker, .. } => {
                                         tasks.push(create_fetch_ticksize_task(exchange, ticker, pane_id));
                                     },
                                     _ => {}
                                 }
                             }
                         }
+
+                        // bars built from trades have no klines to wait for before backfilling
+                        if content == "Footprint chart" && basis.timeframe().is_none() {
+                            tasks.push(self.backfill_trades_task(pane_id));
+                        }
                         
                         return Task::batch(tasks)
                     },
-                    pane::Message::TimeframeSelected(timeframe, pane_id) => {    
+                    pane::Message::BasisSelected(basis, pane_id) => {
+                        let mut content_change = None;
+
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                let was_time_based = pane_state.settings.basis().timeframe().is_some();
+
+                                pane_state.settings.selected_basis = Some(basis);
+                                if let Some(timeframe) = basis.timeframe() {
+                                    pane_state.settings.selected_timeframe = Some(timeframe);
+                                }
+
+                                // the pane's streams change along with how its bars are built, so re-initialize it
+                                if !was_time_based || basis.timeframe().is_none() {
+                                    if let (Some(label), Some(exchange), Some(ticker)) = (pane_state.content.label(), pane_state.stream_exchange(), pane_state.stream_ticker()) {
+                                        let resolution = pane_state.settings.depth_resolution(exchange);
+
+                                        content_change = Some((label, pane::content_streams(label, exchange, ticker, basis, resolution)));
+                                    }
+                                }
+                            }
+                        }
+
+                        if let Some((label, pane_stream)) = content_change {
+                            let task = self.update(
+                                Message::Pane(pane::Message::PaneContentSelected(label.to_string(), pane_id, pane_stream))
+                            );
+
+                            self.pane_streams = self.get_all_diff_streams();
+
+                            return task;
+                        }
+
+                        let Some(timeframe) = basis.timeframe() else {
+                            return Task::none();
+                        };
+
                         let mut tasks = vec![];
                 
                         match self.set_pane_timeframe(pane_id, timeframe) {
@@ -295,26 +427,12 @@ impl Dashboard {
                                 if let StreamType::Kline { exchange, ticker, timeframe } = stream_type {
                                     let stream = *stream_type;
                     
-                                    match exchange {
-                                        Exchange::BinanceFutures => {
-                                            tasks.push(
-                                                Task::perform(
-                                                    binance::market_data::fetch_klines(*ticker, *timeframe)
-                                                        .map_err(|err| format!("{err}")),
-                                                    move |klines| Message::FetchEvent(klines, stream, pane_id)
-                                                )
-                                            );
-                                        },
-                                        Exchange::BybitLinear => {                                    
-                                            tasks.push(
-                                                Task::perform(
-                                                    bybit::market_data::fetch_klines(*ticker, *timeframe)
-                                                        .map_err(|err| format!("{err}")),
-                                                    move |klines| Message::FetchEvent(klines, stream, pane_id)
-                                                )
-                                            );
-                                        },
-                                    }
+                                    tasks.push(
+                                        Task::perform(
+                                            data_providers::provider(*exchange).fetch_klines(*ticker, *timeframe, None),
+                                            move |klines| Message::FetchEvent(klines, stream, pane_id)
+                                        )
+                                    );
         
                                     tasks.push(
                                         Task::perform(
@@ -348,7 +466,31 @@ impl Dashboard {
                             }
                         }
                     },
+                    pane::Message::VolumeProfileSelected(mode, pane_id) => {
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id == pane_id {
+                                pane_state.settings.volume_profile = match &mut pane_state.content {
+                                    PaneContent::Footprint(chart) => chart.select_profile_mode(mode),
+                                    PaneContent::Candlestick(chart) => chart.select_profile_mode(mode),
+                                    _ => continue,
+                                };
+                            }
+                        }
+                    },
                     pane::Message::SetMinTickSize(pane_id, ticksize) => {        
+                        for (_, pane_state) in self.panes.iter_mut() {
+                            if pane_state.id != pane_id {
+                                continue;
+                            }
+
+                            // candlesticks only group their volume profile by it, nothing to wait for a pick
+                            if let PaneContent::Candlestick(chart) = &mut pane_state.content {
+                                chart.change_tick_size(
+                                    pane_state.settings.tick_multiply.unwrap_or(TickMultiplier(1)).multiply_with_min_tick_size(ticksize)
+                                );
+                            }
+                        }
+
                         match self.get_pane_settings_mut(pane_id) {
                             Ok(pane_settings) => {
                                 pane_settings.min_tick_size = Some(ticksize);
@@ -383,6 +525,8 @@ impl Dashboard {
                     Ok(klines) => {
                         if let StreamType::Kline { .. } = pane_stream {
                             self.insert_klines_vec(&pane_stream, &klines, pane_id);
+
+                            return self.backfill_trades_task(pane_id);
                         } else {
                             log::error!("Invalid stream type for klines: {pane_stream:?}");
                         }
@@ -395,12 +539,52 @@ impl Dashboard {
                     }
                 }
             },
+            Message::OlderKlinesFetched(klines, pane_stream, pane_id) => {
+                let pane_state = self.panes.iter_mut()
+                    .map(|(_, pane_state)| pane_state)
+                    .find(|pane_state| pane_state.id == pane_id && pane_state.matches_stream(&pane_stream));
+
+                let Some(pane_state) = pane_state else {
+                    return Task::none();
+                };
+
+                match klines {
+                    Ok(klines) => match &mut pane_state.content {
+                        PaneContent::Candlestick(chart) => chart.insert_older_klines(&klines),
+                        PaneContent::Footprint(chart) => chart.insert_older_klines(&klines),
+                        _ => {}
+                    },
+                    Err(err) => {
+                        match &mut pane_state.content {
+                            PaneContent::Candlestick(chart) => chart.older_klines_failed(),
+                            PaneContent::Footprint(chart) => chart.older_klines_failed(),
+                            _ => {}
+                        }
+
+                        return Task::perform(
+                            async { err },
+                            move |err: String| Message::ErrorOccurred(Error::FetchError(err))
+                        )
+                    }
+                }
+            },
             Message::FetchDistributeKlines(stream_type, klines) => {
                 match klines {
                     Ok(klines) => {
                         if let Err(err) = self.find_and_insert_klines(&stream_type, &klines) {
                             log::error!("{err}");
                         }
+
+                        let footprint_panes: Vec<Uuid> = self.panes.iter()
+                            .filter(|(_, pane_state)| pane_state.matches_stream(&stream_type))
+                            .filter(|(_, pane_state)| matches!(pane_state.content, PaneContent::Footprint(_)))
+                            .map(|(_, pane_state)| pane_state.id)
+                            .collect();
+
+                        return Task::batch(
+                            footprint_panes.into_iter()
+                                .map(|pane_id| self.backfill_trades_task(pane_id))
+                        );
                     },
                     Err(err) => {
                         log::error!("{err}");
@@ -419,6 +603,28 @@ impl Dashboard {
                     }
                 }
             },
+            Message::TradesBackfilled(pane_id, stream_type, batch) => {
+                let pane_state = self.panes.iter_mut()
+                    .map(|(_, pane_state)| pane_state)
+                    .find(|pane_state| pane_state.id == pane_id && pane_state.matches_stream(&stream_type));
+
+                // the pane has been closed or switched to another stream since
+                let Some(PaneState { content: PaneContent::Footprint(chart), .. }) = pane_state else {
+                    return Task::none();
+                };
+
+                match batch {
+                    Ok(batch) => chart.insert_backfill_trades(&batch.trades, batch.progress),
+                    Err(err) => {
+                        chart.cancel_backfill();
+
+                        return Task::perform(
+                            async { err },
+                            move |err: String| Message::ErrorOccurred(Error::FetchError(err))
+                        )
+                    }
+                }
+            },
             Message::FetchForLayout => {
                 let mut tasks = vec![];
 
@@ -438,7 +644,7 @@ impl Dashboard {
         Task::none()
     }
 
-    pub fn view<'a>(&'a self) -> Element<'a, Message> {
+    pub fn view<'a>(&'a self, tickers: &'a HashMap<Exchange, Vec<Ticker>>) -> Element<'a, Message> {
         let focus = self.focus;
         let pane_locked = self.layout_lock;
         
@@ -449,6 +655,9 @@ impl Dashboard {
                 self.panes.len(),
                 is_focused,
                 maximized,
+                tickers,
+                &self.stream_states,
+                &self.feed_monitor,
             )
         })
         .spacing(4);
@@ -469,6 +678,14 @@ impl Dashboard {
         pane_grid.into()
     }
 
+    pub fn set_stream_state(&mut self, streams: Vec<StreamType>, state: ConnectionState) {
+        self.feed_monitor.record_state(&streams, &state);
+
+        for stream in streams {
+            self.stream_states.insert(stream, state.clone());
+        }
+    }
+
     pub fn layout_changed(&mut self) -> Task<Message> {
         self.pane_streams = self.get_all_diff_streams();
 
@@ -480,6 +697,7 @@ impl Dashboard {
 
     fn replace_new_pane(&mut self, pane: pane_grid::Pane) {
         if let Some(pane) = self.panes.get_mut(pane) {
+            pane.abort_backfill();
             *pane = PaneState::new(Uuid::new_v4(), vec![], PaneSettings::default());
         }
     }
@@ -496,6 +714,7 @@ impl Dashboard {
     fn set_pane_content(&mut self, pane_id: Uuid, content: PaneContent) -> Result<(), &str> {
         for (_, pane_state) in self.panes.iter_mut() {
             if pane_state.id == pane_id {
+                pane_state.abort_backfill();
                 pane_state.content = content;
 
                 return Ok(());
@@ -507,8 +726,15 @@ impl Dashboard {
     fn set_pane_stream(&mut self, pane_id: Uuid, stream: Vec<StreamType>) -> Result<(), &str> {
         for (_, pane_state) in self.panes.iter_mut() {
             if pane_state.id == pane_id {
+                let previous_market = (pane_state.stream_exchange(), pane_state.stream_ticker());
+
                 pane_state.stream = stream;
 
+                if previous_market != (pane_state.stream_exchange(), pane_state.stream_ticker()) {
+                    pane_state.market_stats.clear();
+                    pane_state.abort_backfill();
+                }
+
                 return Ok(());
             }
         }
@@ -536,6 +762,13 @@ impl Dashboard {
                             
                             return Ok(());
                         },
+                        PaneContent::Candlestick(ref mut chart) => {
+                            chart.change_tick_size(
+                                new_tick_multiply.multiply_with_min_tick_size(min_tick_size)
+                            );
+
+                            return Ok(());
+                        },
                         _ => {
                             return Err(Error::UnknownError("No chart found to change ticksize".to_string()));
                         }
@@ -601,7 +834,7 @@ impl Dashboard {
         Err(Error::UnknownError("No pane found".to_string()))
     }
 
-    pub fn find_and_insert_ticksizes(&mut self, stream_type: &StreamType, tick_sizes: f32) -> Result<(), &str> {
+    pub fn find_and_insert_ticksizes(&mut self, stream_type: &StreamType, tick_sizes: Price) -> Result<(), &str> {
         let mut found_match = false;
 
         for (_, pane_state) in self.panes.iter_mut() {
@@ -617,6 +850,15 @@ impl Dashboard {
 
                         found_match = true;
                     },
+                    PaneContent::Candlestick(chart) => {
+                        pane_state.settings.min_tick_size = Some(tick_sizes);
+
+                        chart.change_tick_size(
+                            pane_state.settings.tick_multiply.unwrap_or(TickMultiplier(1)).multiply_with_min_tick_size(tick_sizes)
+                        );
+
+                        found_match = true;
+                    },
                     _ => {}
                 }
             }
@@ -636,20 +878,16 @@ impl Dashboard {
             if pane_state.matches_stream(stream_type) {
                 match stream_type {
                     StreamType::Kline { timeframe, .. } => {
-                        let timeframe_u16 = timeframe.to_minutes();
+                        let basis = ChartBasis::Time(*timeframe);
 
                         match &mut pane_state.content {
                             PaneContent::Candlestick(chart) => {
-                                *chart = CandlestickChart::new(klines.to_vec(), timeframe_u16);
+                                chart.reset_klines(basis, klines.to_vec());
 
                                 found_match = true;
                             },
                             PaneContent::Footprint(chart) => {
-                                let raw_trades = chart.get_raw_trades();
-
-                                let tick_size = chart.get_tick_size();
-
-                                *chart = FootprintChart::new(timeframe_u16, tick_size, klines.to_vec(), raw_trades);
+                                chart.reset_klines(basis, klines.to_vec());
 
                                 found_match = true;
                             },
@@ -673,18 +911,14 @@ impl Dashboard {
             if pane_state.id == pane_id {
                 match stream_type {
                     StreamType::Kline { timeframe, .. } => {
-                        let timeframe_u16 = timeframe.to_minutes();
+                        let basis = ChartBasis::Time(*timeframe);
 
                         match &mut pane_state.content {
                             PaneContent::Candlestick(chart) => {
-                                *chart = CandlestickChart::new(klines.to_vec(), timeframe_u16);
+                                chart.reset_klines(basis, klines.to_vec());
                             },
                             PaneContent::Footprint(chart) => {
-                                let raw_trades = chart.get_raw_trades();
-
-                                let tick_size = chart.get_tick_size();
-
-                                *chart = FootprintChart::new(timeframe_u16, tick_size, klines.to_vec(), raw_trades);
+                                chart.reset_klines(basis, klines.to_vec());
                             },
                             _ => {}
                         }
@@ -695,6 +929,40 @@ impl Dashboard {
         }
     }
 
+    /// Streams historical trades into a footprint pane for the candles it shows
+    fn backfill_trades_task(&mut self, pane_id: Uuid) -> Task<Message> {
+        for (_, pane_state) in self.panes.iter_mut() {
+            if pane_state.id != pane_id {
+                continue;
+            }
+
+            let trades_stream = pane_state.stream.iter()
+                .find(|stream| matches!(stream, StreamType::DepthAndTrades { .. }));
+
+            let Some(&stream_type @ StreamType::DepthAndTrades { exchange, ticker, .. }) = trades_stream else {
+                continue;
+            };
+
+            if let PaneContent::Footprint(chart) = &mut pane_state.content {
+                if let Some((from_time, to_time)) = chart.backfill_range() {
+                    chart.start_backfill();
+
+                    let (task, handle) = Task::run(
+                        data_providers::provider(exchange).fetch_trades(ticker, from_time, to_time),
+                        move |batch| Message::TradesBackfilled(pane_id, stream_type, batch)
+                    ).abortable();
+
+                    pane_state.abort_backfill();
+                    pane_state.backfill = Some(handle.abort_on_drop());
+
+                    return task;
+                }
+            }
+        }
+
+        Task::none()
+    }
+
     pub fn update_latest_klines(&mut self, stream_type: &StreamType, kline: &Kline) -> Result<(), &str> {
         let mut found_match = false;
     
@@ -718,18 +986,112 @@ impl Dashboard {
         }
     }
 
+    pub fn update_market_stats(&mut self, stream_type: &StreamType, stats: MarketStats) -> Result<(), &str> {
+        let mut found_match = false;
+
+        for (_, pane_state) in self.panes.iter_mut() {
+            if pane_state.matches_stream(stream_type) {
+                pane_state.market_stats.insert(stats);
+
+                found_match = true;
+            }
+        }
+
+        if found_match {
+            Ok(())
+        } else {
+            self.pane_streams = self.get_all_diff_streams();
+
+            Err("No matching pane found for the stream")
+        }
+    }
+
+    pub fn update_liquidations(&mut self, stream_type: &StreamType, liquidations: &[Liquidation]) -> Result<(), &str> {
+        let mut found_match = false;
+
+        for (_, pane_state) in self.panes.iter_mut() {
+            if pane_state.matches_stream(stream_type) {
+                match &mut pane_state.content {
+                    PaneContent::Heatmap(chart) => chart.insert_liquidations(liquidations),
+                    PaneContent::Candlestick(chart) => chart.insert_liquidations(liquidations),
+                    PaneContent::TimeAndSales(chart) => chart.insert_liquidations(liquidations),
+                    _ => {}
+                }
+                found_match = true;
+            }
+        }
+
+        if found_match {
+            Ok(())
+        } else {
+            self.pane_streams = self.get_all_diff_streams();
+
+            Err("No matching pane found for the stream")
+        }
+    }
+
+    /// Starts the charts of panes showing a replayed ticker over, from the klines played up to the replay's position
+    pub fn reset_replayed_panes(&mut self, exchange: Exchange, ticker: Ticker, klines: &HashMap<Timeframe, Vec<Kline>>) {
+        for (_, pane_state) in self.panes.iter_mut() {
+            let replayed = pane_state.stream.iter().any(|stream| matches!(
+                stream,
+                StreamType::DepthAndTrades { exchange: e, ticker: t, .. } | StreamType::Kline { exchange: e, ticker: t, .. }
+                    if *e == exchange && *t == ticker
+            ));
+            if !replayed {
+                continue;
+            }
+
+            // recordings don't have market stats, live ones would be out of place
+            pane_state.market_stats.clear();
+            pane_state.abort_backfill();
+
+            let basis = pane_state.settings.basis();
+            let basis_klines = match basis {
+                ChartBasis::Time(timeframe) => klines.get(&timeframe).cloned().unwrap_or_default(),
+                _ => vec![],
+            };
+
+            match &mut pane_state.content {
+                PaneContent::Heatmap(chart) => {
+                    let mut new_chart = pane::new_heatmap(chart.get_tick_size(), &pane_state.settings);
+                    new_chart.set_size_filter(chart.get_size_filter());
+
+                    *chart = new_chart;
+                },
+                PaneContent::Footprint(chart) => {
+                    let mut new_chart = FootprintChart::new(basis, chart.get_tick_size(), basis_klines, vec![]);
+                    // the recording is all there is, nothing to fetch from the exchange
+                    new_chart.cancel_backfill();
+                    new_chart.set_volume_profile(chart.get_volume_profile());
+
+                    *chart = new_chart;
+                },
+                PaneContent::Candlestick(chart) => {
+                    chart.reset_klines(basis, basis_klines);
+                },
+                PaneContent::TimeAndSales(chart) => {
+                    let mut new_chart = TimeAndSales::new();
+                    new_chart.set_size_filter(chart.get_size_filter());
+                    new_chart.set_filter_sync_heatmap(chart.get_filter_sync_heatmap());
+
+                    *chart = new_chart;
+                },
+                _ => {}
+            }
+        }
+    }
+
     pub fn update_depth_and_trades(&mut self, stream_type: StreamType, depth_update_t: i64, depth: Depth, trades_buffer: Vec<Trade>) -> Result<(), &str> {
         let mut found_match = false;
         
-        let depth = Rc::new(depth);
-
         let trades_buffer = trades_buffer.into_boxed_slice();
 
         for (_, pane_state) in self.panes.iter_mut() {
             if pane_state.matches_stream(&stream_type) {
                 match &mut pane_state.content {
                     PaneContent::Heatmap(chart) => {
-                        chart.insert_datapoint(&trades_buffer, depth_update_t, Rc::clone(&depth));
+                        chart.insert_datapoint(&trades_buffer, depth_update_t, &depth);
                     },
                     PaneContent::Footprint(chart) => {
                         chart.insert_datapoint(&trades_buffer, depth_update_t);
@@ -737,6 +1099,9 @@ impl Dashboard {
                     PaneContent::TimeAndSales(chart) => {
                         chart.update(&trades_buffer);
                     },
+                    PaneContent::Candlestick(chart) => {
+                        chart.insert_trades(&trades_buffer);
+                    },
                     _ => {}
                 }
 
@@ -753,6 +1118,33 @@ impl Dashboard {
         }
     }
 
+    /// Requests the previous page of klines when a chart has been panned past its earliest one
+    fn fetch_older_klines_task(&mut self, pane_id: Uuid) -> Task<Message> {
+        for (_, pane_state) in self.panes.iter_mut() {
+            if pane_state.id != pane_id {
+                continue;
+            }
+
+            let end_time = match &mut pane_state.content {
+                PaneContent::Candlestick(chart) => chart.older_klines_request(),
+                PaneContent::Footprint(chart) => chart.older_klines_request(),
+                _ => None,
+            };
+
+            let kline_stream = pane_state.stream.iter()
+                .find(|stream| matches!(stream, StreamType::Kline { .. }));
+
+            if let (Some(end_time), Some(&stream @ StreamType::Kline { exchange, ticker, timeframe })) = (end_time, kline_stream) {
+                return Task::perform(
+                    data_providers::provider(exchange).fetch_klines(ticker, timeframe, Some(end_time)),
+                    move |klines| Message::OlderKlinesFetched(klines, stream, pane_id)
+                );
+            }
+        }
+
+        Task::none()
+    }
+
     fn update_chart_state(&mut self, pane_id: Uuid, chart_message: ChartMessage) -> Result<(), Error> {
         for (_, pane_state) in self.panes.iter_mut() {
             if pane_state.id == pane_id {
@@ -796,13 +1188,30 @@ impl Dashboard {
                         let ticker_map = exchange_map.entry(ticker).or_insert(HashSet::new());
                         ticker_map.insert(StreamType::Kline { exchange, ticker, timeframe });
                     },
-                    StreamType::DepthAndTrades { exchange, ticker } => {
+                    StreamType::DepthAndTrades { exchange, ticker, resolution } => {
                         let exchange = *exchange;
                         let ticker = *ticker;
+                        let resolution = *resolution;
 
                         let exchange_map = pane_streams.entry(exchange).or_insert(HashMap::new());
                         let ticker_map = exchange_map.entry(ticker).or_insert(HashSet::new());
-                        ticker_map.insert(StreamType::DepthAndTrades { exchange, ticker });
+                        ticker_map.insert(StreamType::DepthAndTrades { exchange, ticker, resolution });
+                    },
+                    StreamType::MarketStats { exchange, ticker } => {
+                        let exchange = *exchange;
+                        let ticker = *ticker;
+
+                        let exchange_map = pane_streams.entry(exchange).or_insert(HashMap::new());
+                        let ticker_map = exchange_map.entry(ticker).or_insert(HashSet::new());
+                        ticker_map.insert(StreamType::MarketStats { exchange, ticker });
+                    },
+                    StreamType::Liquidations { exchange, ticker } => {
+                        let exchange = *exchange;
+                        let ticker = *ticker;
+
+                        let exchange_map = pane_streams.entry(exchange).or_insert(HashMap::new());
+                        let ticker_map = exchange_map.entry(ticker).or_insert(HashSet::new());
+                        ticker_map.insert(StreamType::Liquidations { exchange, ticker });
                     },
                     _ => {}
                 }
@@ -819,20 +1228,10 @@ fn create_fetch_klines_task(
     pane_id: Uuid,
 ) -> Task<Message> {
     match stream {
-        StreamType::Kline { exchange, ticker, timeframe } => {
-            match exchange {
-                Exchange::BinanceFutures => Task::perform(
-                    binance::market_data::fetch_klines(ticker, timeframe)
-                        .map_err(|err| format!("{err}")),
-                    move |klines| Message::FetchEvent(klines, stream, pane_id),
-                ),
-                Exchange::BybitLinear => Task::perform(
-                    bybit::market_data::fetch_klines(ticker, timeframe)
-                        .map_err(|err| format!("{err}")),
-                    move |klines| Message::FetchEvent(klines, stream, pane_id),
-                ),
-            }
-        },
+        StreamType::Kline { exchange, ticker, timeframe } => Task::perform(
+            data_providers::provider(exchange).fetch_klines(ticker, timeframe, None),
+            move |klines| Message::FetchEvent(klines, stream, pane_id),
+        ),
         _ => Task::none(),
     }
 }
@@ -842,22 +1241,13 @@ fn create_fetch_ticksize_task(
     ticker: &Ticker,
     pane_id: Uuid,
 ) -> Task<Message> {
-    match exchange {
-        Exchange::BinanceFutures => Task::perform(
-            binance::market_data::fetch_ticksize(*ticker),
-            move |result| match result {
-                Ok(ticksize) => Message::Pane(pane::Message::SetMinTickSize(pane_id, ticksize)),
-                Err(err) => Message::ErrorOccurred(Error::FetchError(err.to_string())),
-            },
-        ),
-        Exchange::BybitLinear => Task::perform(
-            bybit::market_data::fetch_ticksize(*ticker),
-            move |result| match result {
-                Ok(ticksize) => Message::Pane(pane::Message::SetMinTickSize(pane_id, ticksize)),
-                Err(err) => Message::ErrorOccurred(Error::FetchError(err.to_string())),
-            },
-        ),
-    }
+    Task::perform(
+        data_providers::provider(*exchange).fetch_ticksize(*ticker),
+        move |result| match result {
+            Ok(ticksize) => Message::Pane(pane::Message::SetMinTickSize(pane_id, ticksize)),
+            Err(err) => Message::ErrorOccurred(Error::FetchError(err)),
+        },
+    )
 }
 
 fn klines_fetch_all_task(stream_types: &HashMap<Exchange, HashMap<Ticker, HashSet<StreamType>>>) -> Vec<Task<Message>> {
@@ -882,28 +1272,13 @@ fn klines_fetch_all_task(stream_types: &HashMap<Exchange, HashMap<Ticker, HashSe
             let timeframe = timeframe;
             let exchange = *exchange;
 
-            match exchange {
-                Exchange::BinanceFutures => {
-                    let fetch_klines = Task::perform(
-                        binance::market_data::fetch_klines(ticker, timeframe)
-                            .map_err(|err| format!("{err}")),
-                        move |klines| Message::FetchDistributeKlines(
-                            StreamType::Kline { exchange, ticker, timeframe }, klines
-                        )
-                    );
-                    tasks.push(fetch_klines);
-                },
-                Exchange::BybitLinear => {
-                    let fetch_klines = Task::perform(
-                        bybit::market_data::fetch_klines(ticker, timeframe)
-                            .map_err(|err| format!("{err}")),
-                        move |klines| Message::FetchDistributeKlines(
-                            StreamType::Kline { exchange, ticker, timeframe }, klines
-                        )
-                    );
-                    tasks.push(fetch_klines);
-                }
-            }
+            let fetch_klines = Task::perform(
+                data_providers::provider(exchange).fetch_klines(ticker, timeframe, None),
+                move |klines| Message::FetchDistributeKlines(
+                    StreamType::Kline { exchange, ticker, timeframe }, klines
+                )
+            );
+            tasks.push(fetch_klines);
         }
     }
 
@@ -919,40 +1294,21 @@ fn ticksize_fetch_all_task(stream_types: &HashMap<Exchange, HashMap<Ticker, Hash
         for stream_types in stream.values() {
             for stream_type in stream_types {
                 match stream_type {
-                    StreamType::DepthAndTrades { ticker, .. } => {
-                        ticksize_fetches.push(*ticker);
+                    // candlesticks only have klines, their volume profile is grouped by tick size too
+                    StreamType::DepthAndTrades { ticker, .. } | StreamType::Kline { ticker, .. } => {
+                        ticksize_fetches.push((*stream_type, *ticker));
                     },
                     _ => {}
                 }
             }
         }
 
-        for ticker in ticksize_fetches {
-            let ticker = ticker;
-            let exchange = *exchange;
-
-            match exchange {
-                Exchange::BinanceFutures => {
-                    let fetch_ticksize = Task::perform(
-                        binance::market_data::fetch_ticksize(ticker)
-                            .map_err(|err| format!("{err}")),
-                        move |ticksize| Message::FetchDistributeTicks(
-                            StreamType::DepthAndTrades { exchange, ticker }, ticksize
-                        )
-                    );
-                    tasks.push(fetch_ticksize);
-                },
-                Exchange::BybitLinear => {
-                    let fetch_ticksize = Task::perform(
-                        bybit::market_data::fetch_ticksize(ticker)
-                            .map_err(|err| format!("{err}")),
-                        move |ticksize| Message::FetchDistributeTicks(
-                            StreamType::DepthAndTrades { exchange, ticker }, ticksize
-                        )
-                    );
-                    tasks.push(fetch_ticksize);
-                }
-            }
+        for (stream_type, ticker) in ticksize_fetches {
+            let fetch_ticksize = Task::perform(
+                data_providers::provider(*exchange).fetch_ticksize(ticker),
+                move |ticksize| Message::FetchDistributeTicks(stream_type, ticksize)
+            );
+            tasks.push(fetch_ticksize);
         }
     }
 
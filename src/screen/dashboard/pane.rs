@@ -1,14 +1,17 @@
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
-use iced::{alignment, widget::{button, container, pane_grid, pick_list, row, scrollable, text, tooltip, Column, Container, Row, Slider, Text}, Alignment, Element, Length, Renderer, Theme};
+use iced::{alignment, task, widget::{button, container, pane_grid, pick_list, row, scrollable, text, text_input, tooltip, Column, Container, Row, Slider, Text}, Alignment, Element, Length, Renderer, Theme};
 use serde::{Deserialize, Serialize};
 pub use uuid::Uuid;
 
 use crate::{
     charts::{
-        self, candlestick::CandlestickChart, footprint::FootprintChart, heatmap::HeatmapChart, timeandsales::TimeAndSales
+        self, candlestick::CandlestickChart, feed_monitor::FeedMonitor, footprint::FootprintChart,
+        heatmap::{AggregationInterval, ColorMap, ColorScale, HeatmapChart}, timeandsales::TimeAndSales,
+        volume_profile::{ProfileMode, ProfileRange},
+        indicators::{format_large_number, MarketStatsHistory},
     }, data_providers::{
-        Exchange, TickMultiplier, Ticker, Timeframe
+        ChartBasis, ConnectionState, DepthResolution, Exchange, MarketStats, MarketType, Price, TickMultiplier, Ticker, Timeframe
     }, modal, style::{self, Icon, ICON_FONT}, StreamType
 };
 
@@ -22,8 +25,11 @@ pub enum Message {
     MaximizePane(pane_grid::Pane),
     Restore,
     TicksizeSelected(TickMultiplier, Uuid),
-    TimeframeSelected(Timeframe, Uuid),
+    BasisSelected(ChartBasis, Uuid),
     TickerSelected(Ticker, Uuid),
+    TickerSwitched(Ticker, Uuid),
+    TickerSearchChanged(String, Uuid),
+    ToggleTickerSearch(Uuid),
     ExchangeSelected(Exchange, Uuid),
     ShowModal(pane_grid::Pane),
     HideModal(Uuid),
@@ -31,16 +37,28 @@ pub enum Message {
     ReplacePane(pane_grid::Pane),
     ChartUserUpdate(charts::Message, Uuid),
     SliderChanged(Uuid, f32),
-    SetMinTickSize(Uuid, f32),
+    SetMinTickSize(Uuid, Price),
+    ToggleMarketStats(Uuid),
+    DepthResolutionSelected(DepthResolution, Uuid),
+    AggregationSelected(AggregationInterval, Uuid),
+    ColorScaleSelected(ColorScale, Uuid),
+    ColorMapSelected(ColorMap, Uuid),
+    VolumeProfileSelected(ProfileMode, Uuid),
 }
 
 #[derive(Debug)]
 pub struct PaneState {
     pub id: Uuid,
     pub show_modal: bool,
+    pub show_ticker_search: bool,
+    pub ticker_search: String,
     pub stream: Vec<StreamType>,
     pub content: PaneContent,
     pub settings: PaneSettings,
+    /// Samples of the pane's market stats stream, for the title bar and the chart's sub-pane
+    pub market_stats: MarketStatsHistory,
+    /// Historical trades still being fetched for the pane's footprint, aborted along with the pane
+    pub backfill: Option<task::Handle>,
 }
 
 impl PaneState {
@@ -48,9 +66,13 @@ impl PaneState {
         Self {
             id,
             show_modal: false,
+            show_ticker_search: false,
+            ticker_search: String::new(),
             stream,
             content: PaneContent::Starter,
             settings,
+            market_stats: MarketStatsHistory::default(),
+            backfill: None,
         }
     }
 
@@ -58,9 +80,20 @@ impl PaneState {
         Self {
             id: Uuid::new_v4(),
             show_modal: false,
+            show_ticker_search: false,
+            ticker_search: String::new(),
             stream,
             content,
             settings,
+            market_stats: MarketStatsHistory::default(),
+            backfill: None,
+        }
+    }
+
+    /// Stops fetching historical trades for the pane's chart, e.g. once it's closed or shows another market
+    pub fn abort_backfill(&mut self) {
+        if let Some(handle) = self.backfill.take() {
+            handle.abort();
         }
     }
 
@@ -70,6 +103,9 @@ impl PaneState {
         panes: usize,
         is_focused: bool,
         maximized: bool,
+        tickers: &'a HashMap<Exchange, Vec<Ticker>>,
+        stream_states: &HashMap<StreamType, ConnectionState>,
+        feed_monitor: &'a FeedMonitor,
     ) -> iced::widget::pane_grid::Content<'a, Message, Theme, Renderer> {
         let stream_info = self.stream.iter().find_map(|stream: &StreamType| {
             match stream {
@@ -83,7 +119,7 @@ impl PaneState {
         }).or_else(|| {
             self.stream.iter().find_map(|stream: &StreamType| {
                 match stream {
-                    StreamType::DepthAndTrades { exchange, ticker } => {
+                    StreamType::DepthAndTrades { exchange, ticker, .. } => {
                         Some(
                             Some((exchange, ticker.to_string()))
                         )
@@ -100,17 +136,75 @@ impl PaneState {
                 .spacing(3)
                 .push(
                     match exchange {
-                        Exchange::BinanceFutures => text(char::from(Icon::BinanceLogo).to_string()).font(ICON_FONT),
-                        Exchange::BybitLinear => text(char::from(Icon::BybitLogo).to_string()).font(ICON_FONT),
+                        Exchange::BinanceFutures | Exchange::BinanceSpot => text(char::from(Icon::BinanceLogo).to_string()).font(ICON_FONT),
+                        Exchange::BybitLinear | Exchange::BybitInverse | Exchange::BybitSpot => text(char::from(Icon::BybitLogo).to_string()).font(ICON_FONT),
+                        Exchange::OkxSwap => text("OKX"),
+                        Exchange::AggregatedLinear => text(
+                            format!("{}{}", char::from(Icon::BinanceLogo), char::from(Icon::BybitLogo))
+                        ).font(ICON_FONT),
                     }
                 )
                 .push(Text::new(info));
+
+            // perps are the default, only tag the other markets
+            if exchange.market_type() != MarketType::LinearPerps {
+                stream_info_element = stream_info_element.push(
+                    Text::new(exchange.market_type().to_string()).size(12)
+                );
+            }
+        }
+
+        if let PaneContent::FeedMonitor = self.content {
+            stream_info_element = stream_info_element.push(Text::new("Feed monitor"));
+        }
+
+        if let Some(stats) = self.market_stats.latest() {
+            stream_info_element = stream_info_element.push(
+                Text::new(market_stats_info(stats)).size(12)
+            );
+        }
+
+        if let PaneContent::Footprint(chart) = &self.content {
+            if let Some(progress) = chart.backfill_progress() {
+                stream_info_element = stream_info_element.push(
+                    Text::new(format!("Fetching trades {:.0}%", progress * 100.0)).size(12)
+                );
+            }
+        }
+
+        // show the least healthy of the pane's streams, nothing if they are all live
+        let stale_state = self.stream.iter()
+            .filter_map(|stream| stream_states.get(stream))
+            .filter(|state| **state != ConnectionState::Live)
+            .max_by_key(|state| match state {
+                ConnectionState::Live => 0,
+                ConnectionState::Resyncing => 1,
+                ConnectionState::Connecting => 2,
+                ConnectionState::Down(_) => 3,
+            });
+
+        if let Some(state) = stale_state {
+            stream_info_element = stream_info_element.push(
+                Text::new(match state {
+                    ConnectionState::Connecting => "Connecting...".to_string(),
+                    ConnectionState::Resyncing => "Resyncing...".to_string(),
+                    ConnectionState::Down(reason) => format!("Stale: {reason}"),
+                    ConnectionState::Live => String::new(),
+                })
+                .size(12)
+                .style(style::stale_text)
+            );
         }
         
+        let exchange_tickers: &[Ticker] = self.stream_exchange()
+            .or(self.settings.selected_exchange)
+            .and_then(|exchange| tickers.get(&exchange))
+            .map_or(&[], |tickers| tickers.as_slice());
+
         let mut content: pane_grid::Content<'_, Message, _, Renderer> = 
             pane_grid::Content::new({
-                match self.content {
-                    PaneContent::Starter => view_starter(&self.id, &self.settings),
+                let pane_content = match self.content {
+                    PaneContent::Starter => view_starter(&self.id, &self.settings, exchange_tickers, &self.ticker_search),
 
                     PaneContent::Heatmap(ref chart) => view_chart(self, chart),
 
@@ -119,6 +213,40 @@ impl PaneState {
                     PaneContent::Candlestick(ref chart) => view_chart(self, chart),
 
                     PaneContent::TimeAndSales(ref chart) => view_chart(self, chart),
+
+                    PaneContent::FeedMonitor => feed_monitor.view(),
+                };
+
+                if self.show_ticker_search {
+                    let pane_id = self.id;
+
+                    let ticker_search = container(
+                        Column::new()
+                            .spacing(10)
+                            .align_x(Alignment::Center)
+                            .push(Text::new("Switch ticker").size(16))
+                            .push(
+                                ticker_search_list(
+                                    exchange_tickers,
+                                    &self.ticker_search,
+                                    self.settings.selected_ticker,
+                                    pane_id,
+                                    move |ticker| Message::TickerSwitched(ticker, pane_id),
+                                )
+                            )
+                            .push(
+                                button("Close")
+                                    .on_press(Message::ToggleTickerSearch(pane_id))
+                            )
+                    )
+                    .width(Length::Shrink)
+                    .padding(20)
+                    .max_width(300)
+                    .style(style::chart_modal);
+
+                    modal(pane_content, ticker_search, Message::ToggleTickerSearch(pane_id))
+                } else {
+                    pane_content
                 }
             })
             .style(
@@ -154,14 +282,36 @@ impl PaneState {
     pub fn matches_stream(&self, stream_type: &StreamType) -> bool {
         self.stream.iter().any(|stream| stream == stream_type)
     }
+
+    /// Ticker of the streams the pane is subscribed to, if it has any
+    pub fn stream_ticker(&self) -> Option<Ticker> {
+        self.stream.iter().find_map(|stream| match stream {
+            StreamType::Kline { ticker, .. }
+            | StreamType::DepthAndTrades { ticker, .. }
+            | StreamType::MarketStats { ticker, .. }
+            | StreamType::Liquidations { ticker, .. } => Some(*ticker),
+            StreamType::None => None,
+        })
+    }
+
+    /// Exchange of the streams the pane is subscribed to, if it has any
+    pub fn stream_exchange(&self) -> Option<Exchange> {
+        self.stream.iter().find_map(|stream| match stream {
+            StreamType::Kline { exchange, .. }
+            | StreamType::DepthAndTrades { exchange, .. }
+            | StreamType::MarketStats { exchange, .. }
+            | StreamType::Liquidations { exchange, .. } => Some(*exchange),
+            StreamType::None => None,
+        })
+    }
 }
 
 trait ChartView {
-    fn view(&self, id: &PaneState) -> Element<Message>;
+    fn view<'a>(&'a self, pane: &'a PaneState) -> Element<'a, Message>;
 }
 
 impl ChartView for HeatmapChart {
-    fn view(&self, pane: &PaneState) -> Element<Message> {
+    fn view<'a>(&'a self, pane: &'a PaneState) -> Element<'a, Message> {
         let pane_id = pane.id;
 
         let underlay = self.view().map(move |message| Message::ChartUserUpdate(message, pane_id));
@@ -189,6 +339,52 @@ impl ChartView for HeatmapChart {
                                 Text::new(format!("${size_filter}")).size(16)
                             )
                     )
+                    .push(
+                        Row::new()
+                            .spacing(10)
+                            .push(
+                                Column::new()
+                                    .align_x(Alignment::Center)
+                                    .push(Text::new("Aggregation"))
+                                    .push(
+                                        pick_list(
+                                            &AggregationInterval::ALL[..],
+                                            Some(self.get_aggregation()),
+                                            move |aggregation| Message::AggregationSelected(aggregation, pane_id),
+                                        )
+                                        .style(style::picklist_primary)
+                                        .menu_style(style::picklist_menu_primary)
+                                    )
+                            )
+                            .push(
+                                Column::new()
+                                    .align_x(Alignment::Center)
+                                    .push(Text::new("Color scale"))
+                                    .push(
+                                        pick_list(
+                                            &ColorScale::ALL[..],
+                                            Some(self.get_color_scale()),
+                                            move |color_scale| Message::ColorScaleSelected(color_scale, pane_id),
+                                        )
+                                        .style(style::picklist_primary)
+                                        .menu_style(style::picklist_menu_primary)
+                                    )
+                            )
+                            .push(
+                                Column::new()
+                                    .align_x(Alignment::Center)
+                                    .push(Text::new("Colors"))
+                                    .push(
+                                        pick_list(
+                                            &ColorMap::ALL[..],
+                                            Some(self.get_color_map()),
+                                            move |color_map| Message::ColorMapSelected(color_map, pane_id),
+                                        )
+                                        .style(style::picklist_primary)
+                                        .menu_style(style::picklist_menu_primary)
+                                    )
+                            )
+                    )
                     .push( 
                         Row::new()
                             .spacing(10)
@@ -210,14 +406,16 @@ impl ChartView for HeatmapChart {
     }
 }
 impl ChartView for FootprintChart {
-    fn view(&self, pane: &PaneState) -> Element<Message> {
+    fn view<'a>(&'a self, pane: &'a PaneState) -> Element<'a, Message> {
         let pane_id = pane.id;
 
-        self.view().map(move |message| Message::ChartUserUpdate(message, pane_id))
+        let market_stats = pane.settings.show_market_stats.then_some(&pane.market_stats);
+
+        self.view(market_stats).map(move |message| Message::ChartUserUpdate(message, pane_id))
     }
 }
 impl ChartView for TimeAndSales {
-    fn view(&self, pane: &PaneState) -> Element<Message> {
+    fn view<'a>(&'a self, pane: &'a PaneState) -> Element<'a, Message> {
         let pane_id = pane.id;
 
         let underlay = self.view();
@@ -266,11 +464,33 @@ impl ChartView for TimeAndSales {
     }
 }
 impl ChartView for CandlestickChart {
-    fn view(&self, pane: &PaneState) -> Element<Message> {
+    fn view<'a>(&'a self, pane: &'a PaneState) -> Element<'a, Message> {
         let pane_id = pane.id;
 
-        self.view().map(move |message| Message::ChartUserUpdate(message, pane_id))
+        let market_stats = pane.settings.show_market_stats.then_some(&pane.market_stats);
+
+        self.view(market_stats).map(move |message| Message::ChartUserUpdate(message, pane_id))
+    }
+}
+
+/// Mark price, funding rate with the time left until it's paid, and open interest
+fn market_stats_info(stats: &MarketStats) -> String {
+    let until_funding = (stats.next_funding_time - chrono::Utc::now().timestamp_millis()).max(0) / 1000;
+
+    let mut info = format!(
+        "Mark {}  Funding {:.4}% in {:02}:{:02}:{:02}",
+        stats.mark_price,
+        stats.funding_rate * 100.0,
+        until_funding / 3600,
+        until_funding % 3600 / 60,
+        until_funding % 60,
+    );
+
+    if let Some(open_interest) = stats.open_interest {
+        info.push_str(&format!("  OI {}", format_large_number(open_interest)));
     }
+
+    info
 }
 
 fn view_chart<'a, C: ChartView>(
@@ -296,6 +516,20 @@ fn view_controls<'a>(
 ) -> Element<'a, Message> {
     let mut row = row![].spacing(5);
 
+    if let (Some(ticker), false) = (settings.selected_ticker, matches!(pane_type, PaneContent::Starter)) {
+        let ticker_button = button(
+            text(ticker.to_string()).size(11)
+        )
+        .style(style::button_primary)
+        .padding([3, 6])
+        .on_press(Message::ToggleTickerSearch(pane_id));
+
+        row = row.push(
+            tooltip(ticker_button, "Switch ticker", tooltip::Position::FollowCursor)
+                .style(style::tooltip)
+        );
+    }
+
     let (icon, message) = if is_maximized {
         (Icon::ResizeSmall, Message::Restore)
     } else {
@@ -320,14 +554,33 @@ fn view_controls<'a>(
                 .style(style::tooltip);
     
             row = row.push(ticksize_tooltip);
+
+            if let Some(exchange) = settings.selected_exchange.filter(|exchange| DepthResolution::options(*exchange).len() > 1) {
+                let resolution_picker = pick_list(
+                    DepthResolution::options(exchange),
+                    Some(DepthResolution::resolve(settings.depth_resolution(exchange), exchange)),
+                    move |resolution| Message::DepthResolutionSelected(resolution, pane_id)
+                ).text_size(11).width(iced::Pixels(80.0));
+
+                let resolution_tooltip = tooltip(
+                    resolution_picker
+                        .style(style::picklist_primary)
+                        .menu_style(style::picklist_menu_primary),
+                        "Depth resolution",
+                        tooltip::Position::FollowCursor
+                    )
+                    .style(style::tooltip);
+
+                row = row.push(resolution_tooltip);
+            }
         },
-        PaneContent::TimeAndSales(_) => {
+        PaneContent::TimeAndSales(_) | PaneContent::FeedMonitor => {
         },
         PaneContent::Footprint(_) => {
             let timeframe_picker = pick_list(
-                &Timeframe::ALL[..],
-                settings.selected_timeframe,
-                move |timeframe| Message::TimeframeSelected(timeframe, pane_id),
+                &ChartBasis::ALL[..],
+                Some(settings.basis()),
+                move |basis| Message::BasisSelected(basis, pane_id),
             ).placeholder("Choose a timeframe...").text_size(11).width(iced::Pixels(80.0));
     
             let tf_tooltip = tooltip(
@@ -360,12 +613,12 @@ fn view_controls<'a>(
         },
         PaneContent::Candlestick(_) => {
             let timeframe_picker = pick_list(
-                &Timeframe::ALL[..],
-                settings.selected_timeframe,
-                move |timeframe| Message::TimeframeSelected(timeframe, pane_id),
+                &ChartBasis::ALL[..],
+                Some(settings.basis()),
+                move |basis| Message::BasisSelected(basis, pane_id),
             ).placeholder("Choose a timeframe...").text_size(11).width(iced::Pixels(80.0));
     
-            let tooltip = tooltip(
+            let tf_tooltip = tooltip(
                 timeframe_picker
                     .style(style::picklist_primary)
                     .menu_style(style::picklist_menu_primary),
@@ -374,12 +627,68 @@ fn view_controls<'a>(
                 )
                 .style(style::tooltip);
     
-            row = row.push(tooltip);
+            row = row.push(tf_tooltip);
+
+            // only groups the volume profile's levels, candles don't depend on it
+            if settings.volume_profile.mode != ProfileMode::Off {
+                let ticksize_picker = pick_list(
+                    [TickMultiplier(1), TickMultiplier(2), TickMultiplier(5), TickMultiplier(10), TickMultiplier(25), TickMultiplier(50), TickMultiplier(100), TickMultiplier(200)],
+                    settings.tick_multiply,
+                    move |tick_multiply| Message::TicksizeSelected(tick_multiply, pane_id)
+                ).placeholder("Ticksize multiplier...").text_size(11).width(iced::Pixels(80.0));
+
+                let ticksize_tooltip = tooltip(
+                    ticksize_picker
+                        .style(style::picklist_primary)
+                        .menu_style(style::picklist_menu_primary),
+                        "Ticksize multiplier",
+                        tooltip::Position::FollowCursor
+                    )
+                    .style(style::tooltip);
+
+                row = row.push(ticksize_tooltip);
+            }
         },
         PaneContent::Starter => {
         },
     }
 
+    if matches!(pane_type, PaneContent::Footprint(_) | PaneContent::Candlestick(_)) {
+        let profile_picker = pick_list(
+            ProfileMode::ALL,
+            Some(settings.volume_profile.mode),
+            move |mode| Message::VolumeProfileSelected(mode, pane_id)
+        ).text_size(11).width(iced::Pixels(90.0));
+
+        row = row.push(
+            tooltip(
+                profile_picker
+                    .style(style::picklist_primary)
+                    .menu_style(style::picklist_menu_primary),
+                "Volume profile",
+                tooltip::Position::FollowCursor
+            )
+            .style(style::tooltip)
+        );
+    }
+
+    let is_perpetual = settings.selected_exchange
+        .is_some_and(|exchange| exchange.market_type() != MarketType::Spot);
+
+    if is_perpetual && matches!(pane_type, PaneContent::Footprint(_) | PaneContent::Candlestick(_)) {
+        let stats_button = button(
+            text("OI").size(11)
+        )
+        .style(if settings.show_market_stats { style::button_selected } else { style::button_primary })
+        .padding([3, 6])
+        .on_press(Message::ToggleMarketStats(pane_id));
+
+        row = row.push(
+            tooltip(stats_button, "Funding and open interest", tooltip::Position::FollowCursor)
+                .style(style::tooltip)
+        );
+    }
+
     let mut buttons = vec![
         (container(text(char::from(Icon::Cog).to_string()).font(ICON_FONT).size(14)).width(25).center_x(iced::Pixels(25.0)), Message::ShowModal(pane)),
         (container(text(char::from(icon).to_string()).font(ICON_FONT).size(14)).width(25).center_x(iced::Pixels(25.0)), message),
@@ -404,6 +713,8 @@ fn view_controls<'a>(
 fn view_starter<'a>(
     pane_id: &'a Uuid,
     pane_settings: &'a PaneSettings,
+    tickers: &'a [Ticker],
+    ticker_search: &'a str,
 ) -> Element<'a, Message> {
     let content_names = ["Heatmap chart", "Footprint chart", "Candlestick chart", "Time&Sales"];
     
@@ -413,23 +724,7 @@ fn view_starter<'a>(
             .align_x(Alignment::Center), |column, &label| {
                 let mut btn = button(label).width(Length::Fill);
                 if let (Some(exchange), Some(ticker)) = (pane_settings.selected_exchange, pane_settings.selected_ticker) {
-                    let timeframe = pane_settings.selected_timeframe.unwrap_or_else(
-                        || { log::error!("No timeframe found"); Timeframe::M1 }
-                    );
-
-                    let pane_stream: Vec<StreamType> = match label {
-                        "Heatmap chart" | "Time&Sales" => vec![
-                            StreamType::DepthAndTrades { exchange, ticker }
-                        ],
-                        "Footprint chart" => vec![
-                            StreamType::DepthAndTrades { exchange, ticker }, 
-                            StreamType::Kline { exchange, ticker, timeframe }
-                        ],
-                        "Candlestick chart" => vec![
-                            StreamType::Kline { exchange, ticker, timeframe }
-                        ],
-                        _ => vec![]
-                    };
+                    let pane_stream: Vec<StreamType> = content_streams(label, exchange, ticker, pane_settings.basis(), pane_settings.depth_resolution(exchange));
                 
                     btn = btn.on_press(
                         Message::PaneContentSelected(
@@ -441,31 +736,34 @@ fn view_starter<'a>(
                 }
                 column.push(btn)
             }
+    )
+    // watches every stream of the layout, it needs no exchange or ticker of its own
+    .push(
+        button("Feed monitor")
+            .width(Length::Fill)
+            .on_press(Message::PaneContentSelected("Feed monitor".to_string(), *pane_id, vec![]))
     );
 
-    let symbol_selector = pick_list(
-        &Ticker::ALL[..],
-        pane_settings.selected_ticker,
-        move |ticker| Message::TickerSelected(ticker, *pane_id),
-    ).placeholder("ticker...").text_size(13).width(Length::Fill);
-
     let exchange_selector = pick_list(
         &Exchange::ALL[..],
         pane_settings.selected_exchange,
         move |exchange| Message::ExchangeSelected(exchange, *pane_id),
     ).placeholder("exchange...").text_size(13).width(Length::Fill);
 
-    let picklists = Row::new()
-        .spacing(6)
-        .align_y(Alignment::Center)
-        .push(exchange_selector.style(style::picklist_primary).menu_style(style::picklist_menu_primary))
-        .push(symbol_selector.style(style::picklist_primary).menu_style(style::picklist_menu_primary));
+    let symbol_selector = ticker_search_list(
+        tickers,
+        ticker_search,
+        pane_settings.selected_ticker,
+        *pane_id,
+        move |ticker| Message::TickerSelected(ticker, *pane_id),
+    );
 
     let column = Column::new()
         .padding(10)
         .spacing(10)
         .align_x(Alignment::Center)
-        .push(picklists)
+        .push(exchange_selector.style(style::picklist_primary).menu_style(style::picklist_menu_primary))
+        .push(symbol_selector)
         .push(content_selector);
         
     let container = Container::new(
@@ -483,14 +781,129 @@ fn view_starter<'a>(
     container.into()
 }
 
+/// Text input to filter the exchange's tickers, followed by a list of the matching ones
+fn ticker_search_list<'a>(
+    tickers: &'a [Ticker],
+    search_query: &'a str,
+    selected_ticker: Option<Ticker>,
+    pane_id: Uuid,
+    on_select: impl Fn(Ticker) -> Message + 'a,
+) -> Element<'a, Message> {
+    const MAX_RESULTS: usize = 50;
+
+    let search_input = text_input("Search ticker...", search_query)
+        .on_input(move |query| Message::TickerSearchChanged(query, pane_id))
+        .size(13)
+        .width(Length::Fill);
+
+    let query = search_query.trim().to_uppercase();
+
+    let matching_tickers = tickers.iter()
+        .filter(|ticker| ticker.as_str().contains(&query))
+        .take(MAX_RESULTS);
+
+    let ticker_list = matching_tickers.fold(
+        Column::new().spacing(2), |column, ticker| {
+            let is_selected = selected_ticker == Some(*ticker);
+
+            column.push(
+                button(text(ticker.to_string()).size(13))
+                    .width(Length::Fill)
+                    .style(if is_selected { style::button_selected } else { style::button_primary })
+                    .on_press(on_select(*ticker))
+            )
+        }
+    );
+
+    let list: Element<'a, Message> = if tickers.is_empty() {
+        Text::new("Select an exchange to list its tickers").size(12).into()
+    } else {
+        scrollable(ticker_list)
+            .height(Length::Fixed(160.0))
+            .into()
+    };
+
+    Column::new()
+        .spacing(6)
+        .push(search_input)
+        .push(list)
+        .into()
+}
+
+/// A heatmap bucketed and coloured the way the pane is set up
+pub fn new_heatmap(tick_size: Price, settings: &PaneSettings) -> HeatmapChart {
+    let mut chart = HeatmapChart::new(tick_size);
+
+    chart.set_aggregation(settings.aggregation);
+    chart.set_color_scale(settings.color_scale);
+    chart.set_color_map(settings.color_map);
+
+    chart
+}
+
+/// Streams a pane needs to be subscribed to for the given content,
+/// charts with bars built from trades need no klines but the trade stream instead
+pub fn content_streams(
+    content: &str,
+    exchange: Exchange,
+    ticker: Ticker,
+    basis: ChartBasis,
+    resolution: Option<DepthResolution>,
+) -> Vec<StreamType> {
+    let mut streams = match (content, basis.timeframe()) {
+        ("Heatmap chart" | "Time&Sales", _) => vec![
+            StreamType::DepthAndTrades { exchange, ticker, resolution }
+        ],
+        ("Footprint chart", Some(timeframe)) => vec![
+            StreamType::DepthAndTrades { exchange, ticker, resolution }, 
+            StreamType::Kline { exchange, ticker, timeframe }
+        ],
+        ("Candlestick chart", Some(timeframe)) => vec![
+            StreamType::Kline { exchange, ticker, timeframe }
+        ],
+        ("Footprint chart" | "Candlestick chart", None) => vec![
+            StreamType::DepthAndTrades { exchange, ticker, resolution }
+        ],
+        _ => return vec![]
+    };
+
+    // only perpetuals have funding, the stats show up in the title bar of any of their panes
+    if exchange.market_type() != MarketType::Spot {
+        streams.push(StreamType::MarketStats { exchange, ticker });
+
+        // liquidations are marked on time based charts and listed in time&sales, footprints have no room for them
+        if content != "Footprint chart" {
+            streams.push(StreamType::Liquidations { exchange, ticker });
+        }
+    }
+
+    streams
+}
+
 pub enum PaneContent {
     Heatmap(HeatmapChart),
     Footprint(FootprintChart),
     Candlestick(CandlestickChart),
     TimeAndSales(TimeAndSales),
+    /// Latency and throughput of all the layout's streams, kept by the dashboard
+    FeedMonitor,
     Starter,
 }
 
+impl PaneContent {
+    /// Name of the content, as listed on the starter pane
+    pub fn label(&self) -> Option<&'static str> {
+        match self {
+            PaneContent::Heatmap(_) => Some("Heatmap chart"),
+            PaneContent::Footprint(_) => Some("Footprint chart"),
+            PaneContent::Candlestick(_) => Some("Candlestick chart"),
+            PaneContent::TimeAndSales(_) => Some("Time&Sales"),
+            PaneContent::FeedMonitor => Some("Feed monitor"),
+            PaneContent::Starter => None,
+        }
+    }
+}
+
 impl fmt::Debug for PaneContent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -498,6 +911,7 @@ impl fmt::Debug for PaneContent {
             PaneContent::Footprint(_) => write!(f, "Footprint"),
             PaneContent::Candlestick(_) => write!(f, "Candlestick"),
             PaneContent::TimeAndSales(_) => write!(f, "TimeAndSales"),
+            PaneContent::FeedMonitor => write!(f, "FeedMonitor"),
             PaneContent::Starter => write!(f, "Starter"),
         }
     }
@@ -505,12 +919,29 @@ impl fmt::Debug for PaneContent {
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct PaneSettings {
-    pub min_tick_size: Option<f32>,
+    pub min_tick_size: Option<Price>,
     pub trade_size_filter: Option<f32>,
     pub tick_multiply: Option<TickMultiplier>,
     pub selected_ticker: Option<Ticker>,
     pub selected_exchange: Option<Exchange>,
     pub selected_timeframe: Option<Timeframe>,
+    #[serde(default)]
+    pub selected_basis: Option<ChartBasis>,
+    /// Funding and open interest sub-pane under candlestick and footprint charts
+    #[serde(default)]
+    pub show_market_stats: bool,
+    #[serde(default)]
+    pub depth_resolution: Option<DepthResolution>,
+    /// Time bucket of a heatmap's columns
+    #[serde(default)]
+    pub aggregation: AggregationInterval,
+    #[serde(default)]
+    pub color_scale: ColorScale,
+    #[serde(default)]
+    pub color_map: ColorMap,
+    /// Volume profile of candlestick and footprint charts
+    #[serde(default)]
+    pub volume_profile: ProfileRange,
 }
 impl Default for PaneSettings {
     fn default() -> Self {
@@ -521,9 +952,32 @@ impl Default for PaneSettings {
             selected_ticker: None,
             selected_exchange: None,
             selected_timeframe: Some(Timeframe::M1),
+            selected_basis: None,
+            show_market_stats: false,
+            depth_resolution: None,
+            aggregation: AggregationInterval::default(),
+            color_scale: ColorScale::default(),
+            color_map: ColorMap::default(),
+            volume_profile: ProfileRange::default(),
         }
     }
 }
+impl PaneSettings {
+    /// The selected depth resolution for the pane's streams, None if that's the exchange's default
+    /// or one it doesn't offer, e.g. after switching to another exchange
+    pub fn depth_resolution(&self, exchange: Exchange) -> Option<DepthResolution> {
+        self.depth_resolution
+            .filter(|resolution| *resolution != DepthResolution::default_for(exchange))
+            .filter(|resolution| DepthResolution::options(exchange).contains(resolution))
+    }
+
+    /// Layouts saved before bars could be built from trades only have a timeframe
+    pub fn basis(&self) -> ChartBasis {
+        self.selected_basis.unwrap_or(
+            ChartBasis::Time(self.selected_timeframe.unwrap_or(Timeframe::M1))
+        )
+    }
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum SerializablePane {
@@ -550,6 +1004,7 @@ pub enum SerializablePane {
         stream_type: Vec<StreamType>,
         settings: PaneSettings,
     },
+    FeedMonitor,
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
@@ -564,6 +1019,7 @@ impl From<&PaneState> for SerializablePane {
 
         match pane.content {
             PaneContent::Starter => SerializablePane::Starter,
+            PaneContent::FeedMonitor => SerializablePane::FeedMonitor,
             PaneContent::Heatmap(_) => SerializablePane::HeatmapChart {
                 stream_type: pane_stream,
                 settings: pane.settings,
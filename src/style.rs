@@ -83,6 +83,13 @@ pub fn title_bar_focused(theme: &Theme) -> Style {
         ..Default::default()
     }
 }
+pub fn stale_text(theme: &Theme) -> iced::widget::text::Style {
+    let palette = theme.extended_palette();
+
+    iced::widget::text::Style {
+        color: Some(palette.danger.base.color),
+    }
+}
 pub fn pane_active(theme: &Theme) -> Style {
     let palette = theme.extended_palette();
 
@@ -216,6 +223,35 @@ pub fn button_primary(theme: &Theme, status: Status) -> iced::widget::button::St
     }
 }
 
+pub fn button_selected(theme: &Theme, status: Status) -> iced::widget::button::Style {
+    let palette = theme.extended_palette();
+
+    match status {
+        Status::Hovered | Status::Pressed => iced::widget::button::Style {
+            background: Some(palette.background.weak.color.into()),
+            text_color: palette.background.weak.text,
+            border: Border {
+                color: palette.primary.strong.color,
+                width: 1.0,
+                radius: 3.0.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Status::Active | Status::Disabled => iced::widget::button::Style {
+            background: Some(palette.background.weak.color.into()),
+            text_color: palette.background.weak.text,
+            border: Border {
+                color: palette.primary.weak.color,
+                width: 1.0,
+                radius: 3.0.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    }
+}
+
 pub fn picklist_primary(theme: &Theme, status: pick_list::Status) -> pick_list::Style {
     let palette = theme.extended_palette();
     
@@ -288,6 +324,25 @@ pub fn sell_side_red(color_alpha: f32) -> Style {
     }
 }
 
+pub fn liquidation_row(is_sell: bool) -> Style {
+    let color = if is_sell {
+        Color::from_rgb8(255, 120, 40)
+    } else {
+        Color::from_rgb8(240, 210, 60)
+    };
+
+    Style {
+        text_color: color.into(),
+        background: Some(Color { a: 0.15, ..color }.into()),
+        border: Border {
+            width: 1.0,
+            color,
+            ..Border::default()
+        },
+        ..Default::default()
+    }
+}
+
 pub fn buy_side_green(color_alpha: f32) -> Style {
     Style {
         text_color: Color::from_rgba(81.0 / 255.0, 205.0 / 255.0, 160.0 / 255.0, 1.0).into(),